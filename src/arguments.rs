@@ -1,21 +1,184 @@
 #[derive(Debug)]
 pub enum ArgumentParseError {
-    TooManyArguments(String),
     InvalidCommand(crate::command::InvalidCommand),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    WatchTest,
 }
 
-pub fn parse_command_line() -> Result<Option<crate::Command>, ArgumentParseError> {
+// How much of what is run during a build is printed, everything is logged regardless
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,   // Only status lines, and the output of whatever fails
+    Normal,  // The output of everything run
+    Verbose, // The output of everything run and the commands themselves
+}
+
+// How the timings of a build are printed once it finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingsFormat {
+    Table,
+    Json, // The table followed by the build as a line of JSON
+}
+
+pub struct Arguments {
+    pub command: Option<crate::Command>,
+    pub watched: Option<crate::Command>, // The command watch repeats
+    pub created: Option<String>, // The name of the project new-program or new-library creates
+    pub names: Vec<String>, // A configuration and components, told apart once losb.toml is read
+    pub only: Vec<String>,
+    pub arch: Option<String>,
+    pub jobs: Option<usize>,
+    pub verbosity: Verbosity,
+    pub timings: Option<TimingsFormat>,
+    pub compare: bool,
+    pub previous: bool,
+    pub cmdline: Option<String>, // Replaces the kernel command line in the image when running
+    pub features: Vec<String>,   // Either "feature" or "component:feature"
+    pub no_default_features: Vec<Option<String>>, // The component, None for the selected ones
+    pub env: Vec<String>,        // Either "KEY=VALUE" or "component:KEY=VALUE"
+}
+
+pub fn parse_command_line() -> Result<Arguments, ArgumentParseError> {
     parse(std::env::args().collect())
 }
 
-fn parse(arguments: Vec<String>) -> Result<Option<crate::Command>, ArgumentParseError> {
-    match arguments.len() {
-        1 => Ok(None),
-        2 => Ok(Some(crate::Command::parse(&arguments[1])?)),
-        _ => Err(ArgumentParseError::TooManyArguments(
-            arguments[0].to_string(),
-        )),
+pub fn parse(arguments: Vec<String>) -> Result<Arguments, ArgumentParseError> {
+    let mut result = Arguments {
+        command: None,
+        watched: None,
+        created: None,
+        names: Vec::new(),
+        only: Vec::new(),
+        arch: None,
+        jobs: None,
+        verbosity: Verbosity::Normal,
+        timings: None,
+        compare: false,
+        previous: false,
+        cmdline: None,
+        features: Vec::new(),
+        no_default_features: Vec::new(),
+        env: Vec::new(),
+    };
+
+    let mut iter = arguments.iter().skip(1);
+    while let Some(argument) = iter.next() {
+        // Options may appear anywhere, taking their value as "--option value", "--option=value"
+        // or for short options "-ovalue"
+        if argument.starts_with('-') {
+            let (option, value) = match argument.split_once('=') {
+                Some((option, value)) => (option, Some(value.to_owned())),
+                None if !argument.starts_with("--") && argument.len() > 2 => {
+                    (&argument[..2], Some(argument[2..].to_owned()))
+                }
+                None => (argument.as_str(), None),
+            };
+
+            let value = match option {
+                "--quiet" | "-q" | "--verbose" | "-v" | "--compare" | "--previous" => {
+                    if let Some(value) = value {
+                        return Err(ArgumentParseError::InvalidValue(option.to_owned(), value));
+                    }
+
+                    match option {
+                        "--quiet" | "-q" => result.verbosity = Verbosity::Quiet,
+                        "--compare" => result.compare = true,
+                        "--previous" => result.previous = true,
+                        _ => result.verbosity = Verbosity::Verbose,
+                    }
+                    continue;
+                }
+                // The components may only be given as "--no-default-features=kernel" as they are
+                // optional
+                "--no-default-features" => {
+                    match value {
+                        Some(value) => result.no_default_features.extend(
+                            value
+                                .split(',')
+                                .map(|name| Some(name.trim().to_owned()))
+                                .filter(|name| name.as_deref() != Some("")),
+                        ),
+                        None => result.no_default_features.push(None),
+                    }
+                    continue;
+                }
+                // The format may only be given as "--timings=json" as it is optional
+                "--timings" => {
+                    result.timings = match value.as_deref() {
+                        None | Some("table") => Some(TimingsFormat::Table),
+                        Some("json") => Some(TimingsFormat::Json),
+                        Some(_) => {
+                            return Err(ArgumentParseError::InvalidValue(
+                                option.to_owned(),
+                                value.unwrap(),
+                            ))
+                        }
+                    };
+                    continue;
+                }
+                "--arch" | "--jobs" | "-j" | "--only" | "--features" | "--env" | "--cmdline" => {
+                    match value.or_else(|| iter.next().cloned()) {
+                        Some(value) => value,
+                        None => return Err(ArgumentParseError::MissingValue(option.to_owned())),
+                    }
+                }
+                _ => return Err(ArgumentParseError::UnknownOption(option.to_owned())),
+            };
+
+            match option {
+                "--arch" => result.arch = Some(value),
+                "--cmdline" => result.cmdline = Some(value),
+                "--only" => result
+                    .only
+                    .extend(value.split(',').map(|name| name.trim().to_owned())),
+                "--features" => result.features.extend(
+                    value
+                        .split(',')
+                        .map(|feature| feature.trim().to_owned())
+                        .filter(|feature| !feature.is_empty()),
+                ),
+                "--env" => match value.contains('=') {
+                    true => result.env.push(value),
+                    false => {
+                        return Err(ArgumentParseError::InvalidValue(option.to_owned(), value))
+                    }
+                },
+                _ => {
+                    result.jobs = match value.parse() {
+                        Ok(jobs) if jobs > 0 => Some(jobs),
+                        _ => {
+                            return Err(ArgumentParseError::InvalidValue(option.to_owned(), value))
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+
+        match result.command {
+            None => result.command = Some(crate::Command::parse(argument)?),
+            // Watch may be followed by the command to repeat
+            Some(crate::Command::Watch) if result.watched.is_none() && result.names.is_empty() => {
+                match crate::Command::parse(argument) {
+                    Ok(command) => result.watched = Some(command),
+                    // losb has no test command, so this would otherwise name a component
+                    Err(_) if argument == "test" => return Err(ArgumentParseError::WatchTest),
+                    Err(_) => result.names.push(argument.clone()),
+                }
+            }
+            // Names given to new-program and new-library aren't components
+            Some(crate::Command::NewLibrary | crate::Command::NewProgram)
+                if result.created.is_none() =>
+            {
+                result.created = Some(argument.clone())
+            }
+            Some(_) => result.names.push(argument.clone()),
+        }
     }
+
+    Ok(result)
 }
 
 impl std::error::Error for ArgumentParseError {}
@@ -27,9 +190,17 @@ impl std::fmt::Display for ArgumentParseError {
             "{}",
             match self {
                 ArgumentParseError::InvalidCommand(error) => format!("{}", error),
-                ArgumentParseError::TooManyArguments(arg0) => format!(
-                    "Too many arguments\n      \x1B[1mUsage:\x1B[0m {} [command] [configuration]",
-                    arg0
+                ArgumentParseError::UnknownOption(option) =>
+                    format!("Unknown option \"{}\"", option),
+                ArgumentParseError::MissingValue(option) =>
+                    format!("Missing value for \"{}\"", option),
+                ArgumentParseError::InvalidValue(option, value) =>
+                    format!("Invalid value \"{}\" for \"{}\"", value, option),
+                ArgumentParseError::WatchTest => format!(
+                    "There is no test command to watch, only {}, {} and {} can be watched",
+                    crate::Command::Build,
+                    crate::Command::BuildImage,
+                    crate::Command::Run
                 ),
             }
         )
@@ -41,3 +212,95 @@ impl From<crate::command::InvalidCommand> for ArgumentParseError {
         ArgumentParseError::InvalidCommand(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, ArgumentParseError, Arguments};
+
+    fn arguments(arguments: &[&str]) -> Result<Arguments, ArgumentParseError> {
+        parse(
+            std::iter::once("losb")
+                .chain(arguments.iter().copied())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn features() {
+        let parsed = arguments(&[
+            "build",
+            "--features",
+            "kernel:poison, log/verbose,,quiet",
+            "--features=bootloader:serial",
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed.features,
+            ["kernel:poison", "log/verbose", "quiet", "bootloader:serial"]
+        );
+        assert!(parsed.no_default_features.is_empty());
+
+        let parsed = arguments(&[
+            "--no-default-features",
+            "build",
+            "--no-default-features=kernel, bootloader",
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed.no_default_features,
+            [
+                None,
+                Some("kernel".to_owned()),
+                Some("bootloader".to_owned())
+            ]
+        );
+        // The components must be attached, so a name after it isn't taken for one
+        let parsed = arguments(&["build", "--no-default-features", "kernel"]).unwrap();
+        assert_eq!(parsed.no_default_features, [None]);
+        assert_eq!(parsed.names, ["kernel"]);
+
+        assert!(matches!(
+            arguments(&["build", "--features"]),
+            Err(ArgumentParseError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn env() {
+        let parsed = arguments(&[
+            "run",
+            "--env",
+            "RUST_LOG=debug",
+            "--env=kernel:RUSTFLAGS=--cfg x=\"y\"",
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed.env,
+            ["RUST_LOG=debug", "kernel:RUSTFLAGS=--cfg x=\"y\""]
+        );
+
+        assert!(matches!(
+            arguments(&["run", "--env", "RUST_LOG"]),
+            Err(ArgumentParseError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            arguments(&["run", "--env"]),
+            Err(ArgumentParseError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn watch() {
+        let parsed = arguments(&["watch", "run", "kernel"]).unwrap();
+        assert!(matches!(parsed.watched, Some(crate::Command::Run)));
+        assert_eq!(parsed.names, ["kernel"]);
+
+        assert!(matches!(
+            arguments(&["watch", "test"]),
+            Err(ArgumentParseError::WatchTest)
+        ));
+        let parsed = arguments(&["watch", "build", "test"]).unwrap();
+        assert_eq!(parsed.names, ["test"]);
+    }
+}
@@ -0,0 +1,269 @@
+use super::{remove_installed, BuildError};
+use crate::{
+    config::{ComponentKind, Config, KERNEL_INSTALL},
+    manifest::Manifest,
+};
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file, write},
+    path::{Path, PathBuf},
+};
+
+// Bumped whenever a key is removed or changes meaning, new keys may be added at any time
+const FORMAT: usize = 1;
+
+// The name the boot configuration is recorded under in the manifest, as if a component installed
+// it. Component names can't hold spaces, so no component can be recorded under it.
+const OWNER: &str = "the boot configuration";
+
+// What older versions recorded it under, which a component may also be named
+const OLD_OWNER: &str = "boot";
+
+// The boot configuration the bootloader reads, as "key=value" lines. Each entry starts with its
+// title and is followed by the files to load, with paths from the root of the boot volume. The
+// kernel comes first, then each variant. A command line given replaces that of every entry.
+pub fn boot_config(config: &Config, cmdline: Option<&str>) -> String {
+    let kernel = match config.component("kernel").map(|kernel| &kernel.kind) {
+        Some(ComponentKind::Cargo(cargo)) => cargo.install.as_deref().unwrap_or(KERNEL_INSTALL),
+        _ => KERNEL_INSTALL,
+    };
+
+    let mut entries = vec![(
+        config.boot.title.as_str(),
+        kernel,
+        config.symbols.map.as_str(),
+        config.boot.cmdline.as_str(),
+    )];
+    for variant in &config.kernels {
+        entries.push((
+            &variant.title,
+            &variant.install,
+            &variant.symbol_map,
+            &variant.cmdline,
+        ));
+    }
+
+    let mut lines = vec![
+        "# Written by losb from losb.toml".to_owned(),
+        format!("format={}", FORMAT),
+        format!("timeout={}", config.boot.timeout),
+        "default=0".to_owned(),
+    ];
+    for (title, kernel, symbol_map, entry_cmdline) in entries {
+        lines.push(String::new());
+        lines.push(format!("entry={}", single_line(title)));
+        lines.push(format!("kernel={}", volume_path(kernel)));
+        if !symbol_map.is_empty() {
+            lines.push(format!("symbols={}", volume_path(symbol_map)));
+        }
+        if !config.initrd.include.is_empty() {
+            lines.push(format!("initrd={}", volume_path(&config.initrd.path)));
+        }
+        lines.push(format!(
+            "cmdline={}",
+            single_line(cmdline.unwrap_or(entry_cmdline))
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Writes the boot configuration into the sysroot unless it is unchanged, removing one written to
+// another path before. Returns if anything changed.
+pub fn write_boot_config(
+    config: &Config,
+    sysroot: &Path,
+    manifest: &mut Manifest,
+) -> Result<bool, BuildError> {
+    let target = PathBuf::from(&config.boot.path);
+    let mut owners = vec![OWNER];
+    if config.component(OLD_OWNER).is_none() {
+        owners.push(OLD_OWNER);
+    }
+    let written: Vec<PathBuf> = owners
+        .iter()
+        .flat_map(|owner| manifest.files(owner))
+        .map(Path::to_owned)
+        .collect();
+    if owners.len() > 1 {
+        manifest.set_files(OLD_OWNER, &[]);
+    }
+
+    let mut changed = false;
+    for file in &written {
+        if config.boot.path.is_empty() || *file != target {
+            remove_installed(sysroot, file).map_err(BuildError::BootConfig)?;
+            changed = true;
+        }
+    }
+    if config.boot.path.is_empty() {
+        manifest.set_files(OWNER, &[]);
+        return Ok(changed);
+    }
+
+    if let Some(owner) = manifest.owner(&target) {
+        if owner != OWNER {
+            return Err(BuildError::Conflict(
+                config.boot.path.clone(),
+                owner.to_owned(),
+                OWNER.to_owned(),
+            ));
+        }
+    }
+    manifest.set_files(OWNER, &[target]);
+
+    let contents = boot_config(config, None);
+    let path = sysroot.join(&config.boot.path);
+    match read_to_string(&path) {
+        Ok(existing) if existing == contents => return Ok(changed),
+        // The file may be linked into the previous sysroot, so it is replaced rather than written
+        Ok(_) => remove_file(&path).map_err(BuildError::BootConfig)?,
+        Err(_) => {}
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(BuildError::BootConfig)?;
+    }
+    write(path, contents).map_err(BuildError::BootConfig)?;
+    Ok(true)
+}
+
+fn volume_path(path: &str) -> String {
+    format!("/{}", path.trim_start_matches("./").trim_start_matches('/'))
+}
+
+// Lines end each value, so any in a value are replaced
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boot_config, write_boot_config, OWNER};
+    use crate::{
+        build::BuildError,
+        config::{Arch, Config},
+        manifest::Manifest,
+    };
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn one_entry() {
+        let config = Config::parse("", Arch::X86_64).unwrap();
+        assert_eq!(
+            boot_config(&config, None),
+            "# Written by losb from losb.toml\n\
+             format=1\n\
+             timeout=3\n\
+             default=0\n\
+             \n\
+             entry=Lance OS\n\
+             kernel=/kernel.elf\n\
+             symbols=/kernel.sym\n\
+             cmdline=\n"
+        );
+    }
+
+    #[test]
+    fn variants() {
+        let source = r#"
+[components.kernel]
+install = "./los/kernel.elf"
+
+[boot]
+title = "Lance OS"
+timeout = 0
+cmdline = "log=info"
+
+[symbols]
+map = ""
+
+[initrd]
+path = "./boot/initrd.cpio"
+include = ["los/bin"]
+
+[kernels.release]
+profile = "release"
+
+[kernels.quiet]
+title = "Two\nlines"
+cmdline = "log=off"
+"#;
+        let config = Config::parse(source, Arch::X86_64).unwrap();
+        let header = "# Written by losb from losb.toml\nformat=1\ntimeout=0\ndefault=0\n";
+        let entries = |release: &str, quiet: &str| {
+            format!(
+                "{}\n\
+                 entry=Lance OS\n\
+                 kernel=/los/kernel.elf\n\
+                 initrd=/boot/initrd.cpio\n\
+                 cmdline={}\n\
+                 \n\
+                 entry=Lance OS (release)\n\
+                 kernel=/los/kernel-release.elf\n\
+                 initrd=/boot/initrd.cpio\n\
+                 cmdline={}\n\
+                 \n\
+                 entry=Two lines\n\
+                 kernel=/los/kernel-quiet.elf\n\
+                 initrd=/boot/initrd.cpio\n\
+                 cmdline={}\n",
+                header, release, release, quiet
+            )
+        };
+
+        assert_eq!(boot_config(&config, None), entries("log=info", "log=off"));
+
+        // A command line given replaces every entry's
+        assert_eq!(
+            boot_config(&config, Some("log=trace\nquiet")),
+            entries("log=trace quiet", "log=trace quiet")
+        );
+        assert_eq!(boot_config(&config, Some("")), entries("", ""));
+    }
+
+    #[test]
+    fn owner() {
+        let directory = std::env::temp_dir().join(format!("losb-boot-{}", std::process::id()));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        let sysroot = directory.join("sysroot");
+        let target = PathBuf::from("EFI/BOOT/los.cfg");
+
+        // A component named boot is told apart from the boot configuration
+        let source = "[components.boot]\nkind = \"custom\"\nbuild = [\"true\"]\n";
+        let config = Config::parse(source, Arch::X86_64).unwrap();
+        let mut manifest = Manifest::load_from(directory.join("manifest")).unwrap();
+        manifest.set_files("boot", &[PathBuf::from("boot.bin")]);
+        assert!(write_boot_config(&config, &sysroot, &mut manifest).unwrap());
+        assert_eq!(manifest.files(OWNER), [target.as_path()]);
+        assert_eq!(manifest.files("boot"), [Path::new("boot.bin")]);
+        assert_eq!(
+            std::fs::read_to_string(sysroot.join(&target)).unwrap(),
+            boot_config(&config, None)
+        );
+        assert!(!write_boot_config(&config, &sysroot, &mut manifest).unwrap());
+
+        // Older manifests recorded the boot configuration as boot
+        let config = Config::parse("", Arch::X86_64).unwrap();
+        manifest.set_files(OWNER, &[]);
+        manifest.set_files("boot", std::slice::from_ref(&target));
+        write_boot_config(&config, &sysroot, &mut manifest).unwrap();
+        assert_eq!(manifest.components(), [OWNER]);
+        assert_eq!(manifest.files(OWNER), [target.as_path()]);
+
+        // Another component installing the file conflicts
+        manifest.set_files(OWNER, &[]);
+        manifest.set_files("bootloader", std::slice::from_ref(&target));
+        match write_boot_config(&config, &sysroot, &mut manifest) {
+            Err(BuildError::Conflict(path, first, second)) => {
+                assert_eq!(path, "EFI/BOOT/los.cfg");
+                assert_eq!(first, "bootloader");
+                assert_eq!(second, OWNER);
+            }
+            _ => panic!("expected a conflict"),
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
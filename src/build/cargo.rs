@@ -0,0 +1,123 @@
+use super::{job::Job, BuildError};
+use crate::{
+    config::{CargoComponent, Profile},
+    json,
+};
+use std::{path::PathBuf, process::Command};
+
+// An executable reported by cargo in a "compiler-artifact" message
+struct Executable {
+    name: String,
+    path: PathBuf,
+    fresh: bool, // Cargo didn't need to build it again
+}
+
+// Builds a crate and returns the executable to install, chosen by its target name when given,
+// and whether it was fresh
+pub fn build(
+    job: &Job,
+    path: &str,
+    profile: &Profile,
+    cargo: &CargoComponent,
+    env: &[(String, String)],
+) -> Result<(PathBuf, bool), BuildError> {
+    let executables = run(job, path, profile, cargo, env)?;
+    let binary = cargo.binary.as_deref();
+
+    let mut matching = executables.iter().filter(|executable| {
+        binary
+            .map(|binary| executable.name == binary)
+            .unwrap_or(true)
+    });
+    let found = || {
+        executables
+            .iter()
+            .map(|executable| executable.name.clone())
+            .collect()
+    };
+
+    match (matching.next(), matching.next(), binary) {
+        (Some(executable), None, _) => Ok((executable.path.clone(), executable.fresh)),
+        (None, _, None) => Err(BuildError::NoArtifact(path.to_owned())),
+        (None, _, Some(binary)) => Err(BuildError::MissingArtifact(
+            path.to_owned(),
+            binary.to_owned(),
+            found(),
+        )),
+        (Some(_), Some(_), _) => Err(BuildError::AmbiguousArtifact(path.to_owned(), found())),
+    }
+}
+
+fn run(
+    job: &Job,
+    path: &str,
+    profile: &Profile,
+    cargo: &CargoComponent,
+    env: &[(String, String)],
+) -> Result<Vec<Executable>, BuildError> {
+    let mut command = Command::new("cargo");
+    command.args([
+        "build",
+        "--profile",
+        cargo.profile.as_deref().unwrap_or(&profile.cargo),
+        "--message-format=json-render-diagnostics",
+    ]);
+    if let Some(target) = &cargo.target {
+        command.args(["--target", target]);
+    }
+    if !cargo.features.is_empty() {
+        command.args(["--features", &cargo.features.join(",")]);
+    }
+    if !cargo.default_features {
+        command.arg("--no-default-features");
+    }
+    command.envs(env.iter().map(|(key, value)| (key, value)));
+    command.current_dir(path);
+
+    // Diagnostics are rendered by cargo onto stderr, anything on stdout which isn't a message
+    // comes from build scripts and is passed through
+    let mut executables: Vec<Executable> = Vec::new();
+    let mut stdout_line = |line: &str| {
+        let message = match json::parse(line) {
+            Ok(message) => message,
+            Err(_) => return false,
+        };
+
+        if let Some(executable) = parse_executable(&message) {
+            if !executables
+                .iter()
+                .any(|existing| existing.path == executable.path)
+            {
+                executables.push(executable);
+            }
+        }
+        true
+    };
+
+    match job.run(&mut command, Some(&mut stdout_line)) {
+        Ok(status) => match status.success() {
+            true => Ok(executables),
+            false => Err(BuildError::Cargo(path.to_owned(), None)),
+        },
+        Err(error) => Err(BuildError::Cargo(path.to_owned(), Some(error))),
+    }
+}
+
+fn parse_executable(message: &json::Value) -> Option<Executable> {
+    if message.get("reason")?.as_str()? != "compiler-artifact" {
+        return None;
+    }
+
+    // Only binary targets are installed, build scripts and tests are skipped
+    let target = message.get("target")?;
+    let kinds = target.get("kind")?.as_array()?;
+    if !kinds.iter().any(|kind| kind.as_str() == Some("bin")) {
+        return None;
+    }
+
+    Some(Executable {
+        name: target.get("name")?.as_str()?.to_owned(),
+        path: PathBuf::from(message.get("executable")?.as_str()?),
+        fresh: message.get("fresh") == Some(&json::Value::Boolean(true)),
+    })
+}
@@ -0,0 +1,798 @@
+// Turns the symbol names rustc produces back into Rust paths, in either the legacy scheme or v0
+
+use std::{borrow::Cow, convert::TryFrom};
+
+// Deep enough for any real name, while stopping malicious ones from overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+// Demangles a name, leaving names which aren't from Rust or can't be read as they are
+pub fn demangle(name: &str) -> String {
+    let demangled = match name.strip_prefix("_R") {
+        // v0 names only hold letters, digits and "_", so a "." starts a suffix LLVM added, which
+        // is kept as it is
+        Some(mangled) => match mangled.find('.') {
+            Some(suffix) => {
+                demangle_v0(&mangled[..suffix]).map(|demangled| demangled + &mangled[suffix..])
+            }
+            None => demangle_v0(mangled),
+        },
+        None => demangle_legacy(name),
+    };
+    demangled.unwrap_or_else(|| name.to_owned())
+}
+
+// Such as "_ZN4core9panicking5panic17h0123456789abcdefE" for "core::panicking::panic"
+fn demangle_legacy(name: &str) -> Option<String> {
+    let mut rest = name.strip_prefix("_ZN")?;
+
+    // The path is a series of parts, each prefixed with its length
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let length: usize = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        parts.push(rest.get(..length)?);
+        rest = &rest[length..];
+    }
+
+    // The last part is usually a hash telling apart versions of a crate
+    if parts.last().map(|part| is_hash(part)).unwrap_or(false) {
+        parts.pop();
+    }
+    if parts.is_empty() {
+        return None;
+    }
+
+    let parts: Option<Vec<String>> = parts.into_iter().map(unescape).collect();
+    Some(parts?.join("::"))
+}
+
+fn is_hash(part: &str) -> bool {
+    part.len() == 17
+        && part.starts_with('h')
+        && part[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// Replaces the escapes of characters which can't appear in legacy symbol names
+fn unescape(part: &str) -> Option<String> {
+    // A part starting with an escape is prefixed with an underscore
+    let mut rest = match part.starts_with("_$") {
+        true => &part[1..],
+        false => part,
+    };
+
+    let mut result = String::with_capacity(rest.len());
+    while let Some(character) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("..") {
+            result.push_str("::");
+            rest = after;
+            continue;
+        }
+
+        if character != '$' {
+            result.push(character);
+            rest = &rest[character.len_utf8()..];
+            continue;
+        }
+
+        let end = rest[1..].find('$')? + 1;
+        result.push(match &rest[1..end] {
+            "SP" => '@',
+            "BP" => '*',
+            "RF" => '&',
+            "LT" => '<',
+            "GT" => '>',
+            "LP" => '(',
+            "RP" => ')',
+            "C" => ',',
+            escape => char::from_u32(u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?)?,
+        });
+        rest = &rest[end + 1..];
+    }
+    Some(result)
+}
+
+// The v0 scheme, following https://doc.rust-lang.org/rustc/symbol-mangling/v0.html. The crate
+// which instantiated a generic function may follow its path, and is left out.
+fn demangle_v0(name: &str) -> Option<String> {
+    let mut parser = V0 {
+        data: name.as_bytes(),
+        position: 0,
+        depth: 0,
+        output: String::new(),
+    };
+
+    // A version number would precede the path, only the first version exists so far
+    if parser.peek()?.is_ascii_digit() {
+        return None;
+    }
+    parser.path()?;
+    if parser.peek().is_some() {
+        parser.skip(V0::path)?;
+    }
+    match parser.position == parser.data.len() {
+        true => Some(parser.output),
+        false => None,
+    }
+}
+
+// Parses a v0 name while printing it, backreferences are printed by parsing what they point to
+struct V0<'a> {
+    data: &'a [u8],
+    position: usize,
+    depth: usize,
+    output: String,
+}
+
+impl<'a> V0<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matches = self.peek() == Some(byte);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn push(&mut self, string: &str) {
+        self.output.push_str(string);
+    }
+
+    fn enter(&mut self) -> Option<()> {
+        self.depth += 1;
+        match self.depth <= MAX_DEPTH {
+            true => Some(()),
+            false => None,
+        }
+    }
+
+    // Digits and letters terminated by "_", where "_" alone is 0 and anything else is one more
+    fn base62(&mut self) -> Option<u64> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+
+        let mut value: u64 = 0;
+        loop {
+            let digit = match self.next()? {
+                b'_' => return value.checked_add(1),
+                byte @ b'0'..=b'9' => byte - b'0',
+                byte @ b'a'..=b'z' => byte - b'a' + 10,
+                byte @ b'A'..=b'Z' => byte - b'A' + 36,
+                _ => return None,
+            };
+            value = value.checked_mul(62)?.checked_add(digit as u64)?;
+        }
+    }
+
+    // Zero is never followed by more digits, so "00" is two numbers
+    fn decimal(&mut self) -> Option<usize> {
+        let start = self.position;
+        if self.eat(b'0') {
+            return Some(0);
+        }
+        while self
+            .peek()
+            .map(|byte| byte.is_ascii_digit())
+            .unwrap_or(false)
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn disambiguator(&mut self) -> Option<u64> {
+        match self.eat(b's') {
+            true => self.base62()?.checked_add(1),
+            false => Some(0),
+        }
+    }
+
+    // Identifiers which aren't ASCII are punycode, printed encoded when they can't be decoded
+    fn identifier(&mut self) -> Option<Cow<'a, str>> {
+        let encoded = self.eat(b'u');
+        let length = self.decimal()?;
+        self.eat(b'_');
+
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        let identifier = std::str::from_utf8(bytes).ok()?;
+        Some(match encoded {
+            true => Cow::Owned(
+                punycode(identifier).unwrap_or_else(|| format!("punycode{{{}}}", identifier)),
+            ),
+            false => Cow::Borrowed(identifier),
+        })
+    }
+
+    // Parses what a backreference points to, which must come before it
+    fn backref(&mut self, parse: fn(&mut Self) -> Option<()>) -> Option<()> {
+        let start = self.position - 1;
+        let target = self.base62()? as usize;
+        if target >= start {
+            return None;
+        }
+
+        let position = std::mem::replace(&mut self.position, target);
+        parse(self)?;
+        self.position = position;
+        Some(())
+    }
+
+    fn path(&mut self) -> Option<()> {
+        self.enter()?;
+        match self.next()? {
+            b'C' => {
+                self.disambiguator()?;
+                let name = self.identifier()?;
+                self.push(&name);
+            }
+            // Inherent impls print as "<Type>", the path of the impl itself isn't printed
+            b'M' => {
+                self.disambiguator()?;
+                self.skip(Self::path)?;
+                self.push("<");
+                self.kind()?;
+                self.push(">");
+            }
+            b'X' => {
+                self.disambiguator()?;
+                self.skip(Self::path)?;
+                self.push("<");
+                self.kind()?;
+                self.push(" as ");
+                self.path()?;
+                self.push(">");
+            }
+            b'Y' => {
+                self.push("<");
+                self.kind()?;
+                self.push(" as ");
+                self.path()?;
+                self.push(">");
+            }
+            b'N' => {
+                let namespace = self.next()?;
+                self.path()?;
+                let disambiguator = self.disambiguator()?;
+                let name = self.identifier()?;
+
+                // Closures and shims have no name of their own
+                match namespace {
+                    b'a'..=b'z' => {
+                        if !name.is_empty() {
+                            self.push("::");
+                            self.push(&name);
+                        }
+                    }
+                    b'A'..=b'Z' => {
+                        let namespace = match namespace {
+                            b'C' => "closure".to_owned(),
+                            b'S' => "shim".to_owned(),
+                            _ => (namespace as char).to_string(),
+                        };
+                        self.push("::{");
+                        self.push(&namespace);
+                        if !name.is_empty() {
+                            self.push(":");
+                            self.push(&name);
+                        }
+                        self.push(&format!("#{}}}", disambiguator));
+                    }
+                    _ => return None,
+                }
+            }
+            b'I' => {
+                self.path()?;
+                self.push("<");
+                self.generic_arguments()?;
+                self.push(">");
+            }
+            b'B' => self.backref(Self::path)?,
+            _ => return None,
+        }
+        self.depth -= 1;
+        Some(())
+    }
+
+    // Parses without printing
+    fn skip(&mut self, parse: fn(&mut Self) -> Option<()>) -> Option<()> {
+        let length = self.output.len();
+        parse(self)?;
+        self.output.truncate(length);
+        Some(())
+    }
+
+    fn generic_arguments(&mut self) -> Option<()> {
+        let mut first = true;
+        while !self.eat(b'E') {
+            if !first {
+                self.push(", ");
+            }
+            first = false;
+
+            match self.peek()? {
+                b'L' => {
+                    self.position += 1;
+                    self.base62()?;
+                    self.push("'_");
+                }
+                b'K' => {
+                    self.position += 1;
+                    self.constant()?;
+                }
+                _ => self.kind()?,
+            }
+        }
+        Some(())
+    }
+
+    // A type
+    fn kind(&mut self) -> Option<()> {
+        self.enter()?;
+        let tag = self.next()?;
+        if let Some(basic) = basic_type(tag) {
+            self.push(basic);
+            self.depth -= 1;
+            return Some(());
+        }
+
+        match tag {
+            b'R' | b'Q' => {
+                self.push("&");
+                if self.eat(b'L') {
+                    self.base62()?;
+                }
+                if tag == b'Q' {
+                    self.push("mut ");
+                }
+                self.kind()?;
+            }
+            b'P' => {
+                self.push("*const ");
+                self.kind()?;
+            }
+            b'O' => {
+                self.push("*mut ");
+                self.kind()?;
+            }
+            b'A' => {
+                self.push("[");
+                self.kind()?;
+                self.push("; ");
+                self.constant()?;
+                self.push("]");
+            }
+            b'S' => {
+                self.push("[");
+                self.kind()?;
+                self.push("]");
+            }
+            b'T' => {
+                self.push("(");
+                let mut count = 0;
+                while !self.eat(b'E') {
+                    if count > 0 {
+                        self.push(", ");
+                    }
+                    self.kind()?;
+                    count += 1;
+                }
+                if count == 1 {
+                    self.push(",");
+                }
+                self.push(")");
+            }
+            b'F' => self.function()?,
+            b'D' => {
+                if self.eat(b'G') {
+                    self.base62()?;
+                }
+                self.push("dyn ");
+                let mut first = true;
+                while !self.eat(b'E') {
+                    if !first {
+                        self.push(" + ");
+                    }
+                    first = false;
+                    self.dyn_trait()?;
+                }
+                if !self.eat(b'L') {
+                    return None;
+                }
+                self.base62()?;
+            }
+            b'B' => self.backref(Self::kind)?,
+            // Anything else is the path of a named type
+            _ => {
+                self.position -= 1;
+                self.path()?;
+            }
+        }
+        self.depth -= 1;
+        Some(())
+    }
+
+    fn function(&mut self) -> Option<()> {
+        if self.eat(b'G') {
+            self.base62()?;
+        }
+        if self.eat(b'U') {
+            self.push("unsafe ");
+        }
+        if self.eat(b'K') {
+            let abi = match self.eat(b'C') {
+                true => "C".to_owned(),
+                false => self.identifier()?.replace('_', "-"),
+            };
+            self.push(&format!("extern \"{}\" ", abi));
+        }
+
+        self.push("fn(");
+        let mut first = true;
+        while !self.eat(b'E') {
+            if !first {
+                self.push(", ");
+            }
+            first = false;
+            self.kind()?;
+        }
+        self.push(")");
+
+        // Functions returning () leave out the return type
+        match self.eat(b'u') {
+            true => Some(()),
+            false => {
+                self.push(" -> ");
+                self.kind()
+            }
+        }
+    }
+
+    // Associated types join the trait's generic arguments, such as "Fn<(u8,), Output = ()>"
+    fn dyn_trait(&mut self) -> Option<()> {
+        self.path()?;
+        let mut first = true;
+        while self.eat(b'p') {
+            if !first {
+                self.push(", ");
+            } else if self.output.ends_with('>') {
+                self.output.pop();
+                self.push(", ");
+            } else {
+                self.push("<");
+            }
+            first = false;
+
+            let name = self.identifier()?;
+            self.push(&name);
+            self.push(" = ");
+            self.kind()?;
+        }
+        if !first {
+            self.push(">");
+        }
+        Some(())
+    }
+
+    // Only constants of integers, bools and chars can be printed
+    fn constant(&mut self) -> Option<()> {
+        if self.eat(b'B') {
+            return self.backref(Self::constant);
+        }
+        if self.eat(b'p') {
+            self.push("_");
+            return Some(());
+        }
+
+        let kind = self.next()?;
+        let negative = self.eat(b'n');
+        let start = self.position;
+        while self.peek()? != b'_' {
+            self.position += 1;
+        }
+        let digits = std::str::from_utf8(&self.data[start..self.position]).ok()?;
+        self.position += 1;
+        let value = match digits.is_empty() {
+            true => 0,
+            false => u128::from_str_radix(digits, 16).ok()?,
+        };
+
+        let printed = match kind {
+            b'b' => (value != 0).to_string(),
+            b'c' => format!("{:?}", char::from_u32(u32::try_from(value).ok()?)?),
+            b'a' | b'h' | b'i' | b'j' | b'l' | b'm' | b'n' | b'o' | b's' | b't' | b'x' | b'y' => {
+                match negative {
+                    true => format!("-{}", value),
+                    false => value.to_string(),
+                }
+            }
+            _ => return None,
+        };
+        self.push(&printed);
+        Some(())
+    }
+}
+
+// Decodes punycode (RFC 3492), which v0 writes with "_" in place of "-"
+fn punycode(encoded: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+
+    let (basic, deltas) = match encoded.rfind('_') {
+        Some(at) => (&encoded[..at], &encoded[at + 1..]),
+        None => ("", encoded),
+    };
+    let mut output: Vec<char> = basic.chars().collect();
+    let (mut code, mut index, mut bias) = (0x80u32, 0u32, 72u32);
+
+    let mut digits = deltas.bytes().peekable();
+    while digits.peek().is_some() {
+        let previous = index;
+        let mut weight = 1u32;
+        let mut k = BASE;
+        loop {
+            let digit = match digits.next()? {
+                byte @ b'a'..=b'z' => byte - b'a',
+                byte @ b'0'..=b'9' => byte - b'0' + 26,
+                _ => return None,
+            } as u32;
+            index = index.checked_add(digit.checked_mul(weight)?)?;
+
+            let threshold = match k <= bias {
+                true => T_MIN,
+                false => (k - bias).min(T_MAX),
+            };
+            if digit < threshold {
+                break;
+            }
+            weight = weight.checked_mul(BASE - threshold)?;
+            k += BASE;
+        }
+
+        let length = output.len() as u32 + 1;
+        bias = adapt_bias(index - previous, length, previous == 0);
+        code = code.checked_add(index / length)?;
+        index %= length;
+        output.insert(index as usize, char::from_u32(code)?);
+        index += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+fn adapt_bias(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = match first {
+        true => delta / 700,
+        false => delta / 2,
+    };
+    delta += delta / points;
+
+    let mut k = 0;
+    while delta > 35 * 26 / 2 {
+        delta /= 35;
+        k += 36;
+    }
+    k + 36 * delta / (delta + 38)
+}
+
+fn basic_type(tag: u8) -> Option<&'static str> {
+    Some(match tag {
+        b'a' => "i8",
+        b'b' => "bool",
+        b'c' => "char",
+        b'd' => "f64",
+        b'e' => "str",
+        b'f' => "f32",
+        b'h' => "u8",
+        b'i' => "isize",
+        b'j' => "usize",
+        b'l' => "i32",
+        b'm' => "u32",
+        b'n' => "i128",
+        b'o' => "u128",
+        b'p' => "_",
+        b's' => "i16",
+        b't' => "u16",
+        b'u' => "()",
+        b'v' => "...",
+        b'x' => "i64",
+        b'y' => "u64",
+        b'z' => "!",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{demangle, punycode};
+
+    // Names rustc gave a small crate "m", in both schemes
+    const LEGACY: &[(&str, &str)] = &[
+        ("_ZN1m4main17h4009bc0cd8b193aaE", "m::main"),
+        (
+            "_ZN4core9panicking5panic17h0123456789abcdefE",
+            "core::panicking::panic",
+        ),
+        (
+            "_ZN1m16Wrapper$LT$T$GT$3new17h23de2eca87ba39f8E",
+            "m::Wrapper<T>::new",
+        ),
+        (
+            "_ZN1m4main28_$u7b$$u7b$closure$u7d$$u7d$17h9d01ae81c80b9a5eE",
+            "m::main::{{closure}}",
+        ),
+        (
+            "_ZN1m12_$ufc$nicode13gr$ufc$$udf$e17hd0eeb734d701cbecE",
+            "m::ünicode::grüße",
+        ),
+        (
+            "_ZN66_$LT$core..option..Option$LT$T$GT$$u20$as$u20$core..fmt..Debug$GT$3fmt17h5886188c984e0edaE",
+            "<core::option::Option<T> as core::fmt::Debug>::fmt",
+        ),
+        (
+            "_ZN44_$LT$$RF$T$u20$as$u20$core..fmt..Display$GT$3fmt17h41e2f6a5410bc0feE",
+            "<&T as core::fmt::Display>::fmt",
+        ),
+        // Without the hash, the last part is part of the path
+        ("_ZN1m4mainE", "m::main"),
+        ("_ZN1m16h0123456789abcdeE", "m::h0123456789abcde"),
+    ];
+
+    const V0: &[(&str, &str)] = &[
+        ("_RNvCskK7mfDs1mzF_1m4main", "m::main"),
+        (
+            "_RINvCskK7mfDs1mzF_1m7genericINtNtCsgEmfK2I1SDS_4core6option6OptionINtNtCslNYArtu3iFV_5alloc3vec3VechEEEB2_",
+            "m::generic<core::option::Option<alloc::vec::Vec<u8>>>",
+        ),
+        (
+            "_RNvMs_CskK7mfDs1mzF_1mINtB4_7WrapperTlhEE3newB4_",
+            "<m::Wrapper<(i32, u8)>>::new",
+        ),
+        (
+            "_RNvXCskK7mfDs1mzF_1mINtB2_7WrapperhENtB2_5Trait1fB2_",
+            "<m::Wrapper<u8> as m::Trait>::f",
+        ),
+        (
+            "_RNvXs1g_NtCsgEmfK2I1SDS_4core3fmtRINtNtCslNYArtu3iFV_5alloc3vec3VechENtB6_5Debug3fmtCskK7mfDs1mzF_1m",
+            "<&alloc::vec::Vec<u8> as core::fmt::Debug>::fmt",
+        ),
+        (
+            "_RNvXsZ_NtCsjrHSEGnQ3l9_3std7processuNtB5_11Termination6reportCskK7mfDs1mzF_1m",
+            "<() as std::process::Termination>::report",
+        ),
+        (
+            "_RNvYFEuINtNtNtCsgEmfK2I1SDS_4core3ops8function6FnOnceuE9call_onceCskK7mfDs1mzF_1m",
+            "<fn() as core::ops::function::FnOnce<()>>::call_once",
+        ),
+        (
+            "_RNSNvYNCNvCskK7mfDs1mzF_1m4mains_0INtNtNtCsgEmfK2I1SDS_4core3ops8function6FnOnceThEE9call_once6vtableB8_",
+            "<m::main::{closure#1} as core::ops::function::FnOnce<(u8,)>>::call_once::{shim:vtable#0}",
+        ),
+        (
+            "_RNvNvMs1_NtNtCsgEmfK2I1SDS_4core3ptr8non_nullINtB7_7NonNullpE13new_unchecked18precondition_checkCskK7mfDs1mzF_1m",
+            "<core::ptr::non_null::NonNull<_>>::new_unchecked::precondition_check",
+        ),
+        (
+            "_RINvNtNtCsjrHSEGnQ3l9_3std3sys9backtrace28___rust_begin_short_backtraceFEuuECskK7mfDs1mzF_1m",
+            "std::sys::backtrace::__rust_begin_short_backtrace<fn(), ()>",
+        ),
+        (
+            "_RNvNtNtCsjrHSEGnQ3l9_3std6thread11main_thread4MAIN.0",
+            "std::thread::main_thread::MAIN.0",
+        ),
+    ];
+
+    #[test]
+    fn legacy() {
+        for (mangled, demangled) in LEGACY {
+            assert_eq!(demangle(mangled), *demangled, "{}", mangled);
+        }
+    }
+
+    #[test]
+    fn v0_paths() {
+        for (mangled, demangled) in V0 {
+            assert_eq!(demangle(mangled), *demangled, "{}", mangled);
+        }
+    }
+
+    #[test]
+    fn v0_constants() {
+        assert_eq!(
+            demangle("_RINvCskK7mfDs1mzF_1m5konstKj3_Kb1_Kc78_EB2_"),
+            "m::konst<3, true, 'x'>"
+        );
+        assert_eq!(
+            demangle("_RINvCs1_1m5konstKan1_Kb0_KpKhff_EB2_"),
+            "m::konst<-1, false, _, 255>"
+        );
+        assert_eq!(
+            demangle("_RINvCs1_1m5arrayAhKj4_EB2_"),
+            "_RINvCs1_1m5arrayAhKj4_EB2_"
+        );
+        assert_eq!(demangle("_RINvCs1_1m5arrayAhj4_EB2_"), "m::array<[u8; 4]>");
+    }
+
+    #[test]
+    fn v0_punycode() {
+        assert_eq!(
+            demangle("_RNvNtCskK7mfDs1mzF_1mu10nicode_2yau9gre_6ka8l"),
+            "m::ünicode::grüße"
+        );
+        assert_eq!(punycode("nicode_2ya").as_deref(), Some("ünicode"));
+        assert_eq!(punycode("gre_6ka8l").as_deref(), Some("grüße"));
+        assert_eq!(
+            punycode("ihqwcrb4cv8a8dqg056pqjye").as_deref(),
+            Some("他们为什么不说中文")
+        );
+
+        // Undecodable punycode is printed as it is
+        assert_eq!(demangle("_RNvCs1_1mu3a_Z"), "m::punycode{a_Z}");
+        assert_eq!(punycode("zzzzzzzzzzzzzzzzzzzzzzzz"), None);
+    }
+
+    #[test]
+    fn malformed() {
+        for name in [
+            "main",
+            "_ZN",
+            "_ZNE",
+            "_ZN3abE",
+            "_ZN1a$LT1bE",
+            "_ZN2$uE",
+            "_R",
+            "_RB_",
+            "_RNvB_4main",
+        ] {
+            assert_eq!(demangle(name), name, "{}", name);
+        }
+
+        // Backreferences must point behind themselves, so they can't loop
+        assert_eq!(demangle("_RNvB0_1a"), "_RNvB0_1a");
+        assert_eq!(demangle("_RINvCs1_1m1fBa_EB2_"), "_RINvCs1_1m1fBa_EB2_");
+
+        // Deeply nested names stop rather than overflow the stack
+        let deep = format!("_R{}Cs1_1m{}", "I".repeat(10000), "E".repeat(10000));
+        assert_eq!(demangle(&deep), deep);
+        let types = format!("_RINvCs1_1m1f{}hEB2_", "R".repeat(10000));
+        assert_eq!(demangle(&types), types);
+        let number = "_RNvCs1_1mu99999999999999999999999_a";
+        assert_eq!(demangle(number), number);
+    }
+
+    #[test]
+    fn truncated() {
+        for (mangled, _) in LEGACY.iter().chain(V0) {
+            for length in 0..mangled.len() {
+                demangle(&mangled[..length]);
+            }
+        }
+
+        // Without an instantiating crate, a name is only complete at its end
+        for name in [
+            "_RNvCskK7mfDs1mzF_1m4main",
+            "_RNvNtCskK7mfDs1mzF_1mu10nicode_2yau9gre_6ka8l",
+            "_RINvCs1_1m5konstKan1_Kb0_KpKhff_E",
+            "_ZN1m16Wrapper$LT$T$GT$3new17h23de2eca87ba39f8E",
+        ] {
+            for length in 0..name.len() {
+                assert_eq!(demangle(&name[..length]), &name[..length]);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::config::Config;
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file, write},
+    path::Path,
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Bumped whenever a key is removed or changes meaning, new keys may be added at any time
+const FORMAT: usize = 1;
+
+// Writes what the sysroot was built from for the OS to display. The file is only replaced when
+// something besides the time changes, so an unchanged sysroot doesn't produce a new image.
+// Returns if it was replaced.
+pub fn write_build_info(config: &Config, sysroot: &Path) -> Result<bool, std::io::Error> {
+    let mut lines = vec![
+        format!("format={}", FORMAT),
+        format!("losb={}", env!("CARGO_PKG_VERSION")),
+        format!("profile={}", config.profile.name),
+        format!("arch={}", config.arch),
+    ];
+    for component in &config.components {
+        let (commit, dirty) = git_state(&component.path);
+        lines.push(format!("component.{}.commit={}", component.name, commit));
+        lines.push(format!("component.{}.dirty={}", component.name, dirty));
+    }
+
+    let path = sysroot.join(crate::config::BUILD_INFO);
+    if let Ok(existing) = read_to_string(&path) {
+        let unchanged = existing
+            .lines()
+            .filter(|line| !line.starts_with("built="))
+            .eq(lines.iter().map(String::as_str));
+        if unchanged {
+            return Ok(false);
+        }
+
+        // The file may be linked into the previous sysroot, so it is replaced rather than written
+        remove_file(&path)?;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    lines.push(format!("built={}", timestamp(now)));
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    write(path, lines.join("\n") + "\n").map(|_| true)
+}
+
+// The commit checked out in the repository holding a component and whether it has changes,
+// "unknown" when it isn't in a repository or git is missing
+fn git_state(path: &str) -> (String, &'static str) {
+    let git = |arguments: &[&str]| {
+        Command::new("git")
+            .args(arguments)
+            .current_dir(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+
+    let commit = match git(&["rev-parse", "HEAD"]) {
+        Some(commit) => commit,
+        None => return ("unknown".to_owned(), "unknown"),
+    };
+
+    let dirty = match git(&["status", "--porcelain", "--", "."]) {
+        Some(status) if status.is_empty() => "false",
+        Some(_) => "true",
+        None => "unknown",
+    };
+    (commit, dirty)
+}
+
+// A time in seconds since the unix epoch in UTC as "YYYY-MM-DDTHH:MM:SSZ"
+fn timestamp(seconds: u64) -> String {
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Converts days since 1970-01-01 into a date in the proleptic Gregorian calendar
+    let days = days as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{timestamp, write_build_info};
+    use crate::config::{Arch, Config, BUILD_INFO};
+    use std::{path::Path, process::Command};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(68_169_599), "1972-02-28T23:59:59Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_767_268_800), "2026-01-01T12:00:00Z");
+        assert_eq!(timestamp(4_107_542_399), "2100-02-28T23:59:59Z");
+    }
+
+    fn git(path: &Path, arguments: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=losb", "-c", "user.email=losb@example.com"])
+            .args(arguments)
+            .current_dir(path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    }
+
+    // The keys and values of the file, as documented in the README
+    #[test]
+    fn format() {
+        let directory = std::env::temp_dir().join(format!("losb-info-{}", std::process::id()));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        let repository = directory.join("repository");
+        let outside = directory.join("outside");
+        let sysroot = directory.join("sysroot");
+        std::fs::create_dir_all(repository.join("tools")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        git(&repository, &["init", "-q"]);
+        std::fs::write(repository.join("tools/main.c"), "int main;").unwrap();
+        git(&repository, &["add", "."]);
+        git(&repository, &["commit", "-qm", "Initial commit"]);
+        let commit = git(&repository, &["rev-parse", "HEAD"]);
+
+        let source = format!(
+            "[components.tools]\nkind = \"custom\"\npath = {:?}\n\n\
+             [components.docs]\nkind = \"custom\"\npath = {:?}\n",
+            repository.join("tools"),
+            outside
+        );
+        let mut config = Config::parse(&source, Arch::AArch64).unwrap();
+        config
+            .components
+            .retain(|component| component.kind_name() == "custom");
+
+        assert!(write_build_info(&config, &sysroot).unwrap());
+        let info = std::fs::read_to_string(sysroot.join(BUILD_INFO)).unwrap();
+        let lines: Vec<&str> = info.lines().collect();
+        assert_eq!(
+            lines[..lines.len() - 1],
+            [
+                "format=1".to_owned(),
+                format!("losb={}", env!("CARGO_PKG_VERSION")),
+                "profile=debug".to_owned(),
+                "arch=aarch64".to_owned(),
+                format!("component.tools.commit={}", commit),
+                "component.tools.dirty=false".to_owned(),
+                "component.docs.commit=unknown".to_owned(),
+                "component.docs.dirty=unknown".to_owned(),
+            ]
+        );
+        assert!(info.ends_with("Z\n"));
+        let built = lines.last().unwrap().strip_prefix("built=").unwrap();
+        assert_eq!(built.len(), "2026-01-01T12:00:00Z".len());
+
+        // Only a change to something besides the time replaces the file
+        assert!(!write_build_info(&config, &sysroot).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(sysroot.join(BUILD_INFO)).unwrap(),
+            info
+        );
+
+        std::fs::write(repository.join("tools/main.c"), "int main();").unwrap();
+        assert!(write_build_info(&config, &sysroot).unwrap());
+        let info = std::fs::read_to_string(sysroot.join(BUILD_INFO)).unwrap();
+        assert!(info.contains("\ncomponent.tools.dirty=true\n"));
+
+        config.select_profile("release").unwrap();
+        assert!(write_build_info(&config, &sysroot).unwrap());
+        let info = std::fs::read_to_string(sysroot.join(BUILD_INFO)).unwrap();
+        assert!(info.contains("\nprofile=release\n"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
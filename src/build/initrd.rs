@@ -0,0 +1,497 @@
+use super::{remove_installed, BuildError};
+use crate::{
+    config::{Compression, Config, InitrdFormat},
+    manifest::Manifest,
+    ova::{deflate, tar},
+};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, metadata, read, read_dir, remove_file, write},
+    path::{Path, PathBuf},
+};
+
+// The name the initrd is recorded under in the manifest, as if a component installed it. Component
+// names can't hold spaces, so no component can be recorded under it.
+const OWNER: &str = "the initrd";
+
+const CPIO_MAGIC: &str = "070701";
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const MODE_DIRECTORY: u32 = 0o040000;
+const MODE_FILE: u32 = 0o100000;
+
+struct Entry {
+    mode: u32, // Permissions only
+    directory: bool,
+}
+
+// Packs the configured part of the sysroot into an archive in the sysroot. Entries are sorted by
+// path and have no times or owners, so the same files always give the same archive. Returns if
+// anything changed.
+pub fn pack_initrd(
+    config: &Config,
+    sysroot: &Path,
+    manifest: &mut Manifest,
+) -> Result<bool, BuildError> {
+    let target = PathBuf::from(&config.initrd.path);
+    let written: Vec<PathBuf> = manifest
+        .files(OWNER)
+        .into_iter()
+        .map(Path::to_owned)
+        .collect();
+
+    // An initrd left at another path, or by a build which packed one, is removed
+    let mut changed = false;
+    for file in &written {
+        if config.initrd.include.is_empty() || *file != target {
+            remove_installed(sysroot, file).map_err(BuildError::Initrd)?;
+            changed = true;
+        }
+    }
+    if config.initrd.include.is_empty() {
+        manifest.set_files(OWNER, &[]);
+        return Ok(changed);
+    }
+
+    if let Some(owner) = manifest.owner(&target) {
+        if owner != OWNER {
+            return Err(BuildError::Conflict(
+                target.to_string_lossy().into_owned(),
+                owner.to_owned(),
+                OWNER.to_owned(),
+            ));
+        }
+    }
+    manifest.set_files(OWNER, std::slice::from_ref(&target));
+
+    let mut entries = BTreeMap::new();
+    for include in &config.initrd.include {
+        let include = Path::new(include.trim_start_matches('/'));
+        if !sysroot.join(include).exists() {
+            return Err(BuildError::Initrd(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} isn't in the sysroot", include.to_string_lossy()),
+            )));
+        }
+
+        // Parent directories are archived too, so unpacking doesn't depend on them existing
+        for parent in include.ancestors().skip(1) {
+            if !parent.as_os_str().is_empty() {
+                entries.insert(
+                    parent.to_owned(),
+                    Entry {
+                        mode: 0o755,
+                        directory: true,
+                    },
+                );
+            }
+        }
+        collect(sysroot, include, &target, &mut entries).map_err(BuildError::Initrd)?;
+    }
+
+    print!(
+        "     \x1B[36;1mPacking\x1B[0m {} ({} entries) . . .",
+        target.to_string_lossy(),
+        entries.len()
+    );
+
+    let archive = match config.initrd.format {
+        InitrdFormat::Cpio => cpio(sysroot, &entries),
+        InitrdFormat::Ustar => ustar(sysroot, &entries),
+    }
+    .map_err(BuildError::Initrd)?;
+    let archive = match config.initrd.compression {
+        Compression::None => archive,
+        Compression::Gzip => deflate::gzip(&archive),
+    };
+
+    // The sysroot's files may be linked into the previous sysroot, so they're replaced rather than
+    // written into, and only when they change so the image stays fresh
+    let path = sysroot.join(&target);
+    let status = match read(&path) {
+        Ok(existing) if existing == archive => "\r       \x1B[32;1mFresh\x1B[0m",
+        _ => {
+            remove_file(&path)
+                .or_else(|error| match error.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(error),
+                })
+                .and_then(|_| match path.parent() {
+                    Some(parent) => create_dir_all(parent),
+                    None => Ok(()),
+                })
+                .and_then(|_| write(&path, &archive))
+                .map_err(BuildError::Initrd)?;
+            changed = true;
+            "\r    \x1B[32;1mFinished\x1B[0m packing"
+        }
+    };
+    println!(
+        "{} {} ({} entries, {} KB)",
+        status,
+        target.to_string_lossy(),
+        entries.len(),
+        archive.len().div_ceil(1024)
+    );
+    Ok(changed)
+}
+
+// Adds a file or directory below the sysroot along with everything in it, leaving out the initrd
+fn collect(
+    sysroot: &Path,
+    path: &Path,
+    target: &Path,
+    entries: &mut BTreeMap<PathBuf, Entry>,
+) -> Result<(), std::io::Error> {
+    if path == target {
+        return Ok(());
+    }
+
+    let metadata = metadata(sysroot.join(path))?;
+    entries.insert(
+        path.to_owned(),
+        Entry {
+            mode: permissions(&metadata),
+            directory: metadata.is_dir(),
+        },
+    );
+
+    if metadata.is_dir() {
+        for entry in read_dir(sysroot.join(path))? {
+            collect(sysroot, &path.join(entry?.file_name()), target, entries)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &std::fs::Metadata) -> u32 {
+    match metadata.is_dir() {
+        true => 0o755,
+        false => 0o644,
+    }
+}
+
+// Writes a cpio archive in the newc format, each header being hexadecimal fields after the magic
+fn cpio(sysroot: &Path, entries: &BTreeMap<PathBuf, Entry>) -> Result<Vec<u8>, std::io::Error> {
+    let mut archive = Vec::new();
+    let mut append = |inode: usize, name: &str, mode: u32, data: &[u8]| {
+        let links = match mode & MODE_DIRECTORY {
+            0 => 1,
+            _ => 2,
+        };
+        // Inode, mode, owner, group, links, modification time, size, device major and minor,
+        // special device major and minor, name size and checksum, which newc doesn't use
+        let fields = [
+            inode as u32,
+            mode,
+            0,
+            0,
+            links,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u32 + 1,
+            0,
+        ];
+
+        archive.extend_from_slice(CPIO_MAGIC.as_bytes());
+        for field in fields {
+            archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        pad(&mut archive, 4);
+        archive.extend_from_slice(data);
+        pad(&mut archive, 4);
+    };
+
+    for (inode, (path, entry)) in entries.iter().enumerate() {
+        let name = archive_name(path);
+        match entry.directory {
+            true => append(inode + 1, &name, MODE_DIRECTORY | entry.mode, &[]),
+            false => append(
+                inode + 1,
+                &name,
+                MODE_FILE | entry.mode,
+                &read(sysroot.join(path))?,
+            ),
+        }
+    }
+    append(0, CPIO_TRAILER, 0, &[]);
+
+    // Padded to a whole block, as cpio itself does
+    pad(&mut archive, 512);
+    Ok(archive)
+}
+
+fn ustar(sysroot: &Path, entries: &BTreeMap<PathBuf, Entry>) -> Result<Vec<u8>, std::io::Error> {
+    let mut writer = tar::Writer::new(Vec::new(), 0);
+    for (path, entry) in entries {
+        let data = match entry.directory {
+            true => Vec::new(),
+            false => read(sysroot.join(path))?,
+        };
+        writer.append_entry(&archive_name(path), entry.mode, entry.directory, &data)?;
+    }
+    writer.finish()
+}
+
+// Paths in the archive always use forward slashes
+fn archive_name(path: &Path) -> String {
+    path.iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn pad(data: &mut Vec<u8>, alignment: usize) {
+    data.resize(data.len().div_ceil(alignment) * alignment, 0);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{pack_initrd, CPIO_MAGIC, CPIO_TRAILER, MODE_DIRECTORY, MODE_FILE};
+    use crate::{
+        build::BuildError,
+        config::{Arch, Config},
+        manifest::Manifest,
+        ova::deflate::tests::inflate,
+    };
+    use std::{
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+    };
+
+    // A sysroot whose files were written out of order, with the initrd configured to pack some
+    fn sysroot(name: &str) -> PathBuf {
+        let sysroot =
+            std::env::temp_dir().join(format!("losb-initrd-{}-{}", name, std::process::id()));
+        if sysroot.exists() {
+            std::fs::remove_dir_all(&sysroot).unwrap();
+        }
+        for (file, contents) in [
+            ("los/drivers/b.drv", &b"second driver"[..]),
+            ("los/bin/init", b"init"),
+            ("los/drivers/a.drv", b"first"),
+            ("los/bin/shell", b"not packed"),
+        ] {
+            let path = sysroot.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+        }
+        let mode = |path: &str, mode| {
+            std::fs::set_permissions(sysroot.join(path), PermissionsExt::from_mode(mode)).unwrap()
+        };
+        mode("los/bin/init", 0o755);
+        mode("los/drivers/a.drv", 0o644);
+        mode("los/drivers/b.drv", 0o600);
+        mode("los/drivers", 0o750);
+        sysroot
+    }
+
+    fn pack(sysroot: &Path, initrd: &str) -> Result<(bool, Vec<u8>), BuildError> {
+        let config = Config::parse(
+            &format!(
+                "[initrd]\ninclude = [\"los/drivers\", \"/los/bin/init\"]\n{}",
+                initrd
+            ),
+            Arch::X86_64,
+        )
+        .unwrap();
+        let mut manifest = Manifest::load(&config).unwrap();
+        pack_with(&config, sysroot, &mut manifest)
+    }
+
+    fn pack_with(
+        config: &Config,
+        sysroot: &Path,
+        manifest: &mut Manifest,
+    ) -> Result<(bool, Vec<u8>), BuildError> {
+        let changed = pack_initrd(config, sysroot, manifest)?;
+        Ok((
+            changed,
+            std::fs::read(sysroot.join(&config.initrd.path)).unwrap(),
+        ))
+    }
+
+    fn hex(field: &[u8]) -> u32 {
+        u32::from_str_radix(std::str::from_utf8(field).unwrap(), 16).unwrap()
+    }
+
+    fn octal(field: &[u8]) -> u64 {
+        let digits = std::str::from_utf8(field).unwrap().trim_end_matches('\0');
+        u64::from_str_radix(digits, 8).unwrap()
+    }
+
+    // The name, mode and contents of each entry in the order they were archived
+    type Entries = Vec<(String, u32, Vec<u8>)>;
+
+    fn read_cpio(archive: &[u8]) -> Entries {
+        let mut entries = Vec::new();
+        let mut position = 0;
+        loop {
+            assert_eq!(position % 4, 0);
+            let header = &archive[position..position + 110];
+            assert_eq!(&header[..6], CPIO_MAGIC.as_bytes());
+            let field = |index: usize| hex(&header[6 + index * 8..14 + index * 8]);
+            let (inode, mode, size, name_size) = (field(0), field(1), field(6), field(11));
+            assert_eq!((field(2), field(3), field(5)), (0, 0, 0)); // No owners or times
+
+            let name_start = position + 110;
+            let name = &archive[name_start..name_start + name_size as usize];
+            assert_eq!(name.last(), Some(&0));
+            let name = String::from_utf8(name[..name.len() - 1].to_vec()).unwrap();
+
+            let data_start = (name_start + name_size as usize).div_ceil(4) * 4;
+            assert!(archive[name_start + name_size as usize..data_start]
+                .iter()
+                .all(|byte| *byte == 0));
+            let data = archive[data_start..data_start + size as usize].to_vec();
+            position = (data_start + size as usize).div_ceil(4) * 4;
+
+            if name == CPIO_TRAILER {
+                assert_eq!((inode, mode, size), (0, 0, 0));
+                break;
+            }
+            assert_eq!(inode as usize, entries.len() + 1);
+            entries.push((name, mode, data));
+        }
+
+        // Padded with zeroes to a whole block
+        assert_eq!(archive.len() % 512, 0);
+        assert!(archive.len() - position < 512);
+        assert!(archive[position..].iter().all(|byte| *byte == 0));
+        entries
+    }
+
+    fn read_ustar(archive: &[u8]) -> Entries {
+        let mut entries = Vec::new();
+        let mut position = 0;
+        assert_eq!(archive.len() % 512, 0);
+        while archive[position..position + 512]
+            .iter()
+            .any(|byte| *byte != 0)
+        {
+            let header = &archive[position..position + 512];
+            assert_eq!(&header[257..263], b"ustar\0");
+            let name = std::str::from_utf8(&header[..100])
+                .unwrap()
+                .trim_end_matches('\0')
+                .to_owned();
+            let mode = octal(&header[100..108]) as u32;
+            let size = octal(&header[124..136]) as usize;
+            assert_eq!(octal(&header[136..148]), 0); // No time
+            let kind = match header[156] {
+                b'5' => MODE_DIRECTORY,
+                b'0' => MODE_FILE,
+                kind => panic!("unexpected entry kind {}", kind),
+            };
+
+            let data = archive[position + 512..position + 512 + size].to_vec();
+            position += 512 + size.div_ceil(512) * 512;
+            entries.push((name, kind | mode, data));
+        }
+        assert_eq!(archive.len() - position, 1024);
+        assert!(archive[position..].iter().all(|byte| *byte == 0));
+        entries
+    }
+
+    fn expected(directory_suffix: &str) -> Entries {
+        let directory = |name: &str, mode| {
+            (
+                format!("{}{}", name, directory_suffix),
+                MODE_DIRECTORY | mode,
+                Vec::new(),
+            )
+        };
+        let file =
+            |name: &str, mode, data: &[u8]| (name.to_owned(), MODE_FILE | mode, data.to_vec());
+        vec![
+            directory("los", 0o755),
+            directory("los/bin", 0o755),
+            file("los/bin/init", 0o755, b"init"),
+            directory("los/drivers", 0o750),
+            file("los/drivers/a.drv", 0o644, b"first"),
+            file("los/drivers/b.drv", 0o600, b"second driver"),
+        ]
+    }
+
+    #[test]
+    fn cpio() {
+        let sysroot = sysroot("cpio");
+        let (changed, archive) = pack(&sysroot, "").unwrap();
+        assert!(changed);
+        assert_eq!(read_cpio(&archive), expected(""));
+
+        // The same files pack into the same archive, which is left alone
+        let (changed, again) = pack(&sysroot, "").unwrap();
+        assert!(!changed);
+        assert_eq!(again, archive);
+
+        let (changed, compressed) = pack(&sysroot, "compression = \"gzip\"").unwrap();
+        assert!(changed);
+        assert_eq!(&compressed[..2], [0x1F, 0x8B]);
+        assert_eq!(inflate(&compressed[10..]).0, archive);
+
+        std::fs::remove_dir_all(sysroot).ok();
+    }
+
+    #[test]
+    fn ustar() {
+        let sysroot = sysroot("ustar");
+        let (changed, archive) = pack(&sysroot, "format = \"ustar\"").unwrap();
+        assert!(changed);
+        assert_eq!(read_ustar(&archive), expected("/"));
+        std::fs::remove_dir_all(sysroot).ok();
+    }
+
+    #[test]
+    fn manifest() {
+        let sysroot = sysroot("manifest");
+        let config =
+            |initrd: &str| Config::parse(&format!("[initrd]\n{}", initrd), Arch::X86_64).unwrap();
+        let packed = config("include = [\"los/bin/init\"]\npath = \"boot/initrd\"");
+        let mut manifest = Manifest::load(&packed).unwrap();
+
+        // A component installing the same path conflicts with the initrd
+        manifest.set_files("kernel", &[PathBuf::from("boot/initrd")]);
+        assert!(matches!(
+            pack_with(&packed, &sysroot, &mut manifest),
+            Err(BuildError::Conflict(..))
+        ));
+        manifest.set_files("kernel", &[]);
+
+        // A component named like the initrd is still told apart from it
+        manifest.set_files("initrd", &[PathBuf::from("los/bin/init")]);
+        pack_with(&packed, &sysroot, &mut manifest).unwrap();
+        assert_eq!(manifest.files("initrd"), [Path::new("los/bin/init")]);
+        assert_eq!(manifest.owner(Path::new("boot/initrd")), Some("the initrd"));
+
+        // Emptying the list removes the initrd and the directories it leaves empty
+        assert!(pack_initrd(&config(""), &sysroot, &mut manifest).unwrap());
+        assert!(!sysroot.join("boot").exists());
+        assert_eq!(manifest.owner(Path::new("boot/initrd")), None);
+        assert!(!pack_initrd(&config(""), &sysroot, &mut manifest).unwrap());
+
+        // A missing file is an error rather than left out
+        assert!(matches!(
+            pack_initrd(
+                &config("include = [\"los/missing\"]"),
+                &sysroot,
+                &mut manifest
+            ),
+            Err(BuildError::Initrd(_))
+        ));
+
+        std::fs::remove_dir_all(sysroot).ok();
+    }
+}
@@ -0,0 +1,299 @@
+use crate::arguments::Verbosity;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// The lines of output shown again when a component fails
+const TAIL_LINES: usize = 20;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// How long output is still read for once a command exits
+const DRAIN_QUIET: Duration = Duration::from_millis(100);
+const DRAIN_LIMIT: Duration = Duration::from_secs(1);
+
+// A component being built, which either prints its output as it comes or buffers it to be
+// printed once the component is finished. Everything its commands output is also logged.
+pub struct Job<'a> {
+    buffer: Option<Mutex<Vec<String>>>,
+    log: Mutex<Log>,
+    verbosity: Verbosity,
+    cancelled: &'a AtomicBool,
+}
+
+// The log is only replaced once something is run, so skipped components keep their last one
+struct Log {
+    path: PathBuf,
+    file: Option<File>,
+    tail: VecDeque<String>,
+    lines: usize,
+}
+
+impl<'a> Job<'a> {
+    pub fn new(
+        buffered: bool,
+        verbosity: Verbosity,
+        log: &Path,
+        cancelled: &'a AtomicBool,
+    ) -> Self {
+        Job {
+            buffer: match buffered {
+                true => Some(Mutex::new(Vec::new())),
+                false => None,
+            },
+            log: Mutex::new(Log {
+                path: log.to_owned(),
+                file: None,
+                tail: VecDeque::with_capacity(TAIL_LINES),
+                lines: 0,
+            }),
+            verbosity,
+            cancelled,
+        }
+    }
+
+    // Prints a status line straight away, even when the rest of the output is buffered
+    pub fn status(&self, line: String) {
+        println!("{}", line);
+    }
+
+    pub fn println(&self, line: String) {
+        match &self.buffer {
+            Some(buffer) => buffer.lock().unwrap().push(line),
+            // A closed stdout shouldn't stop the build
+            None => {
+                writeln!(std::io::stdout(), "{}", line).ok();
+            }
+        }
+    }
+
+    // Logs a line output by a command, printing it unless quiet
+    fn output(&self, line: String) {
+        let mut log = self.log.lock().unwrap();
+        log.write(&line);
+        if log.tail.len() == TAIL_LINES {
+            log.tail.pop_front();
+        }
+        log.tail.push_back(line.clone());
+        log.lines += 1;
+        drop(log);
+
+        if self.verbosity != Verbosity::Quiet {
+            self.println(line);
+        }
+    }
+
+    // What to print after the component fails, the end of its output unless all of it was just
+    // printed, and where to find the rest
+    pub fn failure_report(&self) -> Vec<String> {
+        let log = self.log.lock().unwrap();
+        let mut report = Vec::new();
+        if log.lines > 0 && (self.verbosity == Verbosity::Quiet || log.lines > TAIL_LINES) {
+            report.push(format!(
+                "\x1B[1mLast {} of {} lines of output:\x1B[0m",
+                log.tail.len(),
+                log.lines
+            ));
+            report.extend(log.tail.iter().cloned());
+        }
+        report.push(format!(
+            "\x1B[1mFull output in {}\x1B[0m",
+            log.path.to_string_lossy()
+        ));
+        report
+    }
+
+    pub fn into_output(self) -> Vec<String> {
+        match self.buffer {
+            Some(buffer) => buffer.into_inner().unwrap(),
+            None => Vec::new(),
+        }
+    }
+
+    // Runs a command, killing it if another job fails. Lines on stdout are given to
+    // stdout_line, those it doesn't consume are output and logged along with stderr.
+    pub fn run(
+        &self,
+        command: &mut Command,
+        mut stdout_line: Option<&mut dyn FnMut(&str) -> bool>,
+    ) -> Result<ExitStatus, std::io::Error> {
+        let command_line = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.log
+            .lock()
+            .unwrap()
+            .write(&format!("$ {}", command_line));
+        if self.verbosity == Verbosity::Verbose {
+            self.println(format!("     \x1B[32;1mRunning\x1B[0m `{}`", command_line));
+        }
+
+        // Output is always piped so it can be logged
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+
+        let mut child = command.spawn()?;
+
+        // Pipes are read on their own threads, which are left behind once the command exits or
+        // is killed as anything it started may keep them open
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, true, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, false, sender.clone());
+        }
+        drop(sender);
+
+        let mut output = |(from_stdout, line): (bool, String)| {
+            let consumed = match (&mut stdout_line, from_stdout) {
+                (Some(stdout_line), true) => stdout_line(&line),
+                _ => false,
+            };
+
+            if !consumed {
+                self.output(line);
+            }
+        };
+
+        let status = loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => output(line),
+                Err(RecvTimeoutError::Timeout) => {}
+                // The command may close its output before it exits
+                Err(RecvTimeoutError::Disconnected) => std::thread::sleep(POLL_INTERVAL),
+            }
+
+            if self.cancelled.load(Ordering::Relaxed) {
+                child.kill().ok(); // It may have already exited
+                return child.wait();
+            }
+
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+        };
+
+        // Anything the command started may keep its output open after it exits, so only what is
+        // left in the pipes is read, stopping once they go quiet
+        let deadline = Instant::now() + DRAIN_LIMIT;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match receiver.recv_timeout(remaining.min(DRAIN_QUIET)) {
+                Ok(line) => output(line),
+                Err(_) => break,
+            }
+        }
+
+        Ok(status)
+    }
+}
+
+impl Log {
+    // Like stdout, a log which can't be written shouldn't stop the build
+    fn write(&mut self, line: &str) {
+        if self.file.is_none() {
+            self.file = File::create(&self.path).ok();
+        }
+
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", line).ok();
+        }
+    }
+}
+
+// Reads until the end of the output, so the command is never left writing into a closed pipe.
+// Output which isn't UTF-8 is read as best it can be, and lines read once nothing is listening
+// are dropped.
+fn read_lines<R: Read + Send + 'static>(reader: R, stdout: bool, sender: Sender<(bool, String)>) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+            }
+            sender
+                .send((stdout, String::from_utf8_lossy(&line).into_owned()))
+                .ok();
+        }
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::Job;
+    use crate::arguments::Verbosity;
+    use std::{
+        process::Command,
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn returns_when_the_command_exits() {
+        let log = std::env::temp_dir().join(format!("losb-job-{}.log", std::process::id()));
+        let cancelled = AtomicBool::new(false);
+        let job = Job::new(true, Verbosity::Normal, &log, &cancelled);
+
+        // The background command keeps stdout open long after the shell exits
+        let start = Instant::now();
+        let status = job
+            .run(
+                Command::new("sh").args(["-c", "echo first; (sleep 10; echo late) & echo last"]),
+                None,
+            )
+            .unwrap();
+        assert!(status.success());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(job.into_output(), ["first", "last"]);
+
+        std::fs::remove_file(log).ok();
+    }
+
+    #[test]
+    fn reads_past_output_which_is_not_utf8() {
+        let log = std::env::temp_dir().join(format!("losb-job-utf8-{}.log", std::process::id()));
+        let cancelled = AtomicBool::new(false);
+        let job = Job::new(true, Verbosity::Normal, &log, &cancelled);
+
+        // Far more follows the bad line than a pipe holds, so a reader which stopped there would
+        // leave the command unable to finish writing
+        let script = "printf 'first\\r\\n\\377\\376 bad\\n'; \
+            i=0; while [ $i -lt 5000 ]; do echo \"line $i of the output\"; i=$((i+1)); done";
+        let status = job
+            .run(Command::new("sh").args(["-c", script]), None)
+            .unwrap();
+        assert!(status.success());
+
+        let output = job.into_output();
+        assert_eq!(output.len(), 5002);
+        assert_eq!(output[0], "first");
+        assert_eq!(output[1], "\u{FFFD}\u{FFFD} bad");
+        assert_eq!(output[5001], "line 4999 of the output");
+
+        std::fs::remove_file(log).ok();
+    }
+}
@@ -0,0 +1,1142 @@
+use crate::config::{
+    Arch, Component, ComponentKind, Config, Profile, DEFAULT_ARCH, DEFAULT_PROFILE,
+};
+use crate::fingerprint::{self, skip_source, Fingerprint, Hasher};
+use crate::manifest::{self, Manifest};
+use crate::timings::Timings;
+use job::Job;
+use std::{
+    env::current_dir,
+    fs::{
+        copy, create_dir_all, hard_link, read_dir, remove_dir, remove_dir_all, remove_file, rename,
+    },
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{atomic::AtomicBool, atomic::Ordering, mpsc, Mutex},
+};
+
+mod boot;
+mod cargo;
+mod demangle;
+mod info;
+mod initrd;
+mod job;
+mod symbol_map;
+mod symbols;
+mod validate;
+
+pub use boot::boot_config;
+
+#[derive(Debug)]
+pub enum BuildError {
+    Cargo(String, Option<std::io::Error>),
+    Brew(String, Option<std::io::Error>),
+    Custom(String, Option<std::io::Error>),
+    PrepareSysroot(std::io::Error),
+    InstallError(String, std::io::Error),
+    NoArtifact(String),
+    MissingArtifact(String, String, Vec<String>),
+    AmbiguousArtifact(String, Vec<String>),
+    Fingerprint(std::io::Error),
+    Log(String, std::io::Error),
+    Timings(std::io::Error),
+    BuildInfo(std::io::Error),
+    BootConfig(std::io::Error),
+    Symbols(String, Option<std::io::Error>),
+    SymbolMap(String, Option<std::io::Error>),
+    InvalidBinary(String, validate::BinaryError),
+    Conflict(String, String, String),
+    Manifest(std::io::Error),
+    ReplaceSysroot(std::io::Error),
+    Initrd(std::io::Error),
+}
+
+// Where components are installed, shared by the components building at once
+struct Sysroot {
+    path: PathBuf, // The next sysroot, which replaces the sysroot once every component succeeds
+    symbols: PathBuf, // The next symbols, replacing the symbols along with the sysroot
+    staging: PathBuf, // Each component installs below here first, then is moved into the sysroot
+    manifest: Mutex<Manifest>,
+    changed: AtomicBool,
+}
+
+fn install_build(
+    job: &Job,
+    component: &Component,
+    profile: &Profile,
+    arch: Arch,
+    prefix: &Path,
+    sysroot: &Path,
+) -> Result<(), BuildError> {
+    let path = &component.path;
+    let mut command = Command::new("brew");
+    command.args([
+        "install",
+        "--sysroot",
+        sysroot.to_str().unwrap(),
+        "--prefix",
+        prefix.to_str().unwrap(),
+    ]);
+    // Older versions of brew have no configurations or architectures, so the defaults aren't
+    // passed and debug builds for x86_64 run brew as they always have
+    if profile.brew != DEFAULT_PROFILE {
+        command.args(["--profile", &profile.brew]);
+    }
+    if arch != DEFAULT_ARCH {
+        command.args(["--arch", arch.name()]);
+    }
+    command.envs(component.env.iter().map(|(key, value)| (key, value)));
+    command.current_dir(path);
+
+    match job.run(&mut command, None) {
+        Ok(status) => match status.success() {
+            true => Ok(()),
+            false => Err(BuildError::Brew(path.to_string(), None)),
+        },
+        Err(error) => Err(BuildError::Brew(path.to_string(), Some(error))),
+    }
+}
+
+// Runs a custom build command, telling it where the sysroot is through the environment
+fn build_custom(
+    job: &Job,
+    component: &Component,
+    command: &[String],
+    config: &Config,
+    prefix: &Path,
+    sysroot: &Path,
+) -> Result<(), BuildError> {
+    let (program, arguments) = match command.split_first() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+
+    let path = &component.path;
+    let mut command = Command::new(program);
+    command.args(arguments);
+    command.envs(component.env.iter().map(|(key, value)| (key, value)));
+    command.env("LOSB_SYSROOT", sysroot);
+    command.env("LOSB_PREFIX", prefix);
+    command.env("LOSB_PROFILE", &config.profile.name);
+    command.env("LOSB_ARCH", config.arch.name());
+    command.current_dir(path);
+
+    match job.run(&mut command, None) {
+        Ok(status) => match status.success() {
+            true => Ok(()),
+            false => Err(BuildError::Custom(path.to_owned(), None)),
+        },
+        Err(error) => Err(BuildError::Custom(path.to_owned(), Some(error))),
+    }
+}
+
+// Starts the next sysroot as the sysroot, linking files rather than copying them where possible.
+// Nothing is written into a linked file, files are always removed and replaced.
+fn start_sysroot(sysroot: &Path, next: &Path) -> Result<(), std::io::Error> {
+    start_tree(sysroot, next)?;
+    create_dir_all(next)
+}
+
+// Starts the next copy of a tree as the tree, which is left missing while the tree is
+fn start_tree(tree: &Path, next: &Path) -> Result<(), std::io::Error> {
+    if next.exists() {
+        remove_dir_all(next)?;
+    }
+
+    match tree.exists() {
+        true => link_tree(tree, next),
+        false => Ok(()),
+    }
+}
+
+fn link_tree(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    if !from.is_dir() {
+        return hard_link(from, to).or_else(|_| copy(from, to).map(|_| ()));
+    }
+
+    create_dir_all(to)?;
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        link_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+// Moves the sysroot aside as the previous sysroot and the next sysroot into its place, and the
+// same for the symbols
+fn replace_sysroot(config: &Config, sysroot: &Sysroot) -> Result<(), std::io::Error> {
+    let current_path = current_dir()?;
+    let absolute = |path: PathBuf| current_path.join(path.strip_prefix(".").unwrap_or(&path));
+    replace_tree(
+        &sysroot.path,
+        &absolute(config.sysroot()),
+        &absolute(config.previous_sysroot()),
+    )?;
+    replace_tree(
+        &sysroot.symbols,
+        &absolute(config.symbols_path()),
+        &absolute(config.previous_symbols()),
+    )
+}
+
+fn replace_tree(next: &Path, tree: &Path, previous: &Path) -> Result<(), std::io::Error> {
+    if previous.exists() {
+        remove_dir_all(previous)?;
+    }
+
+    if tree.exists() {
+        if let Some(parent) = previous.parent() {
+            create_dir_all(parent)?;
+        }
+        rename(tree, previous)?;
+    }
+
+    match next.exists() {
+        true => {
+            if let Some(parent) = tree.parent() {
+                create_dir_all(parent)?;
+            }
+            rename(next, tree)
+        }
+        false => Ok(()),
+    }
+}
+
+fn prepare_sysroot(sysroot: &Path) -> Result<(), std::io::Error> {
+    create_dir_all(sysroot.join("los/bin"))?;
+    create_dir_all(sysroot.join("los/lib"))?;
+    create_dir_all(sysroot.join("los/include"))?;
+    create_dir_all(sysroot.join("EFI/BOOT"))
+}
+
+// Copies a file or directory into the sysroot, replacing files already there
+fn install(from: &Path, to: &Path) -> Result<(), BuildError> {
+    let result = match to.parent() {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|_| copy_tree(from, to));
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildError::InstallError(
+            from.to_string_lossy().into_owned(),
+            error,
+        )),
+    }
+}
+
+// Every file at or below path, relative to the sysroot, in a fixed order
+fn installed_files(sysroot: &Path, path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    if !sysroot.join(path).is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    let mut entries = Vec::new();
+    for entry in read_dir(sysroot.join(path))? {
+        entries.push(entry?.file_name());
+    }
+    entries.sort();
+
+    let mut files = Vec::new();
+    for entry in entries {
+        files.append(&mut installed_files(sysroot, &path.join(entry))?);
+    }
+    Ok(files)
+}
+
+// Empties the directory a component is installed into before being moved into the sysroot
+fn stage(component: &Component, sysroot: &Sysroot) -> Result<PathBuf, BuildError> {
+    let staging = sysroot.staging.join(&component.name);
+    let result = match staging.exists() {
+        true => remove_dir_all(&staging),
+        false => Ok(()),
+    }
+    .and_then(|_| create_dir_all(&staging));
+
+    match result {
+        Ok(()) => Ok(staging),
+        Err(error) => Err(BuildError::InstallError(
+            staging.to_string_lossy().into_owned(),
+            error,
+        )),
+    }
+}
+
+// Splits the debug information out of what a component staged and checks it will run, then
+// moves it into the sysroot. Files the component installed before but no longer does are removed.
+fn merge(
+    job: &Job,
+    config: &Config,
+    component: &Component,
+    sysroot: &Sysroot,
+    staging: &Path,
+) -> Result<(), BuildError> {
+    let install_error =
+        |path: &Path, error| BuildError::InstallError(path.to_string_lossy().into_owned(), error);
+
+    // Only the file the kernel's crate installs is checked as the kernel
+    let kernel = match &component.kind {
+        ComponentKind::Cargo(cargo) if config.is_kernel(component) => cargo.install.as_deref(),
+        _ => None,
+    };
+
+    let files =
+        installed_files(staging, Path::new("")).map_err(|error| install_error(staging, error))?;
+    for file in &files {
+        symbols::split_symbols(job, config, staging, &sysroot.symbols, file)?;
+        validate::validate(
+            config,
+            file,
+            &staging.join(file),
+            kernel.map(Path::new) == Some(file.as_path()),
+        )?;
+    }
+
+    // The manifest is held until the files are moved, so two components can't claim a file at once
+    let mut manifest = sysroot.manifest.lock().unwrap();
+    for file in &files {
+        match manifest.owner(file) {
+            Some(owner) if owner != component.name => {
+                return Err(BuildError::Conflict(
+                    file.to_string_lossy().into_owned(),
+                    owner.to_owned(),
+                    component.name.clone(),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let stale: Vec<PathBuf> = manifest
+        .files(&component.name)
+        .into_iter()
+        .filter(|file| !files.iter().any(|installed| installed == file))
+        .map(Path::to_owned)
+        .collect();
+    for file in stale {
+        for root in [&sysroot.path, &sysroot.symbols] {
+            remove_installed(root, &file)
+                .map_err(|error| install_error(&root.join(&file), error))?;
+        }
+    }
+
+    copy_tree(staging, &sysroot.path).map_err(|error| install_error(staging, error))?;
+    manifest.set_files(&component.name, &files);
+    sysroot.changed.store(true, Ordering::Relaxed);
+    remove_dir_all(staging).map_err(|error| install_error(staging, error))
+}
+
+// Removes a file below root along with the directories it leaves empty
+fn remove_installed(root: &Path, file: &Path) -> Result<(), std::io::Error> {
+    match remove_file(root.join(file)) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    }
+
+    for parent in file.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() || remove_dir(root.join(parent)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Copies a file or directory, replacing rather than writing into files already there
+fn copy_tree(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    if !from.is_dir() {
+        match remove_file(to) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        return copy(from, to).map(|_| ());
+    }
+
+    create_dir_all(to)?;
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+// Removes what variants of the kernel which are no longer configured installed
+fn remove_variants(config: &Config, sysroot: &Sysroot) -> Result<(), BuildError> {
+    let mut manifest = sysroot.manifest.lock().unwrap();
+    let removed: Vec<String> = manifest
+        .components()
+        .into_iter()
+        .filter(|owner| owner.starts_with("kernel-") && config.component(owner).is_none())
+        .map(str::to_owned)
+        .collect();
+    for owner in removed {
+        let files: Vec<PathBuf> = manifest
+            .files(&owner)
+            .into_iter()
+            .map(Path::to_owned)
+            .collect();
+        for file in files {
+            for root in [&sysroot.path, &sysroot.symbols] {
+                remove_installed(root, &file).map_err(BuildError::PrepareSysroot)?;
+            }
+        }
+        manifest.set_files(&owner, &[]);
+        sysroot.changed.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// Hashes everything about a component which changes what it installs, other than its dependencies
+fn component_inputs(config: &Config, component: &Component) -> Hasher {
+    let mut hasher = Hasher::new();
+    hasher.write_str(&config.profile.name);
+    hasher.write_str(&config.profile.cargo);
+    hasher.write_str(&config.profile.brew);
+    hasher.write_str(config.arch.name());
+    hasher.write_str(component.kind_name());
+    for (key, value) in &component.env {
+        hasher.write_str(key);
+        hasher.write_str(value);
+    }
+
+    match &component.kind {
+        ComponentKind::Cargo(cargo) => {
+            for setting in [&cargo.target, &cargo.profile, &cargo.binary, &cargo.install] {
+                hasher.write_str(setting.as_deref().unwrap_or(""));
+            }
+            for feature in &cargo.features {
+                hasher.write_str(feature);
+            }
+            hasher.write(&[cargo.default_features as u8]);
+        }
+        ComponentKind::Brew => hasher.write_tree(Path::new(&component.path), &skip_source),
+        ComponentKind::Custom(custom) => {
+            for argument in &custom.build {
+                hasher.write_str(argument);
+            }
+            for (from, to) in &custom.install {
+                hasher.write_str(from);
+                hasher.write_str(to);
+            }
+            hasher.write_tree(Path::new(&component.path), &skip_source);
+        }
+    }
+
+    hasher
+}
+
+// Hashes the files the manifest says a component installed
+fn component_outputs(component: &Component, sysroot: &Sysroot) -> u64 {
+    let mut hasher = Hasher::new();
+    for file in sysroot.manifest.lock().unwrap().files(&component.name) {
+        hasher.write_str(&file.to_string_lossy());
+        hasher.write_tree(&sysroot.path.join(file), &|_| false);
+    }
+    hasher.finish()
+}
+
+fn save_fingerprint(path: &Path, inputs: u64, outputs: u64) -> Result<(), BuildError> {
+    match fingerprint::save(path, &Fingerprint { inputs, outputs }) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildError::Fingerprint(error)),
+    }
+}
+
+// Builds and installs a component, unless neither it nor its dependencies have changed since it
+// was last installed. Returns if it was fresh.
+fn build_component(
+    job: &Job,
+    config: &Config,
+    component: &Component,
+    dependencies_fresh: bool,
+    sysroot: &Sysroot,
+    timings: &Timings,
+) -> Result<bool, BuildError> {
+    let fingerprint_path = fingerprint::directory(config).join(&component.name);
+    let building = format!(
+        "    \x1B[32;1mBuilding\x1B[0m {} ({}) . . .",
+        component.name,
+        config.variant_name()
+    );
+    let fresh = format!(
+        "       \x1B[32;1mFresh\x1B[0m {} ({})",
+        component.name,
+        config.variant_name()
+    );
+
+    // Cargo decides if a crate is fresh itself, only the install is skipped
+    if let ComponentKind::Cargo(cargo) = &component.kind {
+        job.status(building);
+        let (artifact, artifact_fresh) = timings
+            .time(format!("cargo build {}", component.name), || {
+                cargo::build(job, &component.path, &config.profile, cargo, &component.env)
+            })?;
+
+        let installed_to = match &cargo.install {
+            Some(install) => install,
+            None => return Ok(artifact_fresh && dependencies_fresh),
+        };
+
+        let mut inputs = component_inputs(config, component);
+        inputs.write_str(&artifact.to_string_lossy());
+        inputs.write_tree(&artifact, &|_| false);
+        let inputs = inputs.finish();
+
+        let current = Fingerprint {
+            inputs,
+            outputs: component_outputs(component, sysroot),
+        };
+        if artifact_fresh && fingerprint::load(&fingerprint_path) == Some(current) {
+            return Ok(dependencies_fresh);
+        }
+
+        job.println(format!(
+            "\n  \x1B[32;1mInstalling\x1B[0m {} . . .",
+            component.name
+        ));
+        timings.time(format!("install {}", component.name), || {
+            let staging = stage(component, sysroot)?;
+            install(&artifact, &staging.join(installed_to))?;
+            if let Some(map) = config.symbol_map(component) {
+                symbol_map::write_symbol_map(&staging, Path::new(installed_to), Path::new(map))?;
+            }
+            merge(job, config, component, sysroot, &staging)
+        })?;
+        save_fingerprint(
+            &fingerprint_path,
+            inputs,
+            component_outputs(component, sysroot),
+        )?;
+        return Ok(false);
+    }
+
+    let current = Fingerprint {
+        inputs: component_inputs(config, component).finish(),
+        outputs: component_outputs(component, sysroot),
+    };
+    if dependencies_fresh && fingerprint::load(&fingerprint_path) == Some(current) {
+        job.status(fresh);
+        return Ok(true);
+    }
+
+    // Both brew and custom builds install into the prefix they are given, which is staged
+    job.status(building);
+    let staging = stage(component, sysroot)?;
+    let prefix = staging.join("los");
+    match &component.kind {
+        ComponentKind::Custom(custom) => {
+            timings.time(format!("custom build {}", component.name), || {
+                build_custom(
+                    job,
+                    component,
+                    &custom.build,
+                    config,
+                    &prefix,
+                    &sysroot.path,
+                )
+            })?;
+
+            if !custom.install.is_empty() {
+                job.println(format!(
+                    "\n  \x1B[32;1mInstalling\x1B[0m {} . . .",
+                    component.name
+                ));
+            }
+            timings.time(format!("install {}", component.name), || {
+                for (from, to) in &custom.install {
+                    install(&Path::new(&component.path).join(from), &staging.join(to))?;
+                }
+                merge(job, config, component, sysroot, &staging)
+            })?;
+        }
+        _ => timings.time(format!("brew install {}", component.name), || {
+            install_build(
+                job,
+                component,
+                &config.profile,
+                config.arch,
+                &prefix,
+                &sysroot.path,
+            )?;
+            merge(job, config, component, sysroot, &staging)
+        })?,
+    }
+
+    // The fingerprint is taken after building so files the build itself writes don't count
+    save_fingerprint(
+        &fingerprint_path,
+        component_inputs(config, component).finish(),
+        component_outputs(component, sysroot),
+    )?;
+    Ok(false)
+}
+
+pub fn build(config: &Config) -> Result<(), BuildError> {
+    let timings = Timings::new();
+    build_components(config, &timings)?;
+    match timings.finish(config) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildError::Timings(error)),
+    }
+}
+
+// Builds every selected component, recording how long each part takes
+pub fn build_components(config: &Config, timings: &Timings) -> Result<(), BuildError> {
+    let components = &config.components;
+
+    // Prepare the sysroot, components install into the next sysroot which replaces it at the end
+    let current_path = current_dir().expect("No current working directory!");
+    let absolute = |path: PathBuf| current_path.join(path.strip_prefix(".").unwrap_or(&path));
+    let sysroot = absolute(config.sysroot());
+    let next = absolute(config.next_sysroot());
+    let next_symbols = absolute(config.next_symbols());
+
+    // Anything recorded as installed into a missing sysroot is gone
+    if !sysroot.exists() {
+        match fingerprint::clear(Some(config)) {
+            Ok(()) => {}
+            Err(error) => return Err(BuildError::Fingerprint(error)),
+        }
+        match manifest::clear(Some(config)) {
+            Ok(()) => {}
+            Err(error) => return Err(BuildError::Manifest(error)),
+        }
+    }
+
+    match start_sysroot(&sysroot, &next)
+        .and_then(|_| prepare_sysroot(&next))
+        .and_then(|_| start_tree(&absolute(config.symbols_path()), &next_symbols))
+    {
+        Ok(()) => {}
+        Err(error) => return Err(BuildError::PrepareSysroot(error)),
+    }
+
+    let state = Path::new(crate::config::STATE_DIR);
+    let next = Sysroot {
+        staging: current_path
+            .join(state.strip_prefix(".").unwrap_or(state))
+            .join("staging")
+            .join(sysroot.file_name().unwrap_or_default()),
+        manifest: Mutex::new(Manifest::load(config).map_err(BuildError::Manifest)?),
+        changed: AtomicBool::new(false),
+        path: next,
+        symbols: next_symbols,
+    };
+
+    remove_variants(config, &next)?;
+
+    // Everything run is logged, one file for each component
+    let logs = Path::new(crate::config::STATE_DIR).join("logs");
+    match create_dir_all(&logs) {
+        Ok(()) => {}
+        Err(error) => return Err(BuildError::Log(logs.to_string_lossy().into_owned(), error)),
+    }
+
+    // Output is only buffered when components build alongside each other
+    let buffered = config.jobs > 1;
+    let prefix_width = components
+        .iter()
+        .map(|component| component.name.len())
+        .max()
+        .unwrap_or(0);
+    let dependencies: Vec<Vec<usize>> = components
+        .iter()
+        .map(|component| {
+            component
+                .depends_on
+                .iter()
+                .filter_map(|dependency| {
+                    components
+                        .iter()
+                        .position(|component| &component.name == dependency)
+                })
+                .collect()
+        })
+        .collect();
+
+    // Components which weren't selected are left as they are, as if already built
+    let skipped: Vec<bool> = config
+        .build_selection()
+        .into_iter()
+        .map(|selected| !selected)
+        .collect();
+
+    let cancelled = AtomicBool::new(false);
+    let mut started = skipped.clone();
+    let mut finished = skipped.clone();
+    let mut fresh = skipped;
+    let mut any_started = false;
+    let mut running = 0;
+    let mut first_error = None;
+
+    // Build every component once those it depends on are installed, up to the job limit at once
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| loop {
+        if first_error.is_none() {
+            for (index, component) in components.iter().enumerate() {
+                if running >= config.jobs {
+                    break;
+                }
+
+                if started[index]
+                    || !dependencies[index]
+                        .iter()
+                        .all(|dependency| finished[*dependency])
+                {
+                    continue;
+                }
+
+                if !buffered && any_started {
+                    println!();
+                }
+
+                started[index] = true;
+                any_started = true;
+                running += 1;
+
+                let dependencies_fresh = dependencies[index]
+                    .iter()
+                    .all(|dependency| fresh[*dependency]);
+                let sender = sender.clone();
+                let (cancelled, sysroot, logs) = (&cancelled, &next, &logs);
+                scope.spawn(move || {
+                    let log = logs.join(format!("{}.log", component.name));
+                    let job = Job::new(buffered, config.verbosity, &log, cancelled);
+
+                    let result = build_component(
+                        &job,
+                        config,
+                        component,
+                        dependencies_fresh,
+                        sysroot,
+                        timings,
+                    );
+                    let report = match result {
+                        Ok(_) => Vec::new(),
+                        Err(_) => job.failure_report(),
+                    };
+                    sender.send((index, result, job.into_output(), report)).ok();
+                });
+            }
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let (index, result, output, report) = receiver.recv().unwrap();
+        running -= 1;
+
+        // Components failing after the first were cancelled, so their output is dropped
+        if result.is_err() && first_error.is_some() {
+            continue;
+        }
+
+        for line in output.iter().flat_map(|output| output.split('\n')) {
+            println!(
+                "{:>width$} | {}",
+                components[index].name,
+                line,
+                width = prefix_width
+            );
+        }
+
+        match result {
+            Ok(component_fresh) => {
+                finished[index] = true;
+                fresh[index] = component_fresh;
+            }
+            Err(error) => {
+                cancelled.store(true, Ordering::Relaxed);
+                first_error = Some(error);
+
+                println!();
+                for line in report {
+                    println!("{}", line);
+                }
+            }
+        }
+    });
+
+    if let Some(error) = first_error {
+        return Err(error);
+    }
+
+    match info::write_build_info(config, &next.path) {
+        Ok(true) => next.changed.store(true, Ordering::Relaxed),
+        Ok(false) => {}
+        Err(error) => return Err(BuildError::BuildInfo(error)),
+    }
+    if boot::write_boot_config(config, &next.path, &mut next.manifest.lock().unwrap())? {
+        next.changed.store(true, Ordering::Relaxed);
+    }
+    // Packed last, so the initrd holds everything else in the sysroot
+    if timings.time("pack initrd".to_owned(), || {
+        initrd::pack_initrd(config, &next.path, &mut next.manifest.lock().unwrap())
+    })? {
+        next.changed.store(true, Ordering::Relaxed);
+    }
+
+    // An unchanged build is dropped so the previous sysroot stays the one before the last change
+    if !next.changed.load(Ordering::Relaxed) {
+        let result = remove_dir_all(&next.path).and_then(|_| match next.symbols.exists() {
+            true => remove_dir_all(&next.symbols),
+            false => Ok(()),
+        });
+        return result.map_err(BuildError::ReplaceSysroot);
+    }
+
+    match replace_sysroot(config, &next) {
+        Ok(()) => {}
+        Err(error) => return Err(BuildError::ReplaceSysroot(error)),
+    }
+    match next.manifest.into_inner().unwrap().save() {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildError::Manifest(error)),
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                BuildError::Cargo(path, error) => format!(
+                    "Unable to build {} using cargo{}",
+                    path,
+                    match error {
+                        Some(error) => format!(" ({})", error),
+                        None => String::new(),
+                    }
+                ),
+                BuildError::Brew(path, error) => format!(
+                    "Unable to build {} using brew{}",
+                    path,
+                    match error {
+                        Some(error) => format!(" ({})", error),
+                        None => String::new(),
+                    }
+                ),
+                BuildError::Custom(path, error) => format!(
+                    "Unable to build {}{}",
+                    path,
+                    match error {
+                        Some(error) => format!(" ({})", error),
+                        None => String::new(),
+                    }
+                ),
+                BuildError::Symbols(path, error) => format!(
+                    "Unable to split the debug information out of {}{}",
+                    path,
+                    match error {
+                        Some(error) => format!(" ({})", error),
+                        None => String::new(),
+                    }
+                ),
+                BuildError::SymbolMap(path, error) => format!(
+                    "Unable to write the symbol map of {} ({})",
+                    path,
+                    match error {
+                        Some(error) => error.to_string(),
+                        None => "it has no symbol table".to_owned(),
+                    }
+                ),
+                BuildError::InvalidBinary(path, error) => format!("{} {}", path, error),
+                BuildError::Conflict(path, first, second) =>
+                    format!("{} and {} both install {}", first, second, path),
+                BuildError::Manifest(error) =>
+                    format!("Unable to record what was installed ({})", error),
+                BuildError::Initrd(error) => format!("Unable to pack the initrd ({})", error),
+                BuildError::ReplaceSysroot(error) => format!(
+                    "Unable to replace the sysroot with the one built ({})",
+                    error
+                ),
+                BuildError::BuildInfo(error) => format!(
+                    "Unable to write {} into the sysroot ({})",
+                    crate::config::BUILD_INFO,
+                    error
+                ),
+                BuildError::BootConfig(error) => format!(
+                    "Unable to write the boot configuration into the sysroot ({})",
+                    error
+                ),
+                BuildError::Timings(error) => format!("Unable to record build timings ({})", error),
+                BuildError::Log(path, error) =>
+                    format!("Unable to write the build log {} ({})", path, error),
+                BuildError::Fingerprint(error) =>
+                    format!("Unable to record what was built ({})", error),
+                BuildError::PrepareSysroot(error) =>
+                    format!("Unable to prepare sysroot ({})", error),
+                BuildError::InstallError(path, error) =>
+                    format!("Unable to install {} into sysroot ({})", path, error),
+                BuildError::NoArtifact(path) =>
+                    format!("Cargo did not report an executable for {}", path),
+                BuildError::MissingArtifact(path, binary, found) => format!(
+                    "Cargo did not build the binary \"{}\" for {} (found {})",
+                    binary,
+                    path,
+                    match found.is_empty() {
+                        true => "none".to_owned(),
+                        false => found.join(", "),
+                    }
+                ),
+                BuildError::AmbiguousArtifact(path, found) => format!(
+                    "Cargo built several binaries for {} ({}), set \"binary\" in {} to choose one",
+                    path,
+                    found.join(", "),
+                    crate::config::CONFIG_FILE
+                ),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{job::Job, merge, replace_tree, stage, start_sysroot, BuildError, Sysroot};
+    use crate::{
+        arguments::Verbosity,
+        config::{Arch, Component, ComponentKind, Config},
+        manifest::Manifest,
+    };
+    use std::{
+        path::{Path, PathBuf},
+        sync::{atomic::AtomicBool, Mutex},
+    };
+
+    // Installed files are neither split nor validated, so any contents will do
+    fn config() -> Config {
+        Config::parse(
+            "[symbols]\nsplit = false\n\n[validate]\nenabled = false\n",
+            Arch::X86_64,
+        )
+        .unwrap()
+    }
+
+    // A next sysroot, its symbols and its manifest in a directory of their own
+    fn sysroot(name: &str) -> (PathBuf, Sysroot) {
+        let directory =
+            std::env::temp_dir().join(format!("losb-build-{}-{}", name, std::process::id()));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let sysroot = Sysroot {
+            path: directory.join("next"),
+            symbols: directory.join("next-symbols"),
+            staging: directory.join("staging"),
+            manifest: Mutex::new(Manifest::load_from(directory.join("manifest")).unwrap()),
+            changed: AtomicBool::new(false),
+        };
+        (directory, sysroot)
+    }
+
+    // Stages files as a component would install them, then moves them into the sysroot
+    fn install(sysroot: &Sysroot, name: &str, files: &[(&str, &str)]) -> Result<(), BuildError> {
+        let component = Component {
+            name: name.to_owned(),
+            path: ".".to_owned(),
+            depends_on: Vec::new(),
+            env: Vec::new(),
+            kind: ComponentKind::Brew,
+        };
+        let staging = stage(&component, sysroot)?;
+        for (file, contents) in files {
+            let path = staging.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let cancelled = AtomicBool::new(false);
+        let log = sysroot.staging.with_file_name(format!("{}.log", name));
+        let job = Job::new(false, Verbosity::Quiet, &log, &cancelled);
+        merge(&job, &config(), &component, sysroot, &staging)
+    }
+
+    fn files(sysroot: &Sysroot, component: &str) -> Vec<PathBuf> {
+        let manifest = sysroot.manifest.lock().unwrap();
+        manifest
+            .files(component)
+            .into_iter()
+            .map(Path::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn removes_stale_files() {
+        let (directory, sysroot) = sysroot("stale");
+        install(&sysroot, "libraries", &[("los/lib/libc.a", "libc")]).unwrap();
+        install(
+            &sysroot,
+            "programs",
+            &[("los/bin/shell", "shell"), ("los/bin/tools/edit", "edit")],
+        )
+        .unwrap();
+        assert_eq!(
+            files(&sysroot, "programs"),
+            [Path::new("los/bin/shell"), Path::new("los/bin/tools/edit")]
+        );
+
+        // The symbols split out of a removed file go with it
+        let symbols = sysroot.symbols.join("los/bin/tools/edit");
+        std::fs::create_dir_all(symbols.parent().unwrap()).unwrap();
+        std::fs::write(&symbols, "edit symbols").unwrap();
+
+        // A file no longer installed is removed along with the directories it leaves empty
+        install(&sysroot, "programs", &[("los/bin/shell", "new shell")]).unwrap();
+        assert_eq!(files(&sysroot, "programs"), [Path::new("los/bin/shell")]);
+        assert_eq!(
+            std::fs::read_to_string(sysroot.path.join("los/bin/shell")).unwrap(),
+            "new shell"
+        );
+        assert!(!sysroot.path.join("los/bin/tools").exists());
+        assert!(!sysroot.symbols.join("los/bin/tools").exists());
+        assert!(sysroot.path.join("los/bin").is_dir());
+
+        // Files another component owns are left alone
+        install(&sysroot, "programs", &[]).unwrap();
+        assert!(files(&sysroot, "programs").is_empty());
+        assert!(!sysroot.path.join("los/bin").exists());
+        assert_eq!(
+            std::fs::read_to_string(sysroot.path.join("los/lib/libc.a")).unwrap(),
+            "libc"
+        );
+        assert_eq!(files(&sysroot, "libraries"), [Path::new("los/lib/libc.a")]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn conflict() {
+        let (directory, sysroot) = sysroot("conflict");
+        install(
+            &sysroot,
+            "libraries",
+            &[("los/include/stdio.h", "libraries")],
+        )
+        .unwrap();
+
+        match install(
+            &sysroot,
+            "programs",
+            &[
+                ("los/bin/shell", "shell"),
+                ("los/include/stdio.h", "programs"),
+            ],
+        ) {
+            Err(BuildError::Conflict(path, first, second)) => {
+                assert_eq!(path, "los/include/stdio.h");
+                assert_eq!(first, "libraries");
+                assert_eq!(second, "programs");
+            }
+            Err(error) => panic!("expected a conflict, found {}", error),
+            Ok(()) => panic!("two components installed the same file"),
+        }
+
+        // Nothing of the component which conflicted is installed
+        assert_eq!(
+            std::fs::read_to_string(sysroot.path.join("los/include/stdio.h")).unwrap(),
+            "libraries"
+        );
+        assert!(!sysroot.path.join("los/bin/shell").exists());
+        assert!(files(&sysroot, "programs").is_empty());
+
+        // Reinstalling its own file isn't a conflict
+        install(&sysroot, "libraries", &[("los/include/stdio.h", "again")]).unwrap();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn replaces_sysroot() {
+        let (directory, next) = sysroot("replace");
+        let live = directory.join("sysroot");
+        let previous = directory.join("previous/sysroot");
+
+        // The first build starts from nothing
+        start_sysroot(&live, &next.path).unwrap();
+        assert!(next.path.is_dir());
+        install(
+            &next,
+            "programs",
+            &[("los/bin/shell", "old shell"), ("los/bin/edit", "old edit")],
+        )
+        .unwrap();
+        replace_tree(&next.path, &live, &previous).unwrap();
+        assert!(!next.path.exists());
+        assert!(!previous.exists());
+        assert_eq!(read(&live.join("los/bin/shell")), "old shell");
+
+        // The next build starts as a copy of the sysroot, and replaces it once finished
+        start_sysroot(&live, &next.path).unwrap();
+        assert_eq!(read(&next.path.join("los/bin/edit")), "old edit");
+        install(&next, "programs", &[("los/bin/shell", "new shell")]).unwrap();
+        assert_eq!(read(&live.join("los/bin/shell")), "old shell");
+        assert!(live.join("los/bin/edit").exists());
+        replace_tree(&next.path, &live, &previous).unwrap();
+
+        assert!(!next.path.exists());
+        assert_eq!(read(&live.join("los/bin/shell")), "new shell");
+        assert!(!live.join("los/bin/edit").exists());
+        assert_eq!(read(&previous.join("los/bin/shell")), "old shell");
+        assert_eq!(read(&previous.join("los/bin/edit")), "old edit");
+
+        // Only the one sysroot before is kept
+        start_sysroot(&live, &next.path).unwrap();
+        install(&next, "programs", &[("los/bin/shell", "newest shell")]).unwrap();
+        replace_tree(&next.path, &live, &previous).unwrap();
+        assert_eq!(read(&live.join("los/bin/shell")), "newest shell");
+        assert_eq!(read(&previous.join("los/bin/shell")), "new shell");
+        assert!(!previous.join("los/bin/edit").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn failed_build_leaves_sysroot() {
+        let (directory, next) = sysroot("failed");
+        let live = directory.join("sysroot");
+        let previous = directory.join("previous/sysroot");
+
+        start_sysroot(&live, &next.path).unwrap();
+        install(&next, "libraries", &[("los/lib/libc.a", "libc")]).unwrap();
+        install(
+            &next,
+            "programs",
+            &[("los/bin/shell", "shell"), ("los/bin/edit", "edit")],
+        )
+        .unwrap();
+        replace_tree(&next.path, &live, &previous).unwrap();
+
+        // One component replaces and removes files in the next sysroot, then another fails
+        start_sysroot(&live, &next.path).unwrap();
+        install(&next, "libraries", &[("los/lib/libc.a", "broken libc")]).unwrap();
+        install(&next, "programs", &[("los/lib/libc.a", "shell")]).unwrap_err();
+        install(&next, "programs", &[("los/bin/shell", "broken shell")]).unwrap();
+        assert_eq!(read(&next.path.join("los/lib/libc.a")), "broken libc");
+        assert!(!next.path.join("los/bin/edit").exists());
+
+        // The files linked into the next sysroot were replaced, never written into
+        assert_eq!(read(&live.join("los/lib/libc.a")), "libc");
+        assert_eq!(read(&live.join("los/bin/shell")), "shell");
+        assert_eq!(read(&live.join("los/bin/edit")), "edit");
+        assert!(!previous.exists());
+
+        // The build after starts again from the sysroot
+        start_sysroot(&live, &next.path).unwrap();
+        assert_eq!(read(&next.path.join("los/lib/libc.a")), "libc");
+        assert_eq!(read(&next.path.join("los/bin/edit")), "edit");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
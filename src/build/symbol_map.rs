@@ -0,0 +1,144 @@
+use super::{demangle::demangle, BuildError};
+use crate::elf;
+use std::{fs::read, path::Path};
+
+// Bumped whenever the layout changes
+const VERSION: u32 = 1;
+
+// Writes a map of the kernel's functions for it to symbolize its own backtraces. Both paths are
+// relative to the sysroot. Everything is little-endian:
+//   "LSYM", the version, the number of functions and the size of the names, each as a u32
+//   each function sorted by address, as its address (u64), size (u32) and name offset (u32)
+//   the names, each followed by a null byte
+pub fn write_symbol_map(sysroot: &Path, kernel: &Path, map: &Path) -> Result<(), BuildError> {
+    let error = |error| BuildError::SymbolMap(kernel.to_string_lossy().into_owned(), error);
+
+    let data = read(sysroot.join(kernel)).map_err(|io_error| error(Some(io_error)))?;
+    let symbols = elf::symbols(&data).ok_or_else(|| error(None))?;
+
+    // Of several names for one address, such as aliases, the first in order is kept
+    let mut functions: Vec<(u64, u64, String)> = symbols
+        .into_iter()
+        .filter(|symbol| symbol.kind == elf::SYMBOL_FUNCTION && symbol.address != 0)
+        .map(|symbol| (symbol.address, symbol.size, demangle(&symbol.name)))
+        .collect();
+    functions.sort();
+    functions.dedup_by_key(|(address, _, _)| *address);
+
+    let mut entries = Vec::with_capacity(functions.len() * 16);
+    let mut names = Vec::new();
+    for (address, size, name) in &functions {
+        entries.extend_from_slice(&address.to_le_bytes());
+        entries.extend_from_slice(&((*size).min(u32::MAX as u64) as u32).to_le_bytes());
+        entries.extend_from_slice(&(names.len() as u32).to_le_bytes());
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+
+    let mut map_data = b"LSYM".to_vec();
+    map_data.extend_from_slice(&VERSION.to_le_bytes());
+    map_data.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    map_data.extend_from_slice(&(names.len() as u32).to_le_bytes());
+    map_data.append(&mut entries);
+    map_data.append(&mut names);
+
+    let path = sysroot.join(map);
+    let result = match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|_| std::fs::write(&path, map_data));
+    result.map_err(|io_error| error(Some(io_error)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_symbol_map;
+    use crate::elf::{tests::executable, SYMBOL_FUNCTION};
+    use std::path::Path;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn round_trip() {
+        let sysroot = std::env::temp_dir().join(format!("losb-symbols-{}", std::process::id()));
+        std::fs::create_dir_all(&sysroot).unwrap();
+        let kernel = executable(&[
+            (
+                "_ZN6kernel4main17h0123456789abcdefE",
+                SYMBOL_FUNCTION,
+                0x3000,
+                0x40,
+            ),
+            ("kmain", SYMBOL_FUNCTION, 0x1000, 0x20),
+            ("KERNEL_STACK", 1, 0x2000, 0x1000),
+            ("undefined", SYMBOL_FUNCTION, 0, 0),
+            ("_RNvCs1_6kernel5panic", SYMBOL_FUNCTION, 0x2800, 1 << 40),
+            ("kmain_alias", SYMBOL_FUNCTION, 0x1000, 0x20),
+        ]);
+        std::fs::write(sysroot.join("kernel.elf"), kernel).unwrap();
+
+        write_symbol_map(
+            &sysroot,
+            Path::new("kernel.elf"),
+            Path::new("los/kernel.sym"),
+        )
+        .unwrap();
+        let map = std::fs::read(sysroot.join("los/kernel.sym")).unwrap();
+        std::fs::remove_dir_all(&sysroot).ok();
+
+        // Functions only, by address, with one name for each address
+        let names = b"kmain\0kernel::panic\0kernel::main\0";
+        assert_eq!(&map[..4], b"LSYM");
+        assert_eq!(u32_at(&map, 4), 1);
+        assert_eq!(u32_at(&map, 8), 3);
+        assert_eq!(u32_at(&map, 12) as usize, names.len());
+        assert_eq!(map.len(), 16 + 3 * 16 + names.len());
+        assert_eq!(&map[16 + 3 * 16..], names);
+
+        let mut previous = 0;
+        for (index, (address, size, name)) in [
+            (0x1000, 0x20, "kmain"),
+            (0x2800, u32::MAX, "kernel::panic"),
+            (0x3000, 0x40, "kernel::main"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let entry = &map[16 + index * 16..16 + (index + 1) * 16];
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&entry[..8]);
+            let entry_address = u64::from_le_bytes(bytes);
+            assert_eq!(entry_address, *address);
+            assert!(entry_address > previous);
+            previous = entry_address;
+            assert_eq!(u32_at(entry, 8), *size);
+
+            let offset = 16 + 3 * 16 + u32_at(entry, 12) as usize;
+            let end = offset + map[offset..].iter().position(|byte| *byte == 0).unwrap();
+            assert_eq!(std::str::from_utf8(&map[offset..end]).unwrap(), *name);
+        }
+    }
+
+    #[test]
+    fn unreadable_kernel() {
+        let sysroot = std::env::temp_dir().join(format!("losb-no-symbols-{}", std::process::id()));
+        std::fs::create_dir_all(&sysroot).unwrap();
+        std::fs::write(sysroot.join("kernel.elf"), b"not an ELF file").unwrap();
+        assert!(
+            write_symbol_map(&sysroot, Path::new("kernel.elf"), Path::new("kernel.sym")).is_err()
+        );
+        assert!(
+            write_symbol_map(&sysroot, Path::new("missing.elf"), Path::new("kernel.sym")).is_err()
+        );
+        assert!(!sysroot.join("kernel.sym").exists());
+        std::fs::remove_dir_all(&sysroot).ok();
+    }
+}
@@ -0,0 +1,73 @@
+use super::{copy_tree, job::Job, BuildError};
+use crate::{config::Config, elf};
+use std::{
+    fs::{create_dir_all, read, File},
+    io::Read,
+    path::Path,
+    process::Command,
+};
+
+// Moves the debug information of an ELF file installed at path, relative to the sysroot, into
+// the symbols directory given. The stripped file is linked to its symbols with .gnu_debuglink.
+pub fn split_symbols(
+    job: &Job,
+    config: &Config,
+    sysroot: &Path,
+    symbols: &Path,
+    path: &Path,
+) -> Result<(), BuildError> {
+    if !config.symbols.split {
+        return Ok(());
+    }
+
+    let installed = sysroot.join(path);
+    match has_debug_info(&installed) {
+        Ok(true) => {}
+        Ok(false) => return Ok(()),
+        Err(error) => return Err(symbols_error(&installed, Some(error))),
+    }
+
+    // The symbols are a full copy of the file, so they can be loaded into a debugger alone. They
+    // may be linked to the current symbols, so are replaced rather than written into.
+    let symbols = symbols.join(path);
+    let result = match symbols.parent() {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|_| copy_tree(&installed, &symbols));
+    if let Err(error) = result {
+        return Err(symbols_error(&installed, Some(error)));
+    }
+
+    let mut debuglink = std::ffi::OsString::from("--add-gnu-debuglink=");
+    debuglink.push(&symbols);
+    let mut command = Command::new(&config.symbols.objcopy);
+    command.arg("--strip-debug").arg(debuglink).arg(&installed);
+
+    match job.run(&mut command, None) {
+        Ok(status) => match status.success() {
+            true => Ok(()),
+            false => Err(symbols_error(&installed, None)),
+        },
+        Err(error) => Err(symbols_error(&installed, Some(error))),
+    }
+}
+
+// Only the header is read from files which aren't ELF files
+fn has_debug_info(path: &Path) -> Result<bool, std::io::Error> {
+    let mut magic = [0; 4];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+        Err(error) => return Err(error),
+    }
+
+    match elf::is_elf(&magic) {
+        true => Ok(elf::has_debug_info(&read(path)?)),
+        false => Ok(false),
+    }
+}
+
+fn symbols_error(path: &Path, error: Option<std::io::Error>) -> BuildError {
+    BuildError::Symbols(path.to_string_lossy().into_owned(), error)
+}
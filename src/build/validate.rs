@@ -0,0 +1,220 @@
+use super::BuildError;
+use crate::{
+    config::{Arch, Config},
+    elf, pe,
+};
+use std::{fs::read, path::Path};
+
+// Why an installed file wouldn't run
+#[derive(Debug)]
+pub enum BinaryError {
+    NotElf,
+    NotElf64,
+    NotPe,
+    NotPe32Plus(u16),
+    NotEfiApplication(u16),
+    WrongMachine(Arch, u16),
+    NotExecutable(u16),
+    NoLoadableSegments,
+    BadEntryPoint(u64),
+    WrongOsAbi(u8, u8),
+}
+
+// Checks a file installed into the sysroot can run where it is going. UEFI applications must be
+// PE32+ files, the kernel an ELF64 executable, and any other ELF file must be for the OS.
+pub fn validate(
+    config: &Config,
+    relative: &Path,
+    file: &Path,
+    kernel: bool,
+) -> Result<(), BuildError> {
+    if !config.validate.enabled {
+        return Ok(());
+    }
+
+    let data = match read(file) {
+        Ok(data) => data,
+        Err(error) => {
+            return Err(BuildError::InstallError(
+                file.to_string_lossy().into_owned(),
+                error,
+            ))
+        }
+    };
+
+    let efi = relative
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("efi"))
+        .unwrap_or(false);
+    let result = match (efi, kernel) {
+        (true, _) => validate_efi(config.arch, &data),
+        (false, true) => validate_kernel(config.arch, &data),
+        (false, false) if elf::is_elf(&data) => validate_userland(config, &data),
+        (false, false) => Ok(()),
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildError::InvalidBinary(
+            relative.to_string_lossy().into_owned(),
+            error,
+        )),
+    }
+}
+
+fn validate_efi(arch: Arch, data: &[u8]) -> Result<(), BinaryError> {
+    let header = pe::parse_header(data).ok_or(BinaryError::NotPe)?;
+    if header.magic != pe::MAGIC_PE32_PLUS {
+        return Err(BinaryError::NotPe32Plus(header.magic));
+    }
+    if header.subsystem != pe::SUBSYSTEM_EFI_APPLICATION {
+        return Err(BinaryError::NotEfiApplication(header.subsystem));
+    }
+    if header.machine != arch.pe_machine() {
+        return Err(BinaryError::WrongMachine(arch, header.machine));
+    }
+    if header.entry == 0 {
+        return Err(BinaryError::BadEntryPoint(0));
+    }
+    Ok(())
+}
+
+fn validate_elf(arch: Arch, data: &[u8]) -> Result<elf::Header, BinaryError> {
+    let header = elf::parse_header(data).ok_or(BinaryError::NotElf)?;
+    if !header.wide {
+        return Err(BinaryError::NotElf64);
+    }
+    if header.machine != arch.elf_machine() {
+        return Err(BinaryError::WrongMachine(arch, header.machine));
+    }
+    Ok(header)
+}
+
+// The bootloader loads the kernel's segments and jumps to its entry point, which must be in one
+fn validate_kernel(arch: Arch, data: &[u8]) -> Result<(), BinaryError> {
+    let header = validate_elf(arch, data)?;
+    if header.kind != elf::TYPE_EXECUTABLE && header.kind != elf::TYPE_SHARED {
+        return Err(BinaryError::NotExecutable(header.kind));
+    }
+
+    let mut loadable = header
+        .segments
+        .iter()
+        .filter(|segment| segment.kind == elf::SEGMENT_LOAD)
+        .peekable();
+    if loadable.peek().is_none() {
+        return Err(BinaryError::NoLoadableSegments);
+    }
+
+    let entry_mapped = loadable.any(|segment| {
+        segment.flags & elf::SEGMENT_EXECUTE != 0
+            && header.entry >= segment.address
+            && header.entry - segment.address < segment.size
+    });
+    match entry_mapped {
+        true => Ok(()),
+        false => Err(BinaryError::BadEntryPoint(header.entry)),
+    }
+}
+
+fn validate_userland(config: &Config, data: &[u8]) -> Result<(), BinaryError> {
+    let header = validate_elf(config.arch, data)?;
+    match header.os_abi == config.validate.os_abi {
+        true => Ok(()),
+        false => Err(BinaryError::WrongOsAbi(
+            config.validate.os_abi,
+            header.os_abi,
+        )),
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+impl std::fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                BinaryError::NotElf => "isn't an ELF file".to_owned(),
+                BinaryError::NotElf64 => "is a 32-bit ELF file rather than 64-bit".to_owned(),
+                BinaryError::NotPe => "isn't a PE file, as UEFI applications must be".to_owned(),
+                BinaryError::NotPe32Plus(magic) => format!(
+                    "is a PE file with optional header magic {:#x} rather than PE32+ ({:#x})",
+                    magic,
+                    pe::MAGIC_PE32_PLUS
+                ),
+                BinaryError::NotEfiApplication(subsystem) => format!(
+                    "has subsystem {} rather than EFI application ({})",
+                    subsystem,
+                    pe::SUBSYSTEM_EFI_APPLICATION
+                ),
+                BinaryError::WrongMachine(arch, machine) =>
+                    format!("is for machine {:#x} rather than {}", machine, arch),
+                BinaryError::NotExecutable(kind) =>
+                    format!("is an ELF file of type {} rather than an executable", kind),
+                BinaryError::NoLoadableSegments => "has no loadable segments".to_owned(),
+                BinaryError::BadEntryPoint(0) => "has no entry point".to_owned(),
+                BinaryError::BadEntryPoint(entry) => format!(
+                    "has entry point {:#x} outside of its executable segments",
+                    entry
+                ),
+                BinaryError::WrongOsAbi(expected, found) =>
+                    format!("has OS ABI {} rather than {}", found, expected),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_efi, BinaryError};
+    use crate::{
+        config::{Arch, ARCHES},
+        pe::{tests::application, SUBSYSTEM_EFI_APPLICATION},
+    };
+
+    #[test]
+    fn efi_applications() {
+        for arch in ARCHES {
+            let data = application(arch.pe_machine(), SUBSYSTEM_EFI_APPLICATION);
+            assert!(validate_efi(*arch, &data).is_ok());
+        }
+
+        // An EFI boot service driver, or a Windows console program
+        for subsystem in [11, 3] {
+            let data = application(0x8664, subsystem);
+            assert!(matches!(
+                validate_efi(Arch::X86_64, &data),
+                Err(BinaryError::NotEfiApplication(found)) if found == subsystem
+            ));
+        }
+
+        let data = application(Arch::AArch64.pe_machine(), SUBSYSTEM_EFI_APPLICATION);
+        assert!(matches!(
+            validate_efi(Arch::X86_64, &data),
+            Err(BinaryError::WrongMachine(Arch::X86_64, 0xAA64))
+        ));
+
+        // PE32 rather than PE32+
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        data[0x98..0x9A].copy_from_slice(&0x10Bu16.to_le_bytes());
+        assert!(matches!(
+            validate_efi(Arch::X86_64, &data),
+            Err(BinaryError::NotPe32Plus(0x10B))
+        ));
+
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        data[0x98 + 16..0x98 + 20].copy_from_slice(&[0; 4]);
+        assert!(matches!(
+            validate_efi(Arch::X86_64, &data),
+            Err(BinaryError::BadEntryPoint(0))
+        ));
+
+        let data = crate::elf::tests::executable(&[]);
+        assert!(matches!(
+            validate_efi(Arch::X86_64, &data),
+            Err(BinaryError::NotPe)
+        ));
+    }
+}
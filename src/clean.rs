@@ -1,3 +1,4 @@
+use crate::config::{ComponentKind, Config, ARCHES, STATE_DIR};
 use std::{fs::remove_dir_all, path::Path, process::Command};
 
 #[derive(Debug)]
@@ -30,38 +31,116 @@ fn clean_brew(path: &str) -> Result<(), CleanError> {
     }
 }
 
-pub fn clean_user() -> Result<(), CleanError> {
-    // Remove sysroot
-    println!("    \x1B[32;1mCleaning\x1B[0m sysroot . . .");
-    if Path::new("./sysroot").exists() {
-        match remove_dir_all("./sysroot") {
-            Ok(_) => {}
-            Err(error) => return Err(CleanError("./sysroot".to_owned(), Some(error))),
-        };
+fn clean_custom(path: &str, command: &[String]) -> Result<(), CleanError> {
+    let (program, arguments) = match command.split_first() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+
+    let mut command = Command::new(program);
+    command.args(arguments);
+    command.current_dir(path);
+
+    match command.status() {
+        Ok(status) => match status.success() {
+            true => Ok(()),
+            false => Err(CleanError(path.to_owned(), None)),
+        },
+        Err(error) => Err(CleanError(path.to_owned(), Some(error))),
     }
+}
 
-    // Clean libraries
-    println!("    \x1B[32;1mCleaning\x1B[0m libraries . . .");
-    clean_brew("./libraries")?;
+pub fn clean_user(config: &Config) -> Result<(), CleanError> {
+    // Cleaning named components leaves the sysroot alone
+    match config.selected.is_empty() {
+        true => clean_sysroots(config)?,
+        false => {
+            for name in &config.selected {
+                match crate::fingerprint::forget(name) {
+                    Ok(()) => {}
+                    Err(error) => return Err(CleanError(STATE_DIR.to_owned(), Some(error))),
+                }
+            }
+        }
+    }
 
-    // Clean programs
-    println!("    \x1B[32;1mCleaning\x1B[0m programs . . .");
-    clean_brew("./programs")?;
+    // Clean userspace, which brew builds
+    for component in &config.components {
+        if let ComponentKind::Brew = component.kind {
+            if config.is_selected(component) {
+                println!("    \x1B[32;1mCleaning\x1B[0m {} . . .", component.name);
+                clean_brew(&component.path)?;
+            }
+        }
+    }
 
     Ok(())
 }
 
-pub fn clean() -> Result<(), CleanError> {
-    // Clean bootloader
-    println!("    \x1B[32;1mCleaning\x1B[0m bootloader . . .");
-    clean_cargo("./bootloader")?;
+fn clean_sysroots(config: &Config) -> Result<(), CleanError> {
+    // Remove the sysroot and symbols of every profile and architecture
+    println!("    \x1B[32;1mCleaning\x1B[0m sysroot . . .");
+    let variants = config
+        .profiles
+        .iter()
+        .flat_map(|profile| ARCHES.iter().map(move |arch| (profile, *arch)));
+    for (profile, arch) in variants {
+        let directories = [
+            config.variant_sysroot(profile, arch),
+            config.variant_symbols(profile, arch),
+        ];
+        for directory in directories.iter().filter(|directory| directory.exists()) {
+            remove_directory(directory)?;
+        }
+    }
+
+    // Along with the sysroots and symbols a build is installing into or has replaced
+    for directory in ["next", "previous", "next-symbols", "previous-symbols"] {
+        let directory = Path::new(STATE_DIR).join(directory);
+        if directory.exists() {
+            remove_directory(&directory)?;
+        }
+    }
+
+    // Forget what was built and installed into them
+    match crate::fingerprint::clear(None).and_then(|_| crate::manifest::clear(None)) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(CleanError(STATE_DIR.to_owned(), Some(error))),
+    }
+}
+
+fn remove_directory(directory: &Path) -> Result<(), CleanError> {
+    match remove_dir_all(directory) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(CleanError(
+            directory.to_string_lossy().into_owned(),
+            Some(error),
+        )),
+    }
+}
 
-    // Clean kernel
-    println!("    \x1B[32;1mCleaning\x1B[0m kernel . . .");
-    clean_cargo("./kernel")?;
+pub fn clean(config: &Config) -> Result<(), CleanError> {
+    // Clean everything outside of userspace
+    for component in config
+        .components
+        .iter()
+        .filter(|component| config.is_selected(component))
+    {
+        match &component.kind {
+            ComponentKind::Cargo(_) => {
+                println!("    \x1B[32;1mCleaning\x1B[0m {} . . .", component.name);
+                clean_cargo(&component.path)?;
+            }
+            ComponentKind::Custom(custom) if !custom.clean.is_empty() => {
+                println!("    \x1B[32;1mCleaning\x1B[0m {} . . .", component.name);
+                clean_custom(&component.path, &custom.clean)?;
+            }
+            ComponentKind::Custom(_) | ComponentKind::Brew => {}
+        }
+    }
 
     // Clean user
-    clean_user()
+    clean_user(config)
 }
 
 impl std::error::Error for CleanError {}
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Command {
     Build,
     BuildImage,
@@ -6,10 +6,17 @@ pub enum Command {
     Clean,
     CleanUser,
     Debug,
+    Doctor,
+    ExportOVA,
     Help,
+    NewLibrary,
+    NewProgram,
     Run,
+    Status,
+    Timings,
     VBox,
     Version,
+    Watch,
 }
 
 #[derive(Debug)]
@@ -24,10 +31,17 @@ impl Command {
             "clean" => Ok(Command::Clean),
             "clean-user" => Ok(Command::CleanUser),
             "debug" => Ok(Command::Debug),
+            "doctor" => Ok(Command::Doctor),
+            "export-ova" => Ok(Command::ExportOVA),
             "help" => Ok(Command::Help),
+            "new-library" => Ok(Command::NewLibrary),
+            "new-program" => Ok(Command::NewProgram),
             "run" => Ok(Command::Run),
+            "status" => Ok(Command::Status),
+            "timings" => Ok(Command::Timings),
             "vbox" => Ok(Command::VBox),
             "version" => Ok(Command::Version),
+            "watch" => Ok(Command::Watch),
             _ => Err(InvalidCommand(command.to_string())),
         }
     }
@@ -45,10 +59,17 @@ impl std::fmt::Display for Command {
                 Command::Clean => "clean",
                 Command::CleanUser => "clean-user",
                 Command::Debug => "debug",
+                Command::Doctor => "doctor",
+                Command::ExportOVA => "export-ova",
                 Command::Help => "help",
+                Command::NewLibrary => "new-library",
+                Command::NewProgram => "new-program",
                 Command::Run => "run",
+                Command::Status => "status",
+                Command::Timings => "timings",
                 Command::VBox => "vbox",
                 Command::Version => "version",
+                Command::Watch => "watch",
             }
         )
     }
@@ -0,0 +1,194 @@
+// A CPU architecture the operating system can be built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    AArch64,
+    RiscV64,
+}
+
+pub const DEFAULT_ARCH: Arch = Arch::X86_64;
+
+pub const ARCHES: &[Arch] = &[Arch::X86_64, Arch::AArch64, Arch::RiscV64];
+
+impl Arch {
+    pub fn parse(name: &str) -> Option<Self> {
+        ARCHES.iter().find(|arch| arch.name() == name).cloned()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::AArch64 => "aarch64",
+            Arch::RiscV64 => "riscv64",
+        }
+    }
+
+    pub fn bootloader_target(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64-unknown-uefi",
+            Arch::AArch64 => "aarch64-unknown-uefi",
+            Arch::RiscV64 => "riscv64gc-unknown-uefi",
+        }
+    }
+
+    pub fn kernel_target(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64-los",
+            Arch::AArch64 => "aarch64-los",
+            Arch::RiscV64 => "riscv64gc-los",
+        }
+    }
+
+    // The path firmware loads from removable media, relative to the root of the image
+    pub fn boot_file(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "EFI/BOOT/BOOTX64.EFI",
+            Arch::AArch64 => "EFI/BOOT/BOOTAA64.EFI",
+            Arch::RiscV64 => "EFI/BOOT/BOOTRISCV64.EFI",
+        }
+    }
+
+    // The e_machine of ELF files built for the architecture
+    pub fn elf_machine(&self) -> u16 {
+        match self {
+            Arch::X86_64 => 62,
+            Arch::AArch64 => 183,
+            Arch::RiscV64 => 243,
+        }
+    }
+
+    // The machine of PE files, such as the bootloader, built for the architecture
+    pub fn pe_machine(&self) -> u16 {
+        match self {
+            Arch::X86_64 => 0x8664,
+            Arch::AArch64 => 0xAA64,
+            Arch::RiscV64 => 0x5064,
+        }
+    }
+
+    pub fn emulator(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "qemu-system-x86_64",
+            Arch::AArch64 => "qemu-system-aarch64",
+            Arch::RiscV64 => "qemu-system-riscv64",
+        }
+    }
+
+    // QEMU has no default machine for aarch64 or riscv64, x86_64 keeps QEMU's own
+    pub fn machine(&self) -> Option<&'static str> {
+        match self {
+            Arch::X86_64 => None,
+            Arch::AArch64 | Arch::RiscV64 => Some("virt"),
+        }
+    }
+
+    // The virt machine defaults to a 32-bit CPU on aarch64
+    pub fn cpu(&self) -> Option<&'static str> {
+        match self {
+            Arch::AArch64 => Some("cortex-a72"),
+            Arch::X86_64 | Arch::RiscV64 => None,
+        }
+    }
+
+    pub fn firmware(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "OVMF.fd",
+            Arch::AArch64 => "QEMU_EFI.fd",
+            Arch::RiscV64 => "RISCV_VIRT_CODE.fd",
+        }
+    }
+}
+
+impl std::fmt::Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arch, ARCHES, DEFAULT_ARCH};
+    use crate::config::{ComponentKind, Config};
+
+    #[test]
+    fn names() {
+        for arch in ARCHES {
+            assert_eq!(Arch::parse(arch.name()), Some(*arch));
+            assert_eq!(arch.to_string(), arch.name());
+        }
+        assert_eq!(Arch::parse("x86-64"), None);
+        assert_eq!(Arch::parse("X86_64"), None);
+        assert_eq!(DEFAULT_ARCH, Arch::X86_64);
+    }
+
+    #[test]
+    fn settings() {
+        // Triples, boot file, ELF and PE machines, QEMU binary, machine, CPU and firmware
+        let expected = [
+            (
+                Arch::X86_64,
+                "x86_64-unknown-uefi",
+                "x86_64-los",
+                "EFI/BOOT/BOOTX64.EFI",
+                62,
+                0x8664,
+                "qemu-system-x86_64",
+                None,
+                None,
+                "OVMF.fd",
+            ),
+            (
+                Arch::AArch64,
+                "aarch64-unknown-uefi",
+                "aarch64-los",
+                "EFI/BOOT/BOOTAA64.EFI",
+                183,
+                0xAA64,
+                "qemu-system-aarch64",
+                Some("virt"),
+                Some("cortex-a72"),
+                "QEMU_EFI.fd",
+            ),
+            (
+                Arch::RiscV64,
+                "riscv64gc-unknown-uefi",
+                "riscv64gc-los",
+                "EFI/BOOT/BOOTRISCV64.EFI",
+                243,
+                0x5064,
+                "qemu-system-riscv64",
+                Some("virt"),
+                None,
+                "RISCV_VIRT_CODE.fd",
+            ),
+        ];
+        assert_eq!(expected.len(), ARCHES.len());
+
+        for (arch, bootloader, kernel, boot_file, elf, pe, emulator, machine, cpu, firmware) in
+            expected
+        {
+            assert_eq!(arch.bootloader_target(), bootloader);
+            assert_eq!(arch.kernel_target(), kernel);
+            assert_eq!(arch.boot_file(), boot_file);
+            assert_eq!(arch.elf_machine(), elf);
+            assert_eq!(arch.pe_machine(), pe);
+            assert_eq!(arch.emulator(), emulator);
+            assert_eq!(arch.machine(), machine);
+            assert_eq!(arch.cpu(), cpu);
+            assert_eq!(arch.firmware(), firmware);
+
+            // And they are what the configuration defaults to
+            let config = Config::parse("", arch).unwrap();
+            let cargo = |name| match &config.component(name).unwrap().kind {
+                ComponentKind::Cargo(cargo) => cargo.clone(),
+                _ => panic!("{} isn't built by cargo", name),
+            };
+            assert_eq!(cargo("bootloader").target.as_deref(), Some(bootloader));
+            assert_eq!(cargo("bootloader").install.as_deref(), Some(boot_file));
+            assert_eq!(cargo("kernel").target.as_deref(), Some(kernel));
+            assert_eq!(config.emulator.program, emulator);
+            assert_eq!(config.emulator.machine.as_deref(), machine);
+            assert_eq!(config.emulator.firmware, firmware);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use super::{section::Section, Arch, ConfigError};
+
+// A project built by losb and installed into the sysroot
+pub struct Component {
+    pub name: String,
+    pub path: String,
+    pub depends_on: Vec<String>,
+    pub env: Vec<(String, String)>, // Set for every command run to build it
+    pub kind: ComponentKind,
+}
+
+pub enum ComponentKind {
+    Cargo(CargoComponent),
+    Brew,
+    Custom(CustomComponent),
+}
+
+#[derive(Clone)]
+pub struct CargoComponent {
+    pub target: Option<String>,  // Passed to cargo as --target
+    pub profile: Option<String>, // The cargo profile, instead of the selected configuration's
+    pub binary: Option<String>,  // The cargo binary to install, needed when a crate has several
+    pub install: Option<String>, // Relative to the sysroot
+    pub features: Vec<String>,
+    pub default_features: bool,
+}
+
+pub struct CustomComponent {
+    pub build: Vec<String>,
+    pub clean: Vec<String>,
+    pub install: Vec<(String, String)>, // Relative to the component and to the sysroot
+}
+
+const KINDS: &[&str] = &["cargo", "brew", "custom"];
+
+// The components built when losb.toml doesn't change them
+const BUILT_IN: &[&str] = &["bootloader", "kernel", "libraries", "programs"];
+
+impl Component {
+    // The built in components followed by those declared in [components], ordered so every
+    // component comes after its dependencies
+    pub fn parse_all(section: Section, arch: Arch) -> Result<Vec<Self>, ConfigError> {
+        let mut names: Vec<&str> = BUILT_IN.to_vec();
+        for name in section.keys() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        let mut components = Vec::with_capacity(names.len());
+        for name in names {
+            components.push(Component::parse(&section, name, arch)?);
+        }
+
+        // Every dependency must be declared
+        for component in &components {
+            for dependency in &component.depends_on {
+                if !components
+                    .iter()
+                    .any(|component| &component.name == dependency)
+                {
+                    let component_section = section.section(&component.name)?;
+                    return Err(ConfigError::InvalidValue(
+                        component_section.name().to_owned(),
+                        "depends_on".to_owned(),
+                        component_section.line("depends_on"),
+                        format!("unknown component \"{}\"", dependency),
+                    ));
+                }
+            }
+        }
+
+        sort(components)
+    }
+
+    fn parse(section: &Section, name: &str, arch: Arch) -> Result<Self, ConfigError> {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ConfigError::InvalidValue(
+                section.name().to_owned(),
+                name.to_owned(),
+                section.line(name),
+                "component names may only contain letters, digits, '-' and '_'".to_owned(),
+            ));
+        }
+
+        let component_section = section.section(name)?;
+        let (kind, path, depends_on) = match name {
+            "bootloader" => ("cargo", super::BOOTLOADER_DIR, &[][..]),
+            "kernel" => ("cargo", super::KERNEL_DIR, &[][..]),
+            "libraries" => ("brew", super::LIBRARIES_DIR, &[][..]),
+            "programs" => ("brew", super::PROGRAMS_DIR, &["libraries"][..]),
+            _ => ("", "", &[][..]),
+        };
+        let path = match path.is_empty() {
+            true => format!("./{}", name),
+            false => path.to_owned(),
+        };
+
+        let kind = component_section.string("kind", kind)?;
+        let kind = match kind.as_str() {
+            "cargo" => {
+                component_section.check_keys(&[
+                    "kind",
+                    "path",
+                    "depends_on",
+                    "env",
+                    "target",
+                    "binary",
+                    "install",
+                    "features",
+                    "default_features",
+                ])?;
+
+                let (target, install) = match name {
+                    "bootloader" => (Some(arch.bootloader_target()), Some(arch.boot_file())),
+                    "kernel" => (Some(arch.kernel_target()), Some(super::KERNEL_INSTALL)),
+                    _ => (None, None),
+                };
+
+                ComponentKind::Cargo(CargoComponent {
+                    target: component_section
+                        .optional_string("target")?
+                        .or_else(|| target.map(str::to_owned)),
+                    profile: None,
+                    binary: component_section.optional_string("binary")?,
+                    install: component_section
+                        .optional_string("install")?
+                        .or_else(|| install.map(str::to_owned)),
+                    features: component_section.strings("features", &[])?,
+                    default_features: component_section.boolean("default_features", true)?,
+                })
+            }
+            "brew" => {
+                component_section.check_keys(&["kind", "path", "depends_on", "env"])?;
+                ComponentKind::Brew
+            }
+            "custom" => {
+                component_section.check_keys(&[
+                    "kind",
+                    "path",
+                    "depends_on",
+                    "env",
+                    "build",
+                    "clean",
+                    "install",
+                ])?;
+
+                let install_section = component_section.section("install")?;
+                let mut install = Vec::new();
+                for from in install_section.keys() {
+                    install.push((from.to_owned(), install_section.string(from, "")?));
+                }
+
+                ComponentKind::Custom(CustomComponent {
+                    build: component_section.strings("build", &[])?,
+                    clean: component_section.strings("clean", &[])?,
+                    install,
+                })
+            }
+            _ => {
+                return Err(ConfigError::InvalidValue(
+                    component_section.name().to_owned(),
+                    "kind".to_owned(),
+                    match component_section.line("kind") {
+                        0 => section.line(name),
+                        line => line,
+                    },
+                    format!("expected one of {}", KINDS.join(", ")),
+                ))
+            }
+        };
+
+        let env_section = component_section.section("env")?;
+        let mut env = Vec::new();
+        for key in env_section.keys() {
+            env.push((key.to_owned(), env_section.string(key, "")?));
+        }
+
+        Ok(Component {
+            name: name.to_owned(),
+            path: component_section.string("path", &path)?,
+            depends_on: component_section.strings("depends_on", depends_on)?,
+            env,
+            kind,
+        })
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            ComponentKind::Cargo(_) => "cargo",
+            ComponentKind::Brew => "brew",
+            ComponentKind::Custom(_) => "custom",
+        }
+    }
+}
+
+// Orders components so dependencies come first, otherwise keeping the declared order
+fn sort(mut remaining: Vec<Component>) -> Result<Vec<Component>, ConfigError> {
+    let mut sorted: Vec<Component> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|component| {
+            component
+                .depends_on
+                .iter()
+                .all(|dependency| sorted.iter().any(|done| &done.name == dependency))
+        });
+
+        match ready {
+            Some(index) => sorted.push(remaining.remove(index)),
+            // Every component left depends on another left, so following them finds a cycle
+            None => {
+                let mut path = vec![0];
+                loop {
+                    let component = &remaining[*path.last().unwrap()];
+                    let next = remaining
+                        .iter()
+                        .position(|other| component.depends_on.contains(&other.name))
+                        .unwrap();
+                    if let Some(start) = path.iter().position(|index| *index == next) {
+                        return Err(ConfigError::DependencyCycle(
+                            path[start..]
+                                .iter()
+                                .map(|index| remaining[*index].name.clone())
+                                .collect(),
+                        ));
+                    }
+                    path.push(next);
+                }
+            }
+        }
+    }
+
+    Ok(sorted)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Arch, Config, ConfigError};
+
+    fn order(source: &str) -> Vec<String> {
+        Config::parse(source, Arch::X86_64)
+            .unwrap()
+            .components
+            .iter()
+            .map(|component| component.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        assert_eq!(order(""), ["bootloader", "kernel", "libraries", "programs"]);
+
+        let source = r#"
+[components.kernel]
+depends_on = ["tools"]
+
+[components.docs]
+kind = "custom"
+depends_on = ["programs", "tools"]
+
+[components.tools]
+kind = "custom"
+
+[components.tests]
+kind = "custom"
+"#;
+        assert_eq!(
+            order(source),
+            [
+                "bootloader",
+                "libraries",
+                "programs",
+                "tools",
+                "kernel",
+                "docs",
+                "tests"
+            ]
+        );
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let source = r#"
+[components.d]
+kind = "custom"
+depends_on = ["a"]
+
+[components.a]
+kind = "custom"
+depends_on = ["b"]
+
+[components.b]
+kind = "custom"
+depends_on = ["libraries", "c"]
+
+[components.c]
+kind = "custom"
+depends_on = ["a"]
+"#;
+        let error = Config::parse(source, Arch::X86_64).err().unwrap();
+        assert!(matches!(&error, ConfigError::DependencyCycle(cycle) if cycle == &["a", "b", "c"]));
+        assert_eq!(
+            error.to_string(),
+            "Components in ./losb.toml depend on each other (a -> b -> c -> a)"
+        );
+
+        let source = "[components.kernel]\ndepends_on = [\"kernel\"]\n";
+        assert!(matches!(
+            Config::parse(source, Arch::X86_64),
+            Err(ConfigError::DependencyCycle(cycle)) if cycle == ["kernel"]
+        ));
+    }
+
+    #[test]
+    fn unknown_dependency() {
+        let source = "[components.tools]\nkind = \"custom\"\n\n\ndepends_on = [\"missing\"]\n";
+        match Config::parse(source, Arch::X86_64) {
+            Err(ConfigError::InvalidValue(section, key, line, message)) => {
+                assert_eq!(section, "components.tools");
+                assert_eq!(key, "depends_on");
+                assert_eq!(line, 5);
+                assert_eq!(message, "unknown component \"missing\"");
+            }
+            _ => panic!("expected an unknown dependency"),
+        }
+    }
+}
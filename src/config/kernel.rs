@@ -0,0 +1,273 @@
+use super::{section::Section, Boot, Component, ComponentKind, ConfigError, Symbols};
+use std::path::Path;
+
+// Another build of the kernel, installed beside it and offered as its own boot entry
+pub struct KernelVariant {
+    pub name: String,
+    pub component: String,  // The component building it
+    pub install: String,    // Relative to the sysroot
+    pub symbol_map: String, // Relative to the sysroot, empty for nowhere
+    pub title: String,
+    pub cmdline: String,
+}
+
+impl KernelVariant {
+    // Adds a component building each variant declared in [kernels], copied from the kernel
+    // component with the variant's settings applied
+    pub fn parse_all(
+        section: Section,
+        components: &mut Vec<Component>,
+        boot: &Boot,
+        symbols: &Symbols,
+    ) -> Result<Vec<Self>, ConfigError> {
+        let mut variants = Vec::new();
+        for name in section.keys() {
+            let invalid = |message: String| {
+                ConfigError::InvalidValue(
+                    section.name().to_owned(),
+                    name.to_owned(),
+                    section.line(name),
+                    message,
+                )
+            };
+
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(invalid(
+                    "kernel names may only contain letters, digits, '-' and '_'".to_owned(),
+                ));
+            }
+
+            let component_name = format!("kernel-{}", name);
+            if components
+                .iter()
+                .any(|component| component.name == component_name)
+            {
+                return Err(invalid(format!(
+                    "the component \"{}\" already exists",
+                    component_name
+                )));
+            }
+
+            let kernel = components
+                .iter()
+                .find(|component| component.name == "kernel")
+                .unwrap();
+            let mut cargo = match &kernel.kind {
+                ComponentKind::Cargo(cargo) if cargo.install.is_some() => cargo.clone(),
+                _ => {
+                    return Err(invalid(
+                        "variants need the kernel to be built by cargo and installed".to_owned(),
+                    ))
+                }
+            };
+
+            let variant_section = section.section(name)?;
+            variant_section.check_keys(&[
+                "profile",
+                "features",
+                "default_features",
+                "title",
+                "cmdline",
+            ])?;
+
+            let install = suffixed(cargo.install.as_deref().unwrap(), name);
+            cargo.install = Some(install.clone());
+            cargo.profile = variant_section.optional_string("profile")?;
+            let features: Vec<&str> = cargo.features.iter().map(String::as_str).collect();
+            cargo.features = variant_section.strings("features", &features)?;
+            cargo.default_features =
+                variant_section.boolean("default_features", cargo.default_features)?;
+
+            // Each variant builds into its own subdirectory of the kernel's target directory so
+            // they don't replace each other
+            let mut env = kernel.env.clone();
+            let target_dir = match env.iter().find(|(key, _)| key == "CARGO_TARGET_DIR") {
+                Some((_, value)) => value.clone(),
+                None => std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".to_owned()),
+            };
+            env.retain(|(key, _)| key != "CARGO_TARGET_DIR");
+            env.push((
+                "CARGO_TARGET_DIR".to_owned(),
+                variant_target_dir(&target_dir, name),
+            ));
+
+            components.push(Component {
+                name: component_name.clone(),
+                path: kernel.path.clone(),
+                depends_on: kernel.depends_on.clone(),
+                env,
+                kind: ComponentKind::Cargo(cargo),
+            });
+            variants.push(KernelVariant {
+                name: name.to_owned(),
+                component: component_name,
+                install,
+                symbol_map: match symbols.map.is_empty() {
+                    true => String::new(),
+                    false => suffixed(&symbols.map, name),
+                },
+                title: variant_section.string("title", &format!("{} ({})", boot.title, name))?,
+                cmdline: variant_section.string("cmdline", &boot.cmdline)?,
+            });
+        }
+
+        Ok(variants)
+    }
+}
+
+// Where a variant builds, given the target directory the kernel builds into
+pub fn variant_target_dir(target_dir: &str, name: &str) -> String {
+    Path::new(target_dir)
+        .join("variants")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Adds the variant's name to the end of a file's name, before its extension
+fn suffixed(path: &str, name: &str) -> String {
+    let path = Path::new(path);
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push("-");
+    file_name.push(name);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Arch, ComponentKind, Config};
+
+    fn env<'a>(config: &'a Config, name: &str, key: &str) -> Option<&'a str> {
+        config
+            .component(name)
+            .unwrap()
+            .env
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn variants() {
+        let source = r#"
+[components.kernel]
+depends_on = ["libraries"]
+features = ["log"]
+
+[components.kernel.env]
+CARGO_TARGET_DIR = "/tmp/kernel-target"
+RUSTFLAGS = "-g"
+
+[boot]
+title = "Lance OS"
+cmdline = "log=info"
+
+[kernels.release]
+profile = "release"
+
+[kernels.quiet]
+features = ["quiet"]
+default_features = false
+title = "Quiet"
+cmdline = "log=off"
+"#;
+        let config = Config::parse(source, Arch::X86_64).unwrap();
+
+        let names: Vec<&str> = config.kernels.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["release", "quiet"]);
+
+        let release = &config.kernels[0];
+        assert_eq!(release.component, "kernel-release");
+        assert_eq!(release.install, "kernel-release.elf");
+        assert_eq!(release.symbol_map, "kernel-release.sym");
+        assert_eq!(release.title, "Lance OS (release)");
+        assert_eq!(release.cmdline, "log=info");
+
+        let quiet = &config.kernels[1];
+        assert_eq!(quiet.component, "kernel-quiet");
+        assert_eq!(quiet.install, "kernel-quiet.elf");
+        assert_eq!(quiet.title, "Quiet");
+        assert_eq!(quiet.cmdline, "log=off");
+
+        let kernel = config.component("kernel").unwrap();
+        for variant in &config.kernels {
+            let component = config.component(&variant.component).unwrap();
+            assert_eq!(component.path, kernel.path);
+            assert_eq!(component.depends_on, ["libraries"]);
+            assert!(config.is_kernel(component));
+        }
+
+        match &config.component("kernel-release").unwrap().kind {
+            ComponentKind::Cargo(cargo) => {
+                assert_eq!(cargo.profile.as_deref(), Some("release"));
+                assert_eq!(cargo.features, ["log"]);
+                assert!(cargo.default_features);
+                assert_eq!(cargo.install.as_deref(), Some("kernel-release.elf"));
+            }
+            _ => panic!("variant isn't built by cargo"),
+        }
+        match &config.component("kernel-quiet").unwrap().kind {
+            ComponentKind::Cargo(cargo) => {
+                assert_eq!(cargo.profile, None);
+                assert_eq!(cargo.features, ["quiet"]);
+                assert!(!cargo.default_features);
+            }
+            _ => panic!("variant isn't built by cargo"),
+        }
+
+        // Each variant builds under the kernel's own target directory, once
+        assert_eq!(
+            env(&config, "kernel", "CARGO_TARGET_DIR"),
+            Some("/tmp/kernel-target")
+        );
+        assert_eq!(
+            env(&config, "kernel-release", "CARGO_TARGET_DIR"),
+            Some("/tmp/kernel-target/variants/release")
+        );
+        assert_eq!(
+            env(&config, "kernel-quiet", "CARGO_TARGET_DIR"),
+            Some("/tmp/kernel-target/variants/quiet")
+        );
+        assert_eq!(env(&config, "kernel-quiet", "RUSTFLAGS"), Some("-g"));
+        for variant in &config.kernels {
+            let component = config.component(&variant.component).unwrap();
+            let count = component
+                .env
+                .iter()
+                .filter(|(key, _)| key == "CARGO_TARGET_DIR")
+                .count();
+            assert_eq!(count, 1);
+        }
+    }
+
+    #[test]
+    fn default_target_dir() {
+        let config = Config::parse("[kernels.debug]\n", Arch::X86_64).unwrap();
+        let target_dir = env(&config, "kernel-debug", "CARGO_TARGET_DIR").unwrap();
+        let base = std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".to_owned());
+        assert_eq!(target_dir, super::variant_target_dir(&base, "debug"),);
+        assert!(target_dir.ends_with("variants/debug"));
+        assert_eq!(env(&config, "kernel", "CARGO_TARGET_DIR"), None);
+    }
+
+    #[test]
+    fn invalid_variants() {
+        for source in [
+            "[kernels.\"a b\"]\n",
+            "[kernels.debug]\nunknown = 1\n",
+            "[components.kernel-debug]\nkind = \"custom\"\nbuild = [\"true\"]\n[kernels.debug]\n",
+            "[components.kernel]\nkind = \"custom\"\nbuild = [\"true\"]\n[kernels.debug]\n",
+        ] {
+            assert!(Config::parse(source, Arch::X86_64).is_err(), "{}", source);
+        }
+    }
+}
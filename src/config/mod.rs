@@ -0,0 +1,895 @@
+use crate::arguments::{Arguments, TimingsFormat, Verbosity};
+use section::Section;
+use std::path::{Path, PathBuf};
+
+mod arch;
+mod component;
+mod kernel;
+mod profile;
+mod section;
+pub mod toml;
+
+pub use arch::{Arch, ARCHES, DEFAULT_ARCH};
+pub use component::{CargoComponent, Component, ComponentKind};
+pub use kernel::KernelVariant;
+pub use profile::{Profile, DEFAULT_PROFILE};
+
+// Parameters
+pub const DEFAULT_COMMAND: crate::Command = crate::Command::Build;
+
+// Files
+pub const CONFIG_FILE: &str = "./losb.toml";
+pub const TARGET_IMG: &str = "./os.img";
+pub const BUILD_INFO: &str = "los/etc/build-info"; // Relative to the sysroot
+
+// Directories
+pub const SYSROOT_DIR: &str = "./sysroot";
+pub const STATE_DIR: &str = "./.losb";
+pub const SYMBOLS_DIR: &str = "./symbols";
+
+// Components
+pub const BOOTLOADER_DIR: &str = "./bootloader";
+pub const KERNEL_DIR: &str = "./kernel";
+pub const KERNEL_INSTALL: &str = "kernel.elf";
+pub const KERNEL_SYMBOL_MAP: &str = "kernel.sym"; // Relative to the sysroot
+pub const LIBRARIES_DIR: &str = "./libraries";
+pub const PROGRAMS_DIR: &str = "./programs";
+
+// Image
+pub const IMAGE_SIZE: usize = 128; // In MB
+pub const MIN_IMAGE_SIZE: usize = 64; // In MB
+pub const INITRD_FILE: &str = "initrd"; // Relative to the sysroot
+
+// Boot configuration
+pub const BOOT_CONFIG: &str = "EFI/BOOT/los.cfg"; // Relative to the sysroot
+pub const BOOT_TITLE: &str = "Lance OS";
+pub const BOOT_TIMEOUT: usize = 3; // In seconds
+
+// New programs and libraries
+pub const TEMPLATES_DIR: &str = "./templates";
+pub const MEMBERS_FILE: &str = "brew.toml"; // Relative to the programs and libraries
+
+// Programs
+pub const EMULATOR_MEMORY: usize = 128; // In MB
+
+pub const OBJCOPY: &str = "objcopy";
+pub const OS_ABI: usize = 0; // System V, which the Lance OS toolchain targets
+
+pub const DEBUGGER: &str = "gdb";
+pub const DEBUGGER_PORT: usize = 1234;
+
+pub const VBOX: &str = "VBoxManage";
+pub const VBOX_IMAGE: &str = "os.vdi";
+
+// VirtualBox machine
+pub const VBOX_MACHINE: &str = "./os.vbox";
+pub const VBOX_MACHINE_NAME: &str = "Lance OS";
+pub const VBOX_MEMORY: usize = 512; // In MB
+pub const VBOX_CPU_COUNT: usize = 2;
+
+// OVA appliance
+pub const OVA_FILE: &str = "./os.ova";
+pub const OVA_NAME: &str = "Lance OS";
+pub const OVA_MEMORY: usize = 512; // In MB
+pub const OVA_CPU_COUNT: usize = 2;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),
+    Syntax(usize, String),
+    UnknownKey(String, String, usize),
+    InvalidValue(String, String, usize, String),
+    UnknownProfile(String, Vec<String>),
+    UnknownArch(String),
+    UnknownComponent(String, Vec<String>),
+    NoFeatures(String),
+    DependencyCycle(Vec<String>), // Each depending on the next, and the last on the first
+}
+
+pub struct Config {
+    pub profile: Profile, // The selected profile
+    pub profiles: Vec<Profile>,
+    pub arch: Arch,
+    pub jobs: usize, // Components built at once
+    pub verbosity: Verbosity,
+    pub timings: Option<TimingsFormat>, // How to print the timings of a build, if at all
+    pub selected: Vec<String>, // Components named on the command line, every one when empty
+    pub components: Vec<Component>, // Dependencies come before the components using them
+    pub kernels: Vec<KernelVariant>,
+    pub image: Image,
+    pub initrd: Initrd,
+    pub boot: Boot,
+    pub symbols: Symbols,
+    pub validate: Validate,
+    pub scaffold: Scaffold,
+    pub emulator: Emulator,
+    pub debugger: Debugger,
+    pub vbox: VBox,
+    pub ova: Appliance,
+}
+
+pub struct Image {
+    pub path: String,
+    pub sysroot: String,
+    pub size: usize, // In MB
+}
+
+// An archive of part of the sysroot, packed into the sysroot before the image is made
+pub struct Initrd {
+    pub include: Vec<String>, // Relative to the sysroot, nothing is packed when empty
+    pub path: String,         // Relative to the sysroot
+    pub format: InitrdFormat,
+    pub compression: Compression,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InitrdFormat {
+    Cpio, // The newc format Linux reads
+    Ustar,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+// What the bootloader is told to load, written into the sysroot by every build
+pub struct Boot {
+    pub path: String, // Relative to the sysroot, empty for nowhere
+    pub title: String,
+    pub timeout: usize, // In seconds
+    pub cmdline: String,
+}
+
+pub struct Symbols {
+    pub path: String,
+    pub split: bool, // Moves debug information out of the files installed into the sysroot
+    pub objcopy: String,
+    pub map: String, // Where the kernel's symbol map is written in the sysroot, empty for nowhere
+}
+
+// Checks made of the files installed into the sysroot
+pub struct Validate {
+    pub enabled: bool,
+    pub os_abi: u8, // Expected of userland ELF files
+}
+
+// How new-program and new-library create projects
+pub struct Scaffold {
+    pub templates: String, // Holding "program" and "library" directories replacing those built in
+    pub register: String,  // The build file listing the members of the programs and libraries
+}
+
+pub struct Emulator {
+    pub program: String,
+    pub machine: Option<String>, // QEMU's default when None
+    pub firmware: String,
+    pub memory: usize, // In MB
+    pub flags: Vec<String>,
+}
+
+pub struct Debugger {
+    pub program: String,
+    pub port: usize,
+    pub flags: Vec<String>,
+}
+
+pub struct VBox {
+    pub program: String,
+    pub image: String,
+    pub machine: String,
+    pub name: String,
+    pub memory: usize, // In MB
+    pub cpus: usize,
+}
+
+pub struct Appliance {
+    pub path: String,
+    pub name: String,
+    pub memory: usize, // In MB
+    pub cpus: usize,
+}
+
+impl Config {
+    // Loads losb.toml from the project root, missing files give the default configuration, then
+    // applies the options given on the command line
+    pub fn load(arguments: &Arguments) -> Result<Self, ConfigError> {
+        let arch = match arguments.arch.as_deref() {
+            Some(name) => match Arch::parse(name) {
+                Some(arch) => arch,
+                None => return Err(ConfigError::UnknownArch(name.to_owned())),
+            },
+            None => DEFAULT_ARCH,
+        };
+
+        let path = Path::new(CONFIG_FILE);
+        let source = if path.exists() {
+            match std::fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(error) => return Err(ConfigError::Read(error)),
+            }
+        } else {
+            String::new()
+        };
+
+        let mut config = Config::parse(&source, arch)?;
+        config.apply_arguments(arguments)?;
+        Ok(config)
+    }
+
+    // Applies the options given on the command line, which take precedence over losb.toml
+    fn apply_arguments(&mut self, arguments: &Arguments) -> Result<(), ConfigError> {
+        let config = self;
+
+        // The first name which is a profile selects it, the rest are components
+        let mut profile_selected = false;
+        for name in &arguments.names {
+            if !profile_selected && config.profiles.iter().any(|profile| &profile.name == name) {
+                config.select_profile(name)?;
+                profile_selected = true;
+            } else {
+                config.select_component(name)?;
+            }
+        }
+        for name in &arguments.only {
+            config.select_component(name)?;
+        }
+
+        // Features and variables given without a component apply to the selected components
+        // Cargo uses "dependency/feature", so the component is given as "component:feature"
+        for feature in &arguments.features {
+            let (name, feature) = match feature.split_once(':') {
+                Some((name, feature)) => (Some(name), feature),
+                None => (None, feature.as_str()),
+            };
+            for index in config.option_targets(name)? {
+                let component = &mut config.components[index];
+                match &mut component.kind {
+                    ComponentKind::Cargo(cargo) => {
+                        cargo.features.retain(|existing| existing != feature);
+                        cargo.features.push(feature.to_owned());
+                    }
+                    _ if name.is_some() => {
+                        return Err(ConfigError::NoFeatures(component.name.clone()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for name in &arguments.no_default_features {
+            for index in config.option_targets(name.as_deref())? {
+                let component = &mut config.components[index];
+                match &mut component.kind {
+                    ComponentKind::Cargo(cargo) => cargo.default_features = false,
+                    _ if name.is_some() => {
+                        return Err(ConfigError::NoFeatures(component.name.clone()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for variable in &arguments.env {
+            let (target, value) = variable.split_once('=').unwrap();
+            let (name, key) = match target.split_once(':') {
+                Some((name, key)) => (Some(name), key),
+                None => (None, target),
+            };
+            for index in config.option_targets(name)? {
+                let component = &config.components[index];
+                let variant = config
+                    .kernels
+                    .iter()
+                    .find(|variant| variant.component == component.name);
+                // Variants keep their own subdirectory of a target directory given here too
+                let value = match variant {
+                    Some(variant) if key == "CARGO_TARGET_DIR" => {
+                        kernel::variant_target_dir(value, &variant.name)
+                    }
+                    _ => value.to_owned(),
+                };
+                let env = &mut config.components[index].env;
+                env.retain(|(existing, _)| existing != key);
+                env.push((key.to_owned(), value));
+            }
+        }
+
+        if let Some(jobs) = arguments.jobs {
+            config.jobs = jobs;
+        }
+        config.verbosity = arguments.verbosity;
+        config.timings = arguments.timings;
+        Ok(())
+    }
+
+    // Defaults which differ between architectures are taken from arch
+    pub fn parse(source: &str, arch: Arch) -> Result<Self, ConfigError> {
+        let table = match toml::parse(source) {
+            Ok(table) => table,
+            Err(error) => return Err(ConfigError::Syntax(error.line, error.message)),
+        };
+
+        let root = Section::root(&table);
+        root.check_keys(&[
+            "profiles",
+            "components",
+            "kernels",
+            "image",
+            "initrd",
+            "boot",
+            "symbols",
+            "validate",
+            "new",
+            "emulator",
+            "debugger",
+            "vbox",
+            "ova",
+        ])?;
+
+        let profiles = Profile::parse_all(root.section("profiles")?)?;
+        let profile = profiles
+            .iter()
+            .find(|profile| profile.name == DEFAULT_PROFILE)
+            .unwrap()
+            .clone();
+
+        let mut components = Component::parse_all(root.section("components")?, arch)?;
+        let boot = Boot::parse(root.section("boot")?)?;
+        let symbols = Symbols::parse(root.section("symbols")?)?;
+        let kernels =
+            KernelVariant::parse_all(root.section("kernels")?, &mut components, &boot, &symbols)?;
+
+        Ok(Config {
+            profile,
+            profiles,
+            arch,
+            jobs: std::thread::available_parallelism()
+                .map(|jobs| jobs.get())
+                .unwrap_or(1),
+            verbosity: Verbosity::Normal,
+            timings: None,
+            selected: Vec::new(),
+            components,
+            kernels,
+            image: Image::parse(root.section("image")?)?,
+            initrd: Initrd::parse(root.section("initrd")?)?,
+            boot,
+            symbols,
+            validate: Validate::parse(root.section("validate")?)?,
+            scaffold: Scaffold::parse(root.section("new")?)?,
+            emulator: Emulator::parse(root.section("emulator")?, arch)?,
+            debugger: Debugger::parse(root.section("debugger")?)?,
+            vbox: VBox::parse(root.section("vbox")?)?,
+            ova: Appliance::parse(root.section("ova")?)?,
+        })
+    }
+
+    pub fn select_profile(&mut self, name: &str) -> Result<(), ConfigError> {
+        match self.profiles.iter().find(|profile| profile.name == name) {
+            Some(profile) => {
+                self.profile = profile.clone();
+                Ok(())
+            }
+            None => Err(ConfigError::UnknownProfile(
+                name.to_owned(),
+                self.profiles
+                    .iter()
+                    .map(|profile| profile.name.clone())
+                    .collect(),
+            )),
+        }
+    }
+
+    // Every profile and architecture but the defaults keeps its own sysroot and image, named
+    // after them
+    fn variant_path(&self, path: &str, profile: &Profile, arch: Arch) -> PathBuf {
+        let path = Path::new(path);
+        if profile.name == DEFAULT_PROFILE && arch == DEFAULT_ARCH {
+            return path.to_owned();
+        }
+
+        let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+        if profile.name != DEFAULT_PROFILE {
+            file_name.push("-");
+            file_name.push(&profile.name);
+        }
+        if arch != DEFAULT_ARCH {
+            file_name.push("-");
+            file_name.push(arch.name());
+        }
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        path.with_file_name(file_name)
+    }
+
+    fn select_component(&mut self, name: &str) -> Result<(), ConfigError> {
+        if self.component(name).is_none() {
+            return Err(ConfigError::UnknownComponent(
+                name.to_owned(),
+                self.components
+                    .iter()
+                    .map(|component| component.name.clone())
+                    .collect(),
+            ));
+        }
+
+        if !self.selected.iter().any(|selected| selected == name) {
+            self.selected.push(name.to_owned());
+        }
+        Ok(())
+    }
+
+    // The component an option names, or the selected components when it doesn't name one
+    fn option_targets(&self, name: Option<&str>) -> Result<Vec<usize>, ConfigError> {
+        match name {
+            Some(name) => match self
+                .components
+                .iter()
+                .position(|component| component.name == name)
+            {
+                Some(index) => Ok(vec![index]),
+                None => Err(ConfigError::UnknownComponent(
+                    name.to_owned(),
+                    self.components
+                        .iter()
+                        .map(|component| component.name.clone())
+                        .collect(),
+                )),
+            },
+            None => Ok((0..self.components.len())
+                .filter(|index| self.is_selected(&self.components[*index]))
+                .collect()),
+        }
+    }
+
+    pub fn is_selected(&self, component: &Component) -> bool {
+        self.selected.is_empty() || self.selected.contains(&component.name)
+    }
+
+    // Which components to build, those selected along with everything depending on them
+    pub fn build_selection(&self) -> Vec<bool> {
+        let mut selection: Vec<bool> = Vec::with_capacity(self.components.len());
+        for component in &self.components {
+            let dependent = component.depends_on.iter().any(|dependency| {
+                self.components
+                    .iter()
+                    .zip(&selection)
+                    .any(|(other, selected)| *selected && &other.name == dependency)
+            });
+            selection.push(self.is_selected(component) || dependent);
+        }
+        selection
+    }
+
+    // Whether a component builds the kernel or one of its variants
+    pub fn is_kernel(&self, component: &Component) -> bool {
+        component.name == "kernel"
+            || self
+                .kernels
+                .iter()
+                .any(|variant| variant.component == component.name)
+    }
+
+    // Where a kernel's symbol map is written in the sysroot, None for other components or when
+    // no map is written
+    pub fn symbol_map(&self, component: &Component) -> Option<&str> {
+        let map = match component.name == "kernel" {
+            true => &self.symbols.map,
+            false => {
+                &self
+                    .kernels
+                    .iter()
+                    .find(|variant| variant.component == component.name)?
+                    .symbol_map
+            }
+        };
+        match map.is_empty() {
+            true => None,
+            false => Some(map),
+        }
+    }
+
+    pub fn component(&self, name: &str) -> Option<&Component> {
+        self.components
+            .iter()
+            .find(|component| component.name == name)
+    }
+
+    // Describes the selected profile and architecture for status messages
+    pub fn variant_name(&self) -> String {
+        match self.arch == DEFAULT_ARCH {
+            true => self.profile.name.clone(),
+            false => format!("{}, {}", self.profile.name, self.arch),
+        }
+    }
+
+    pub fn sysroot(&self) -> PathBuf {
+        self.variant_sysroot(&self.profile, self.arch)
+    }
+
+    pub fn variant_sysroot(&self, profile: &Profile, arch: Arch) -> PathBuf {
+        self.variant_path(&self.image.sysroot, profile, arch)
+    }
+
+    // The sysroot a build installs into, which replaces the sysroot once every component succeeds
+    pub fn next_sysroot(&self) -> PathBuf {
+        Path::new(STATE_DIR)
+            .join("next")
+            .join(self.sysroot().file_name().unwrap_or_default())
+    }
+
+    // The sysroot as it was before the last build which changed it
+    pub fn previous_sysroot(&self) -> PathBuf {
+        Path::new(STATE_DIR)
+            .join("previous")
+            .join(self.sysroot().file_name().unwrap_or_default())
+    }
+
+    pub fn previous_image(&self) -> PathBuf {
+        Path::new(STATE_DIR)
+            .join("previous")
+            .join(self.image_path().file_name().unwrap_or_default())
+    }
+
+    pub fn symbols_path(&self) -> PathBuf {
+        self.variant_symbols(&self.profile, self.arch)
+    }
+
+    // The symbols a build splits out, which replace the symbols along with the sysroot
+    pub fn next_symbols(&self) -> PathBuf {
+        Path::new(STATE_DIR)
+            .join("next-symbols")
+            .join(self.symbols_path().file_name().unwrap_or_default())
+    }
+
+    pub fn previous_symbols(&self) -> PathBuf {
+        Path::new(STATE_DIR)
+            .join("previous-symbols")
+            .join(self.symbols_path().file_name().unwrap_or_default())
+    }
+
+    pub fn variant_symbols(&self, profile: &Profile, arch: Arch) -> PathBuf {
+        self.variant_path(&self.symbols.path, profile, arch)
+    }
+
+    pub fn image_path(&self) -> PathBuf {
+        self.variant_path(&self.image.path, &self.profile, self.arch)
+    }
+}
+
+impl Image {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["path", "sysroot", "size"])?;
+
+        Ok(Image {
+            path: section.string("path", TARGET_IMG)?,
+            sysroot: section.string("sysroot", SYSROOT_DIR)?,
+            size: section.integer("size", IMAGE_SIZE, MIN_IMAGE_SIZE, usize::MAX)?,
+        })
+    }
+}
+
+impl Initrd {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["include", "path", "format", "compression"])?;
+
+        let invalid = |key: &str, expected: &str| {
+            ConfigError::InvalidValue(
+                section.name().to_owned(),
+                key.to_owned(),
+                section.line(key),
+                format!("expected one of {}", expected),
+            )
+        };
+        let format = match section.string("format", "cpio")?.as_str() {
+            "cpio" => InitrdFormat::Cpio,
+            "ustar" => InitrdFormat::Ustar,
+            _ => return Err(invalid("format", "cpio, ustar")),
+        };
+        let compression = match section.string("compression", "none")?.as_str() {
+            "none" => Compression::None,
+            "gzip" => Compression::Gzip,
+            _ => return Err(invalid("compression", "none, gzip")),
+        };
+
+        Ok(Initrd {
+            include: section.strings("include", &[])?,
+            path: section.string("path", INITRD_FILE)?,
+            format,
+            compression,
+        })
+    }
+}
+
+impl Boot {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["path", "title", "timeout", "cmdline"])?;
+
+        Ok(Boot {
+            path: section.string("path", BOOT_CONFIG)?,
+            title: section.string("title", BOOT_TITLE)?,
+            timeout: section.integer("timeout", BOOT_TIMEOUT, 0, usize::MAX)?,
+            cmdline: section.string("cmdline", "")?,
+        })
+    }
+}
+
+impl Symbols {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["path", "split", "objcopy", "map"])?;
+
+        Ok(Symbols {
+            path: section.string("path", SYMBOLS_DIR)?,
+            split: section.boolean("split", true)?,
+            objcopy: section.string("objcopy", OBJCOPY)?,
+            map: section.string("map", KERNEL_SYMBOL_MAP)?,
+        })
+    }
+}
+
+impl Validate {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["enabled", "os_abi"])?;
+
+        Ok(Validate {
+            enabled: section.boolean("enabled", true)?,
+            os_abi: section.integer("os_abi", OS_ABI, 0, 255)? as u8,
+        })
+    }
+}
+
+impl Scaffold {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["templates", "register"])?;
+
+        Ok(Scaffold {
+            templates: section.string("templates", TEMPLATES_DIR)?,
+            register: section.string("register", MEMBERS_FILE)?,
+        })
+    }
+}
+
+impl Emulator {
+    fn parse(section: Section, arch: Arch) -> Result<Self, ConfigError> {
+        section.check_keys(&["program", "machine", "firmware", "memory", "flags"])?;
+
+        Ok(Emulator {
+            program: section.string("program", arch.emulator())?,
+            machine: section
+                .optional_string("machine")?
+                .or_else(|| arch.machine().map(str::to_owned)),
+            firmware: section.string("firmware", arch.firmware())?,
+            memory: section.integer("memory", EMULATOR_MEMORY, 1, usize::MAX)?,
+            flags: section.strings("flags", &[])?,
+        })
+    }
+}
+
+impl Debugger {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["program", "port", "flags"])?;
+
+        Ok(Debugger {
+            program: section.string("program", DEBUGGER)?,
+            port: section.integer("port", DEBUGGER_PORT, 1, 65535)?,
+            flags: section.strings("flags", &[])?,
+        })
+    }
+}
+
+impl VBox {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["program", "image", "machine", "name", "memory", "cpus"])?;
+
+        Ok(VBox {
+            program: section.string("program", VBOX)?,
+            image: section.string("image", VBOX_IMAGE)?,
+            machine: section.string("machine", VBOX_MACHINE)?,
+            name: section.string("name", VBOX_MACHINE_NAME)?,
+            memory: section.integer("memory", VBOX_MEMORY, 4, usize::MAX)?,
+            cpus: section.integer("cpus", VBOX_CPU_COUNT, 1, 64)?,
+        })
+    }
+}
+
+impl Appliance {
+    fn parse(section: Section) -> Result<Self, ConfigError> {
+        section.check_keys(&["path", "name", "memory", "cpus"])?;
+
+        Ok(Appliance {
+            path: section.string("path", OVA_FILE)?,
+            name: section.string("name", OVA_NAME)?,
+            memory: section.integer("memory", OVA_MEMORY, 4, usize::MAX)?,
+            cpus: section.integer("cpus", OVA_CPU_COUNT, 1, 64)?,
+        })
+    }
+}
+
+fn location(section: &str) -> String {
+    match section.is_empty() {
+        true => CONFIG_FILE.to_owned(),
+        false => format!("[{}] of {}", section, CONFIG_FILE),
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ConfigError::Read(error) => format!("Unable to read {} ({})", CONFIG_FILE, error),
+                ConfigError::Syntax(line, message) =>
+                    format!("Invalid {} on line {} ({})", CONFIG_FILE, line, message),
+                ConfigError::UnknownKey(section, key, line) => format!(
+                    "Unknown key \"{}\" in {} on line {}",
+                    key,
+                    location(section),
+                    line
+                ),
+                ConfigError::InvalidValue(section, key, line, expected) => format!(
+                    "Invalid value for \"{}\" in {} on line {} ({})",
+                    key,
+                    location(section),
+                    line,
+                    expected
+                ),
+                ConfigError::UnknownProfile(name, profiles) => format!(
+                    "Unknown configuration \"{}\" (expected one of {})",
+                    name,
+                    profiles.join(", ")
+                ),
+                ConfigError::DependencyCycle(components) => format!(
+                    "Components in {} depend on each other ({} -> {})",
+                    CONFIG_FILE,
+                    components.join(" -> "),
+                    components[0]
+                ),
+                ConfigError::UnknownComponent(name, components) => format!(
+                    "Unknown component \"{}\" (expected one of {})",
+                    name,
+                    components.join(", ")
+                ),
+                ConfigError::NoFeatures(name) =>
+                    format!("{} isn't built by cargo so has no features", name),
+                ConfigError::UnknownArch(name) => format!(
+                    "Unknown architecture \"{}\" (expected one of {})",
+                    name,
+                    ARCHES
+                        .iter()
+                        .map(|arch| arch.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arch, ComponentKind, Config, ConfigError};
+
+    const SOURCE: &str = r#"
+[components.kernel]
+features = ["log"]
+
+[components.kernel.env]
+RUSTFLAGS = "-g"
+RUST_LOG = "info"
+
+[components.tools]
+kind = "custom"
+build = ["make"]
+"#;
+
+    fn load(arguments: &[&str]) -> Result<Config, ConfigError> {
+        let arguments = crate::arguments::parse(
+            std::iter::once("losb")
+                .chain(arguments.iter().copied())
+                .map(str::to_owned)
+                .collect(),
+        )
+        .unwrap();
+        let mut config = Config::parse(SOURCE, Arch::X86_64)?;
+        config.apply_arguments(&arguments)?;
+        Ok(config)
+    }
+
+    // The features and default features of a cargo component
+    fn features(config: &Config, name: &str) -> (Vec<String>, bool) {
+        match &config.component(name).unwrap().kind {
+            ComponentKind::Cargo(cargo) => (cargo.features.clone(), cargo.default_features),
+            _ => panic!("{} isn't built by cargo", name),
+        }
+    }
+
+    fn env(config: &Config, name: &str) -> Vec<(String, String)> {
+        config.component(name).unwrap().env.clone()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn features_add_to_the_config() {
+        let config = load(&[]).unwrap();
+        assert_eq!(features(&config, "kernel"), (vec!["log".to_owned()], true));
+
+        // Given for a component, features are added to those it has
+        let config = load(&["build", "--features", "kernel:poison,kernel:log"]).unwrap();
+        assert_eq!(
+            features(&config, "kernel"),
+            (vec!["poison".to_owned(), "log".to_owned()], true)
+        );
+        assert_eq!(features(&config, "bootloader"), (Vec::new(), true));
+
+        // Without one they apply to the selected components, skipping those without features
+        let config = load(&["build", "kernel", "tools", "--features", "a"]).unwrap();
+        assert_eq!(features(&config, "kernel").0, ["log", "a"]);
+        assert!(features(&config, "bootloader").0.is_empty());
+        let config = load(&["build", "--features", "a"]).unwrap();
+        assert_eq!(features(&config, "bootloader").0, ["a"]);
+
+        // A dependency's feature is passed to cargo as it is
+        let config = load(&["build", "kernel", "--features", "log/verbose"]).unwrap();
+        assert_eq!(features(&config, "kernel").0, ["log", "log/verbose"]);
+
+        assert!(matches!(
+            load(&["build", "--features", "missing:a"]),
+            Err(ConfigError::UnknownComponent(name, _)) if name == "missing"
+        ));
+        assert!(matches!(
+            load(&["build", "--features", "tools:a"]),
+            Err(ConfigError::NoFeatures(name)) if name == "tools"
+        ));
+    }
+
+    #[test]
+    fn no_default_features() {
+        let config = load(&["build", "kernel", "--no-default-features"]).unwrap();
+        assert!(!features(&config, "kernel").1);
+        assert!(features(&config, "bootloader").1);
+
+        let config = load(&["build", "--no-default-features=bootloader"]).unwrap();
+        assert!(features(&config, "kernel").1);
+        assert!(!features(&config, "bootloader").1);
+
+        let config = load(&["build", "--no-default-features"]).unwrap();
+        assert!(!features(&config, "kernel").1);
+        assert!(!features(&config, "bootloader").1);
+
+        assert!(matches!(
+            load(&["build", "--no-default-features=tools"]),
+            Err(ConfigError::NoFeatures(name)) if name == "tools"
+        ));
+    }
+
+    #[test]
+    fn env_replaces_the_config() {
+        let config = load(&["build", "--env", "kernel:RUSTFLAGS=-O"]).unwrap();
+        assert_eq!(
+            env(&config, "kernel"),
+            pairs(&[("RUST_LOG", "info"), ("RUSTFLAGS", "-O")])
+        );
+        assert!(env(&config, "bootloader").is_empty());
+
+        // Values may hold '=' and ':', only the first of each splits them
+        let config = load(&["build", "tools", "--env", "A=b=c:d"]).unwrap();
+        assert_eq!(env(&config, "tools"), pairs(&[("A", "b=c:d")]));
+        assert!(env(&config, "bootloader").is_empty());
+
+        assert!(matches!(
+            load(&["build", "--env", "missing:A=b"]),
+            Err(ConfigError::UnknownComponent(name, _)) if name == "missing"
+        ));
+    }
+}
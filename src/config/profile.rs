@@ -0,0 +1,158 @@
+use super::{section::Section, ConfigError};
+
+// A build configuration, selecting the cargo profile and brew configuration used for every component
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub cargo: String,
+    pub brew: String,
+}
+
+pub const DEFAULT_PROFILE: &str = "debug";
+
+impl Profile {
+    fn new(name: &str, cargo: &str) -> Self {
+        Profile {
+            name: name.to_owned(),
+            cargo: cargo.to_owned(),
+            brew: name.to_owned(),
+        }
+    }
+
+    // The built in profiles followed by those declared in [profiles]
+    pub fn parse_all(section: Section) -> Result<Vec<Self>, ConfigError> {
+        let mut profiles = vec![
+            Profile::new("debug", "dev"),
+            Profile::new("release", "release"),
+        ];
+
+        for name in section.keys() {
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ConfigError::InvalidValue(
+                    section.name().to_owned(),
+                    name.to_owned(),
+                    section.line(name),
+                    "profile names may only contain letters, digits, '-' and '_'".to_owned(),
+                ));
+            }
+
+            let profile_section = section.section(name)?;
+            profile_section.check_keys(&["cargo", "brew"])?;
+
+            let profile = match profiles.iter().position(|profile| profile.name == name) {
+                Some(index) => &mut profiles[index],
+                None => {
+                    profiles.push(Profile::new(name, name));
+                    profiles.last_mut().unwrap()
+                }
+            };
+
+            profile.cargo = profile_section.string("cargo", &profile.cargo)?;
+            profile.brew = profile_section.string("brew", &profile.brew)?;
+        }
+
+        Ok(profiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Arch, Config, ConfigError, DEFAULT_PROFILE};
+    use std::path::Path;
+
+    #[test]
+    fn built_in() {
+        let mut config = Config::parse("", Arch::X86_64).unwrap();
+        assert_eq!(config.profile.name, DEFAULT_PROFILE);
+
+        // Cargo calls its debug profile dev
+        assert_eq!(config.profile.cargo, "dev");
+        assert_eq!(config.profile.brew, "debug");
+
+        config.select_profile("release").unwrap();
+        assert_eq!(
+            (config.profile.cargo.as_str(), config.profile.brew.as_str()),
+            ("release", "release")
+        );
+    }
+
+    #[test]
+    fn custom() {
+        let source = r#"
+[profiles.profiling]
+cargo = "release-with-debug"
+
+[profiles.small]
+
+[profiles.debug]
+brew = "checked"
+"#;
+        let mut config = Config::parse(source, Arch::X86_64).unwrap();
+        let names: Vec<&str> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["debug", "release", "profiling", "small"]);
+
+        // Changing a built in profile keeps what isn't changed
+        assert_eq!(config.profile.cargo, "dev");
+        assert_eq!(config.profile.brew, "checked");
+
+        config.select_profile("profiling").unwrap();
+        assert_eq!(config.profile.cargo, "release-with-debug");
+        assert_eq!(config.profile.brew, "profiling");
+
+        // Both default to the profile's name
+        config.select_profile("small").unwrap();
+        assert_eq!(config.profile.cargo, "small");
+        assert_eq!(config.profile.brew, "small");
+
+        match config.select_profile("fast") {
+            Err(ConfigError::UnknownProfile(name, profiles)) => {
+                assert_eq!(name, "fast");
+                assert_eq!(profiles, ["debug", "release", "profiling", "small"]);
+            }
+            _ => panic!("expected an unknown profile"),
+        }
+        assert_eq!(config.profile.name, "small");
+
+        for source in [
+            "[profiles.\"with space\"]\n",
+            "[profiles.fast]\nopt-level = 3\n",
+        ] {
+            assert!(Config::parse(source, Arch::X86_64).is_err(), "{}", source);
+        }
+    }
+
+    // Every profile and architecture but the defaults is kept apart
+    #[test]
+    fn variant_paths() {
+        let mut config = Config::parse("", Arch::X86_64).unwrap();
+        assert_eq!(config.sysroot(), Path::new("./sysroot"));
+        assert_eq!(config.image_path(), Path::new("./os.img"));
+
+        config.select_profile("release").unwrap();
+        assert_eq!(config.sysroot(), Path::new("./sysroot-release"));
+        assert_eq!(config.image_path(), Path::new("./os-release.img"));
+        assert_eq!(config.symbols_path(), Path::new("./symbols-release"));
+
+        let mut config = Config::parse("", Arch::AArch64).unwrap();
+        assert_eq!(config.sysroot(), Path::new("./sysroot-aarch64"));
+        assert_eq!(config.image_path(), Path::new("./os-aarch64.img"));
+        config.select_profile("release").unwrap();
+        assert_eq!(config.image_path(), Path::new("./os-release-aarch64.img"));
+
+        let source = "[image]\npath = \"build/lance.disk.img\"\nsysroot = \"build/root\"\n";
+        let mut config = Config::parse(source, Arch::RiscV64).unwrap();
+        config.select_profile("release").unwrap();
+        assert_eq!(config.sysroot(), Path::new("build/root-release-riscv64"));
+        assert_eq!(
+            config.image_path(),
+            Path::new("build/lance.disk-release-riscv64.img")
+        );
+        assert_eq!(
+            config.next_sysroot(),
+            Path::new("./.losb/next/root-release-riscv64")
+        );
+    }
+}
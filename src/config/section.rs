@@ -0,0 +1,176 @@
+use super::{
+    toml::{Entry, Table, Value},
+    ConfigError,
+};
+
+// A table of losb.toml being read into a typed configuration
+pub struct Section<'a> {
+    name: String,
+    table: Option<&'a Table>,
+}
+
+impl<'a> Section<'a> {
+    pub fn root(table: &'a Table) -> Self {
+        Section {
+            name: String::new(),
+            table: Some(table),
+        }
+    }
+
+    // Gets a child table, missing tables act as empty ones so every key takes its default
+    pub fn section(&self, key: &str) -> Result<Section<'a>, ConfigError> {
+        let name = if self.name.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", self.name, key)
+        };
+
+        match self.get(key) {
+            None => Ok(Section { name, table: None }),
+            Some(Entry {
+                value: Value::Table(table),
+                ..
+            }) => Ok(Section {
+                name,
+                table: Some(table),
+            }),
+            Some(entry) => Err(self.invalid(key, entry, "a table")),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The names of every child, in the order they appear
+    pub fn keys(&self) -> Vec<&'a str> {
+        match self.table {
+            Some(table) => table.iter().map(|(key, _)| key).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn line(&self, key: &str) -> usize {
+        self.get(key).map(|entry| entry.line).unwrap_or(0)
+    }
+
+    pub fn check_keys(&self, allowed: &[&str]) -> Result<(), ConfigError> {
+        if let Some(table) = self.table {
+            for (key, entry) in table.iter() {
+                if !allowed.contains(&key) {
+                    return Err(ConfigError::UnknownKey(
+                        self.name.clone(),
+                        key.to_owned(),
+                        entry.line,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn string(&self, key: &str, default: &str) -> Result<String, ConfigError> {
+        match self.get(key) {
+            None => Ok(default.to_owned()),
+            Some(Entry {
+                value: Value::String(value),
+                ..
+            }) => Ok(value.clone()),
+            Some(entry) => Err(self.invalid(key, entry, "a string")),
+        }
+    }
+
+    pub fn optional_string(&self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::String(value),
+                ..
+            }) => Ok(Some(value.clone())),
+            Some(entry) => Err(self.invalid(key, entry, "a string")),
+        }
+    }
+
+    pub fn boolean(&self, key: &str, default: bool) -> Result<bool, ConfigError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(Entry {
+                value: Value::Boolean(value),
+                ..
+            }) => Ok(*value),
+            Some(entry) => Err(self.invalid(key, entry, "a boolean")),
+        }
+    }
+
+    pub fn strings(&self, key: &str, default: &[&str]) -> Result<Vec<String>, ConfigError> {
+        let array = match self.get(key) {
+            None => return Ok(default.iter().map(|value| value.to_string()).collect()),
+            Some(Entry {
+                value: Value::Array(array),
+                ..
+            }) => array,
+            Some(entry) => return Err(self.invalid(key, entry, "an array of strings")),
+        };
+
+        let mut strings = Vec::with_capacity(array.len());
+        for entry in array {
+            match &entry.value {
+                Value::String(value) => strings.push(value.clone()),
+                _ => return Err(self.invalid(key, entry, "an array of strings")),
+            }
+        }
+        Ok(strings)
+    }
+
+    // Reads an integer which must lie within minimum..=maximum
+    pub fn integer(
+        &self,
+        key: &str,
+        default: usize,
+        minimum: usize,
+        maximum: usize,
+    ) -> Result<usize, ConfigError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(
+                entry @ Entry {
+                    value: Value::Integer(value),
+                    ..
+                },
+            ) => {
+                if *value >= 0 && (*value as usize) >= minimum && (*value as usize) <= maximum {
+                    Ok(*value as usize)
+                } else if maximum == usize::MAX {
+                    Err(ConfigError::InvalidValue(
+                        self.name.clone(),
+                        key.to_owned(),
+                        entry.line,
+                        format!("expected an integer of at least {}", minimum),
+                    ))
+                } else {
+                    Err(ConfigError::InvalidValue(
+                        self.name.clone(),
+                        key.to_owned(),
+                        entry.line,
+                        format!("expected an integer from {} to {}", minimum, maximum),
+                    ))
+                }
+            }
+            Some(entry) => Err(self.invalid(key, entry, "an integer")),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Entry> {
+        self.table.and_then(|table| table.get(key))
+    }
+
+    fn invalid(&self, key: &str, entry: &Entry, expected: &str) -> ConfigError {
+        ConfigError::InvalidValue(
+            self.name.clone(),
+            key.to_owned(),
+            entry.line,
+            format!("expected {}, found {}", expected, entry.value.type_name()),
+        )
+    }
+}
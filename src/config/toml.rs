@@ -0,0 +1,787 @@
+// A parser for the subset of TOML used by losb.toml. Floats and dates are not supported.
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Entry>),
+    Table(Table),
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Value,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    entries: Vec<(String, Entry)>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    defined_tables: Vec<Vec<String>>,
+}
+
+pub fn parse(source: &str) -> Result<Table, ParseError> {
+    Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        line: 1,
+        defined_tables: Vec::new(),
+    }
+    .parse()
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, entry)| entry)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .find(|(name, _)| name == key)
+            .map(|(_, entry)| entry)
+    }
+
+    fn insert(&mut self, key: String, entry: Entry) -> Result<(), ParseError> {
+        if self.get(&key).is_some() {
+            return Err(ParseError::new(
+                entry.line,
+                format!("\"{}\" is defined more than once", key),
+            ));
+        }
+
+        self.entries.push((key, entry));
+        Ok(())
+    }
+
+    // Finds the table at the end of a path, creating any missing tables along the way
+    fn descend(&mut self, path: &[String], line: usize) -> Result<&mut Table, ParseError> {
+        let mut table = self;
+        for key in path {
+            if table.get(key).is_none() {
+                table.insert(
+                    key.clone(),
+                    Entry {
+                        value: Value::Table(Table::default()),
+                        line,
+                    },
+                )?;
+            }
+
+            table = match &mut table.get_mut(key).unwrap().value {
+                Value::Table(table) => table,
+                Value::Array(array) => match array.last_mut() {
+                    Some(Entry {
+                        value: Value::Table(table),
+                        ..
+                    }) => table,
+                    _ => return Err(ParseError::new(line, format!("\"{}\" is not a table", key))),
+                },
+                _ => return Err(ParseError::new(line, format!("\"{}\" is not a table", key))),
+            };
+        }
+
+        Ok(table)
+    }
+}
+
+impl ParseError {
+    fn new(line: usize, message: String) -> Self {
+        ParseError { line, message }
+    }
+}
+
+impl Parser {
+    fn parse(mut self) -> Result<Table, ParseError> {
+        let mut root = Table::default();
+        let mut current_path = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_newlines();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+
+            if c == '[' {
+                self.position += 1;
+                let line = self.line;
+                if self.peek() == Some('[') {
+                    // Array of tables
+                    self.position += 1;
+                    let path = self.parse_key()?;
+                    self.expect(']')?;
+                    self.expect(']')?;
+
+                    let (last, parents) = path.split_last().unwrap();
+                    let parent = root.descend(parents, line)?;
+                    match parent.get_mut(last) {
+                        Some(Entry {
+                            value: Value::Array(array),
+                            ..
+                        }) => array.push(Entry {
+                            value: Value::Table(Table::default()),
+                            line,
+                        }),
+                        Some(_) => return Err(self.error(format!("\"{}\" is not an array", last))),
+                        None => parent.insert(
+                            last.clone(),
+                            Entry {
+                                value: Value::Array(vec![Entry {
+                                    value: Value::Table(Table::default()),
+                                    line,
+                                }]),
+                                line,
+                            },
+                        )?,
+                    }
+
+                    self.defined_tables
+                        .retain(|table| !table.starts_with(&path));
+                    current_path = path;
+                } else {
+                    // Table
+                    let path = self.parse_key()?;
+                    self.expect(']')?;
+
+                    if self.defined_tables.contains(&path) {
+                        return Err(
+                            self.error(format!("[{}] is defined more than once", path.join(".")))
+                        );
+                    }
+
+                    root.descend(&path, line)?;
+                    self.defined_tables.push(path.clone());
+                    current_path = path;
+                }
+            } else {
+                let line = self.line;
+                let key = self.parse_key()?;
+                self.skip_whitespace();
+                self.expect('=')?;
+                self.skip_whitespace();
+                let value = self.parse_value()?;
+
+                let (last, parents) = key.split_last().unwrap();
+                root.descend(&current_path, line)?
+                    .descend(parents, line)?
+                    .insert(last.clone(), Entry { value, line })?;
+            }
+
+            // Only a comment may follow on the same line
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                None | Some('\n') => {}
+                Some('\r') if self.peek_at(1) == Some('\n') => {}
+                Some(c) => return Err(self.error(format!("Unexpected \"{}\"", c))),
+            }
+        }
+
+        Ok(root)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError::new(self.line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("Expected \"{}\", found \"{}\"", expected, c))),
+            None => Err(self.error(format!("Expected \"{}\", found end of file", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while let Some(c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                self.position += 1;
+            }
+        }
+    }
+
+    fn skip_whitespace_and_newlines(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') => {
+                    self.position += 1;
+                    self.parse_basic_string()?
+                }
+                Some('\'') => {
+                    self.position += 1;
+                    self.parse_literal_string()?
+                }
+                _ => {
+                    let mut key = String::new();
+                    while let Some(c) = self.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                            break;
+                        }
+                        key.push(c);
+                        self.position += 1;
+                    }
+
+                    if key.is_empty() {
+                        return Err(match self.peek() {
+                            Some(c) => self.error(format!("Expected a key, found \"{}\"", c)),
+                            None => self.error("Expected a key, found end of file".to_owned()),
+                        });
+                    }
+
+                    key
+                }
+            };
+            path.push(key);
+
+            self.skip_whitespace();
+            if self.peek() == Some('.') {
+                self.position += 1;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') => {
+                if self.peek_at(1) == Some('"') && self.peek_at(2) == Some('"') {
+                    self.position += 3;
+                    self.skip_first_newline();
+                    self.parse_multiline_string(true)
+                } else {
+                    self.position += 1;
+                    self.parse_basic_string().map(Value::String)
+                }
+            }
+            Some('\'') => {
+                if self.peek_at(1) == Some('\'') && self.peek_at(2) == Some('\'') {
+                    self.position += 3;
+                    self.skip_first_newline();
+                    self.parse_multiline_string(false)
+                } else {
+                    self.position += 1;
+                    self.parse_literal_string().map(Value::String)
+                }
+            }
+            Some('[') => {
+                self.position += 1;
+                self.parse_array()
+            }
+            Some('{') => {
+                self.position += 1;
+                self.parse_inline_table()
+            }
+            Some('t') | Some('f') => {
+                let word = self.take_word();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(self.error(format!("Invalid value \"{}\"", word))),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' => self.parse_integer(),
+            Some(c) => Err(self.error(format!("Expected a value, found \"{}\"", c))),
+            None => Err(self.error("Expected a value, found end of file".to_owned())),
+        }
+    }
+
+    fn take_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+' || c == '.') {
+                break;
+            }
+            word.push(c);
+            self.position += 1;
+        }
+        word
+    }
+
+    fn parse_integer(&mut self) -> Result<Value, ParseError> {
+        let word = self.take_word();
+        let digits = word.replace('_', "");
+
+        let (negative, digits) = match digits.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+        };
+
+        let (radix, digits) = if let Some(digits) = digits.strip_prefix("0x") {
+            (16, digits)
+        } else if let Some(digits) = digits.strip_prefix("0o") {
+            (8, digits)
+        } else if let Some(digits) = digits.strip_prefix("0b") {
+            (2, digits)
+        } else {
+            (10, digits)
+        };
+
+        if radix == 10 && (digits.contains('.') || digits.contains('e') || digits.contains('E')) {
+            return Err(self.error(format!(
+                "Floating point values are not supported (\"{}\")",
+                word
+            )));
+        }
+
+        // The magnitude is parsed alone so the most negative value, which has no positive
+        // counterpart, can still be given
+        let value = match digits.starts_with(['+', '-']) {
+            true => None,
+            false => u64::from_str_radix(digits, radix).ok(),
+        }
+        .and_then(|magnitude| match negative {
+            true => 0i64.checked_sub_unsigned(magnitude),
+            false => 0i64.checked_add_unsigned(magnitude),
+        });
+        match value {
+            Some(value) => Ok(Value::Integer(value)),
+            None => Err(self.error(format!("Invalid integer \"{}\"", word))),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, ParseError> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('b') => Ok('\u{8}'),
+            Some('f') => Ok('\u{c}'),
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some(c) if c == 'u' || c == 'U' => {
+                let length = if c == 'u' { 4 } else { 8 };
+                let mut code = String::new();
+                for _ in 0..length {
+                    match self.next() {
+                        Some(c) => code.push(c),
+                        None => break,
+                    }
+                }
+
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| {
+                        self.error(format!("Invalid unicode escape \"\\{}{}\"", c, code))
+                    })
+            }
+            Some(c) => Err(self.error(format!("Invalid escape sequence \"\\{}\"", c))),
+            None => Err(self.error("Unterminated string".to_owned())),
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, ParseError> {
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(self.parse_escape()?),
+                Some('\n') | None => return Err(self.error("Unterminated string".to_owned())),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, ParseError> {
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(string),
+                Some('\n') | None => return Err(self.error("Unterminated string".to_owned())),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn skip_first_newline(&mut self) {
+        if self.peek() == Some('\r') && self.peek_at(1) == Some('\n') {
+            self.position += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+    }
+
+    fn parse_multiline_string(&mut self, escapes: bool) -> Result<Value, ParseError> {
+        let delimiter = if escapes { '"' } else { '\'' };
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some(c)
+                    if c == delimiter
+                        && self.peek() == Some(delimiter)
+                        && self.peek_at(1) == Some(delimiter) =>
+                {
+                    self.position += 2;
+                    return Ok(Value::String(string));
+                }
+                Some('\\') if escapes => {
+                    // A backslash at the end of a line trims the following whitespace
+                    match self.peek() {
+                        Some('\n') | Some('\r') | Some(' ') | Some('\t') => {
+                            while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek()
+                            {
+                                self.next();
+                            }
+                        }
+                        _ => string.push(self.parse_escape()?),
+                    }
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("Unterminated string".to_owned())),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        let mut array = Vec::new();
+        loop {
+            self.skip_whitespace_and_newlines();
+            if self.peek() == Some(']') {
+                self.position += 1;
+                return Ok(Value::Array(array));
+            }
+
+            let line = self.line;
+            let value = self.parse_value()?;
+            array.push(Entry { value, line });
+
+            self.skip_whitespace_and_newlines();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {}
+                Some(c) => {
+                    return Err(self.error(format!("Expected \",\" or \"]\", found \"{}\"", c)))
+                }
+                None => return Err(self.error("Unterminated array".to_owned())),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, ParseError> {
+        let mut table = Table::default();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Value::Table(table));
+        }
+
+        loop {
+            let line = self.line;
+            let key = self.parse_key()?;
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+
+            let (last, parents) = key.split_last().unwrap();
+            table
+                .descend(parents, line)?
+                .insert(last.clone(), Entry { value, line })?;
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Value::Table(table));
+                }
+                Some(c) => {
+                    return Err(self.error(format!("Expected \",\" or \"}}\", found \"{}\"", c)))
+                }
+                None => return Err(self.error("Unterminated inline table".to_owned())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Table, Value};
+
+    fn value<'a>(table: &'a Table, path: &str) -> &'a Value {
+        let mut value = None;
+        let mut table = table;
+        for key in path.split('.') {
+            let entry = &table
+                .get(key)
+                .unwrap_or_else(|| panic!("{} is missing", path))
+                .value;
+            if let Value::Table(inner) = entry {
+                table = inner;
+            }
+            value = Some(entry);
+        }
+        value.unwrap()
+    }
+
+    fn string<'a>(table: &'a Table, path: &str) -> &'a str {
+        match value(table, path) {
+            Value::String(string) => string,
+            value => panic!("{} is {}", path, value.type_name()),
+        }
+    }
+
+    fn integer(source: &str) -> Result<i64, (usize, String)> {
+        match parse(&format!("value = {}", source)) {
+            Ok(table) => match value(&table, "value") {
+                Value::Integer(integer) => Ok(*integer),
+                value => panic!("{} is {}", source, value.type_name()),
+            },
+            Err(error) => Err((error.line, error.message)),
+        }
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let error = parse(source).unwrap_err();
+        (error.line, error.message)
+    }
+
+    #[test]
+    fn tables() {
+        let table = parse(
+            "name = \"root\"\n\
+             [image]\n\
+             path = \"./os.img\"\n\
+             [components.kernel]\n\
+             path = \"kernel\"\n\
+             env.RUST_LOG = \"debug\"\n\
+             [components.bootloader]\n\
+             path = \"bootloader\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(string(&table, "name"), "root");
+        assert_eq!(string(&table, "image.path"), "./os.img");
+        assert_eq!(string(&table, "components.kernel.path"), "kernel");
+        assert_eq!(string(&table, "components.kernel.env.RUST_LOG"), "debug");
+        assert_eq!(string(&table, "components.bootloader.path"), "bootloader");
+
+        // Keys keep the order they are given in
+        let keys: Vec<&str> = match value(&table, "components") {
+            Value::Table(components) => components.iter().map(|(key, _)| key).collect(),
+            _ => unreachable!(),
+        };
+        assert_eq!(keys, ["kernel", "bootloader"]);
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let table = parse(
+            "[[profile]]\n\
+             name = \"debug\"\n\
+             [[profile]]\n\
+             name = \"release\"\n\
+             [profile.cargo]\n\
+             lto = true\n",
+        )
+        .unwrap();
+
+        let profiles = match value(&table, "profile") {
+            Value::Array(profiles) => profiles,
+            value => panic!("profile is {}", value.type_name()),
+        };
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[1].line, 3);
+        match &profiles[1].value {
+            Value::Table(profile) => {
+                assert_eq!(string(profile, "name"), "release");
+                assert!(matches!(value(profile, "cargo.lto"), Value::Boolean(true)));
+            }
+            value => panic!("a profile is {}", value.type_name()),
+        }
+    }
+
+    #[test]
+    fn inline_tables_and_arrays() {
+        let table = parse(
+            "env = { RUST_LOG = \"debug\", nested.key = 'x' }\n\
+             empty = {}\n\
+             flags = [\n\
+             \x20   \"-s\", # A comment\n\
+             \x20   [1, 2],\n\
+             ]\n",
+        )
+        .unwrap();
+
+        assert_eq!(string(&table, "env.RUST_LOG"), "debug");
+        assert_eq!(string(&table, "env.nested.key"), "x");
+        assert!(matches!(value(&table, "empty"), Value::Table(empty) if empty.iter().count() == 0));
+        match value(&table, "flags") {
+            Value::Array(flags) => {
+                assert_eq!(flags.len(), 2);
+                assert_eq!((flags[0].line, flags[1].line), (4, 5));
+                assert!(matches!(&flags[1].value, Value::Array(inner) if inner.len() == 2));
+            }
+            value => panic!("flags is {}", value.type_name()),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let table = parse(
+            "basic = \"tab\\there \\\"quoted\\\" \\\\ \\u00e9\\U0001F600\"\n\
+             literal = 'C:\\path\\no\\escapes'\n\
+             multiline = \"\"\"\n\
+             first\n\
+             second \\\n\
+             \x20   joined\"\"\"\n\
+             raw = '''\n\
+             keep \\n this'''\n\
+             \"quoted key\" = \"\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            string(&table, "basic"),
+            "tab\there \"quoted\" \\ \u{e9}\u{1F600}"
+        );
+        assert_eq!(string(&table, "literal"), "C:\\path\\no\\escapes");
+        assert_eq!(string(&table, "multiline"), "first\nsecond joined");
+        assert_eq!(string(&table, "raw"), "keep \\n this");
+        assert_eq!(string(&table, "quoted key"), "");
+
+        assert_eq!(error("a = \"\\q\"").1, "Invalid escape sequence \"\\q\"");
+        assert_eq!(
+            error("a = \"\\uD800\"").1,
+            "Invalid unicode escape \"\\uD800\""
+        );
+        assert_eq!(error("a = \"open\nb = 1").1, "Unterminated string");
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(integer("0"), Ok(0));
+        assert_eq!(integer("+17"), Ok(17));
+        assert_eq!(integer("-17"), Ok(-17));
+        assert_eq!(integer("1_000_000"), Ok(1_000_000));
+        assert_eq!(integer("0xDEAD_beef"), Ok(0xDEADBEEF));
+        assert_eq!(integer("0o755"), Ok(0o755));
+        assert_eq!(integer("0b1010"), Ok(10));
+        assert_eq!(integer("9223372036854775807"), Ok(i64::MAX));
+        assert_eq!(integer("-9223372036854775808"), Ok(i64::MIN));
+
+        assert!(integer("9223372036854775808").is_err());
+        assert!(integer("-9223372036854775809").is_err());
+        assert!(integer("--1").is_err());
+        assert!(integer("-+1").is_err());
+        assert!(integer("0x").is_err());
+        assert_eq!(
+            integer("1.5"),
+            Err((
+                1,
+                "Floating point values are not supported (\"1.5\")".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn error_lines() {
+        assert_eq!(error("a = 1\n\nb = ?\n").0, 3);
+        assert_eq!(
+            error("a = 1\n# comment\na = 2\n"),
+            (3, "\"a\" is defined more than once".to_owned())
+        );
+        assert_eq!(
+            error("[image]\n\n[image]\n"),
+            (3, "[image] is defined more than once".to_owned())
+        );
+        assert_eq!(
+            error("a = \"x\"\n[a.b]\n"),
+            (2, "\"a\" is not a table".to_owned())
+        );
+        assert_eq!(
+            error("a = [\n1,\n2\nb = 3"),
+            (4, "Expected \",\" or \"]\", found \"b\"".to_owned())
+        );
+        assert_eq!(
+            error("a = \"\"\"\none\ntwo\n"),
+            (4, "Unterminated string".to_owned())
+        );
+        assert_eq!(error("a = 1 b = 2\n"), (1, "Unexpected \"b\"".to_owned()));
+        assert_eq!(
+            error("\n\n= 1"),
+            (3, "Expected a key, found \"=\"".to_owned())
+        );
+    }
+}
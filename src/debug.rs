@@ -1,4 +1,5 @@
-use std::fmt::Debug;
+use crate::config::{ComponentKind, Config};
+use std::{fmt::Debug, path::PathBuf};
 
 #[derive(Debug)]
 pub enum DebugError {
@@ -7,22 +8,26 @@ pub enum DebugError {
     Debugger(std::io::Error),
 }
 
-pub fn debug() -> Result<(), DebugError> {
-    crate::image::build_image()?;
+pub fn debug(config: &Config) -> Result<(), DebugError> {
+    crate::image::build_image(config)?;
 
-    let mut emulator_command = std::process::Command::new(crate::config::EMULATOR);
-    emulator_command.args(crate::config::EMULATOR_FLAGS);
-    emulator_command.args(crate::config::EMULATOR_DEBUG_FLAGS);
-    emulator_command.stdout(std::process::Stdio::inherit());
-    emulator_command.stderr(std::process::Stdio::inherit());
-    emulator_command.stdin(std::process::Stdio::inherit());
+    let mut emulator_command = crate::run::emulator_command(config, &config.image_path());
+    emulator_command.args(["-S", "-gdb", &format!("tcp::{}", config.debugger.port)]);
     let mut emulator = match emulator_command.spawn() {
         Ok(child) => child,
         Err(error) => return Err(DebugError::Emulator(error)),
     };
 
-    let mut debugger_command = std::process::Command::new(crate::config::DEBUGGER);
-    debugger_command.args(crate::config::DEBUGGER_FLAGS);
+    let mut debugger_command = std::process::Command::new(&config.debugger.program);
+    if let Some(kernel) = kernel_symbols(config) {
+        debugger_command.arg("-s");
+        debugger_command.arg(kernel);
+    }
+    debugger_command.args([
+        "-ex",
+        &format!("target remote localhost:{}", config.debugger.port),
+    ]);
+    debugger_command.args(&config.debugger.flags);
     match debugger_command.status() {
         Ok(_) => Ok(()),
         Err(error) => {
@@ -32,6 +37,21 @@ pub fn debug() -> Result<(), DebugError> {
     }
 }
 
+// The kernel's symbols, or the installed kernel when they weren't split out of it. None if the
+// kernel component is no longer built by cargo.
+fn kernel_symbols(config: &Config) -> Option<PathBuf> {
+    let install = match &config.component("kernel")?.kind {
+        ComponentKind::Cargo(cargo) => cargo.install.as_ref()?,
+        ComponentKind::Brew | ComponentKind::Custom(_) => return None,
+    };
+
+    let symbols = config.symbols_path().join(install);
+    match symbols.exists() {
+        true => Some(symbols),
+        false => Some(config.sysroot().join(install)),
+    }
+}
+
 impl std::error::Error for DebugError {}
 
 impl std::fmt::Display for DebugError {
@@ -0,0 +1,417 @@
+use crate::{
+    arguments::Arguments,
+    config::{Arch, ComponentKind, Config, ConfigError, DEFAULT_ARCH},
+};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+// The number of problems found
+#[derive(Debug)]
+pub struct DoctorError(usize);
+
+// Tallies the checks as they are printed
+struct Doctor {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Doctor {
+    fn found(&self, what: &str) {
+        println!("       \x1B[32;1mFound\x1B[0m {}", what);
+    }
+
+    fn problem(&mut self, what: &str, fix: &str) {
+        println!("     \x1B[31;1mMissing\x1B[0m {}", what);
+        println!("             {}", fix);
+        self.problems += 1;
+    }
+
+    // Something only some commands need
+    fn warning(&mut self, what: &str, fix: &str) {
+        println!("     \x1B[33;1mMissing\x1B[0m {}", what);
+        println!("             {}", fix);
+        self.warnings += 1;
+    }
+
+    // Checks a program runs, printing the version it reports. Returns if it was found.
+    fn program(&mut self, program: &str, directory: &str, required: bool, fix: &str) -> bool {
+        match version(program, directory) {
+            Ok(version) => {
+                self.found(&version);
+                true
+            }
+            Err(error) => {
+                let what = match error.kind() {
+                    std::io::ErrorKind::NotFound => program.to_owned(),
+                    _ => format!("{} ({})", program, error),
+                };
+                match required {
+                    true => self.problem(&what, fix),
+                    false => self.warning(&what, fix),
+                }
+                false
+            }
+        }
+    }
+}
+
+// The first line a program outputs for --version, or its name if it outputs nothing
+fn version(program: &str, directory: &str) -> Result<String, std::io::Error> {
+    let output = Command::new(program)
+        .arg("--version")
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()?;
+
+    Ok([&output.stdout, &output.stderr]
+        .iter()
+        .filter_map(|output| {
+            String::from_utf8_lossy(output)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_owned)
+        })
+        .next()
+        .unwrap_or_else(|| program.to_owned()))
+}
+
+// The lines a successful command outputs, in the directory of the component running it
+fn output_lines(program: &str, arguments: &[&str], directory: &str) -> Option<Vec<String>> {
+    let output = Command::new(program)
+        .args(arguments)
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    match output.status.success() {
+        true => Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim().to_owned())
+                .collect(),
+        ),
+        false => None,
+    }
+}
+
+// Finds a program the way the shell would, relative to directory if it names a path
+fn find_program(program: &str, directory: &str) -> Option<PathBuf> {
+    let candidates = |directory: &Path| {
+        let path = directory.join(program);
+        match cfg!(windows) {
+            true => vec![directory.join(format!("{}.exe", program)), path],
+            false => vec![path],
+        }
+    };
+
+    if program.contains('/') || program.contains('\\') {
+        return candidates(Path::new(directory))
+            .into_iter()
+            .find(|path| path.is_file());
+    }
+
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .flat_map(|directory| candidates(&directory))
+            .find(|path| path.is_file())
+    })
+}
+
+// Where the firmware for each architecture is usually found
+fn firmware_fix(config: &Config) -> String {
+    let package = match config.arch {
+        Arch::X86_64 => "ovmf",
+        Arch::AArch64 => "qemu-efi-aarch64",
+        Arch::RiscV64 => "qemu-efi-riscv64",
+    };
+    format!(
+        "Copy {} from your distribution's {} package into the project root, or set firmware under [emulator] in {}",
+        config.arch.firmware(),
+        package,
+        crate::config::CONFIG_FILE
+    )
+}
+
+fn check_rust(doctor: &mut Doctor, config: &Config) {
+    let cargo_components: Vec<_> = config
+        .components
+        .iter()
+        .filter_map(|component| match &component.kind {
+            ComponentKind::Cargo(cargo) => Some((component, cargo)),
+            _ => None,
+        })
+        .collect();
+    if cargo_components.is_empty() {
+        return;
+    }
+
+    if !doctor.program(
+        "cargo",
+        ".",
+        true,
+        "Install rust using rustup from https://rustup.rs",
+    ) {
+        return;
+    }
+
+    // Targets and components are checked in each crate, which may pin its own toolchain
+    let mut rust_src_checked = false;
+    for (component, cargo) in cargo_components {
+        let target = match &cargo.target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let builtin = output_lines("rustc", &["--print", "target-list"], &component.path)
+            .unwrap_or_default()
+            .contains(target);
+
+        // Built in targets are installed through rustup, others are built with rust-src
+        let (what, installed, fix) = match builtin {
+            true => (
+                format!("target {} for {}", target, component.name),
+                output_lines(
+                    "rustup",
+                    &["target", "list", "--installed"],
+                    &component.path,
+                )
+                .map(|targets| targets.contains(target)),
+                format!("Run \"rustup target add {}\" in {}", target, component.path),
+            ),
+            false if rust_src_checked => continue,
+            false => {
+                rust_src_checked = true;
+                (
+                    format!(
+                        "rust-src, needed to build {} for {}",
+                        component.name, target
+                    ),
+                    output_lines(
+                        "rustup",
+                        &["component", "list", "--installed"],
+                        &component.path,
+                    )
+                    .map(|installed| {
+                        installed
+                            .iter()
+                            .any(|installed| installed.starts_with("rust-src"))
+                    }),
+                    format!(
+                        "Run \"rustup component add rust-src\" in {}",
+                        component.path
+                    ),
+                )
+            }
+        };
+
+        match installed {
+            Some(true) => doctor.found(&what),
+            Some(false) => doctor.problem(&what, &fix),
+            None => doctor.warning(
+                &format!("rustup, needed to check the {}", what),
+                "Install rust using rustup from https://rustup.rs, or make sure the target is installed",
+            ),
+        }
+    }
+}
+
+fn check_components(doctor: &mut Doctor, config: &Config) {
+    if config
+        .components
+        .iter()
+        .any(|component| matches!(component.kind, ComponentKind::Brew))
+    {
+        doctor.program(
+            "brew",
+            ".",
+            true,
+            "Install brew, the Lance OS userspace build tool, and make sure it is on your PATH",
+        );
+    }
+
+    for component in &config.components {
+        if let ComponentKind::Custom(custom) = &component.kind {
+            for command in [&custom.build, &custom.clean] {
+                let program = match command.first() {
+                    Some(program) => program,
+                    None => continue,
+                };
+
+                match find_program(program, &component.path) {
+                    Some(path) => doctor.found(&format!(
+                        "{} for {} ({})",
+                        program,
+                        component.name,
+                        path.to_string_lossy()
+                    )),
+                    None => doctor.problem(
+                        &format!("{} for {}", program, component.name),
+                        &format!(
+                            "Install {} or change the commands of [components.{}] in {}",
+                            program,
+                            component.name,
+                            crate::config::CONFIG_FILE
+                        ),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn check_emulator(doctor: &mut Doctor, config: &Config) {
+    doctor.program(
+        &config.emulator.program,
+        ".",
+        true,
+        &format!(
+            "Install QEMU, which provides {}, or set program under [emulator] in {}",
+            config.emulator.program,
+            crate::config::CONFIG_FILE
+        ),
+    );
+
+    match Path::new(&config.emulator.firmware).is_file() {
+        true => doctor.found(&format!("firmware {}", config.emulator.firmware)),
+        false => doctor.problem(
+            &format!("firmware {}", config.emulator.firmware),
+            &firmware_fix(config),
+        ),
+    }
+
+    // KVM is only required when the emulator is told to use it
+    if !cfg!(target_os = "linux") {
+        return;
+    }
+    let required = config
+        .emulator
+        .flags
+        .iter()
+        .any(|flag| flag.contains("kvm"));
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/kvm")
+    {
+        Ok(_) => doctor.found("KVM (/dev/kvm)"),
+        Err(error) => {
+            let fix = match error.kind() {
+                std::io::ErrorKind::NotFound => {
+                    "Enable virtualization in your firmware settings and load the kvm module"
+                }
+                _ => "Add yourself to the kvm group with \"sudo usermod -aG kvm $USER\", then log in again",
+            };
+            let what = format!("access to KVM ({})", error);
+            match required {
+                true => doctor.problem(&what, fix),
+                false => doctor.warning(&(what + ", QEMU will run without acceleration"), fix),
+            }
+        }
+    }
+}
+
+// Runs even when the configuration can't be loaded, checking what it can with the default one
+pub fn doctor(
+    config: Result<Config, ConfigError>,
+    arguments: &Arguments,
+) -> Result<(), DoctorError> {
+    let mut doctor = Doctor {
+        problems: 0,
+        warnings: 0,
+    };
+
+    let config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            println!("Checking the configuration:");
+            println!("     \x1B[31;1mInvalid\x1B[0m configuration ({})", error);
+            println!(
+                "             Fix it, until then the checks below use the default configuration"
+            );
+            doctor.problems += 1;
+            println!();
+
+            let arch = arguments
+                .arch
+                .as_deref()
+                .and_then(Arch::parse)
+                .unwrap_or(DEFAULT_ARCH);
+            Config::parse("", arch).unwrap()
+        }
+    };
+    let config = &config;
+
+    println!(
+        "Checking the tools needed to build ({}):",
+        config.variant_name()
+    );
+    check_rust(&mut doctor, config);
+    check_components(&mut doctor, config);
+    if config.symbols.split {
+        doctor.program(
+            &config.symbols.objcopy,
+            ".",
+            true,
+            &format!(
+                "Install binutils, set objcopy = \"llvm-objcopy\" under [symbols] in {} to use LLVM's, or set split = false",
+                crate::config::CONFIG_FILE
+            ),
+        );
+    }
+
+    println!();
+    println!("Checking the tools needed to run:");
+    check_emulator(&mut doctor, config);
+    doctor.program(
+        &config.debugger.program,
+        ".",
+        false,
+        &format!(
+            "Install gdb to use {}, or set program under [debugger] in {}",
+            crate::Command::Debug,
+            crate::config::CONFIG_FILE
+        ),
+    );
+    doctor.program(
+        &config.vbox.program,
+        ".",
+        false,
+        &format!(
+            "Install VirtualBox to use {}, or set program under [vbox] in {}",
+            crate::Command::VBox,
+            crate::config::CONFIG_FILE
+        ),
+    );
+
+    println!();
+    match doctor.problems {
+        0 => {
+            println!(
+                "No problems found{}",
+                match doctor.warnings {
+                    0 => String::new(),
+                    1 => ", 1 optional tool is missing".to_owned(),
+                    warnings => format!(", {} optional tools are missing", warnings),
+                }
+            );
+            Ok(())
+        }
+        problems => Err(DoctorError(problems)),
+    }
+}
+
+impl std::error::Error for DoctorError {}
+
+impl std::fmt::Display for DoctorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            1 => write!(f, "1 problem was found"),
+            problems => write!(f, "{} problems were found", problems),
+        }
+    }
+}
@@ -0,0 +1,429 @@
+// Reads just enough of ELF files to tell what they contain
+
+use std::convert::TryFrom;
+
+const MAGIC: &[u8] = b"\x7FELF";
+
+pub const TYPE_EXECUTABLE: u16 = 2;
+pub const TYPE_SHARED: u16 = 3;
+
+const SECTION_SYMBOLS: u32 = 2;
+
+pub const SYMBOL_FUNCTION: u8 = 2;
+
+pub const SEGMENT_LOAD: u32 = 1;
+pub const SEGMENT_EXECUTE: u32 = 1; // A flag of a segment
+
+pub struct Header {
+    pub wide: bool, // 64-bit
+    pub os_abi: u8,
+    pub kind: u16,
+    pub machine: u16,
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+}
+
+pub struct Segment {
+    pub kind: u32,
+    pub flags: u32,
+    pub address: u64,
+    pub size: u64, // In memory
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub kind: u8,
+    pub address: u64,
+    pub size: u64,
+}
+
+// Reads integers of either endianness from anywhere in a file
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if !is_elf(data) {
+            return None;
+        }
+
+        Some(Reader {
+            data,
+            big_endian: match data.get(5)? {
+                1 => false,
+                2 => true,
+                _ => return None,
+            },
+        })
+    }
+
+    fn wide(&self) -> Option<bool> {
+        match self.data.get(4)? {
+            1 => Some(false),
+            2 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn read(&self, offset: usize, size: usize) -> Option<u64> {
+        let bytes = self.data.get(offset..offset.checked_add(size)?)?;
+        let mut value: u64 = 0;
+        for i in 0..size {
+            let byte = match self.big_endian {
+                true => bytes[i],
+                false => bytes[size - 1 - i],
+            };
+            value = (value << 8) | byte as u64;
+        }
+        Some(value)
+    }
+
+    fn offset(&self, offset: usize, size: usize) -> Option<usize> {
+        usize::try_from(self.read(offset, size)?).ok()
+    }
+
+    // Reads a field of a header found elsewhere in the file, whose base may be anything
+    fn field(&self, base: usize, field: usize, size: usize) -> Option<u64> {
+        self.read(base.checked_add(field)?, size)
+    }
+
+    fn field_offset(&self, base: usize, field: usize, size: usize) -> Option<usize> {
+        self.offset(base.checked_add(field)?, size)
+    }
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// The file header and program headers, None if the file isn't an ELF file or is truncated
+pub fn parse_header(data: &[u8]) -> Option<Header> {
+    let reader = Reader::new(data)?;
+    let wide = reader.wide()?;
+
+    // Offsets within the file header and each program header differ between 32 and 64 bits
+    let (entry, table, entry_size, count) = match wide {
+        true => (
+            reader.read(0x18, 8)?,
+            reader.offset(0x20, 8)?,
+            reader.offset(0x36, 2)?,
+            reader.offset(0x38, 2)?,
+        ),
+        false => (
+            reader.read(0x18, 4)?,
+            reader.offset(0x1C, 4)?,
+            reader.offset(0x2A, 2)?,
+            reader.offset(0x2C, 2)?,
+        ),
+    };
+
+    let mut segments = Vec::with_capacity(count);
+    for index in 0..count {
+        let base = table.checked_add(index.checked_mul(entry_size)?)?;
+        segments.push(match wide {
+            true => Segment {
+                kind: reader.read(base, 4)? as u32,
+                flags: reader.field(base, 0x04, 4)? as u32,
+                address: reader.field(base, 0x10, 8)?,
+                size: reader.field(base, 0x28, 8)?,
+            },
+            false => Segment {
+                kind: reader.read(base, 4)? as u32,
+                flags: reader.field(base, 0x18, 4)? as u32,
+                address: reader.field(base, 0x08, 4)?,
+                size: reader.field(base, 0x14, 4)?,
+            },
+        });
+    }
+
+    Some(Header {
+        wide,
+        os_abi: *data.get(7)?,
+        kind: reader.read(0x10, 2)? as u16,
+        machine: reader.read(0x12, 2)? as u16,
+        entry,
+        segments,
+    })
+}
+
+struct SectionHeader {
+    name: usize, // Offset into the section names
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+}
+
+// Every section header along with the index of the section holding their names
+fn section_headers(reader: &Reader) -> Option<(Vec<SectionHeader>, usize)> {
+    let wide = reader.wide()?;
+
+    // Offsets within the file header and each section header differ between 32 and 64 bits
+    let (table, entry_size, count, names_index) = match wide {
+        true => (
+            reader.offset(0x28, 8)?,
+            reader.offset(0x3A, 2)?,
+            reader.offset(0x3C, 2)?,
+            reader.offset(0x3E, 2)?,
+        ),
+        false => (
+            reader.offset(0x20, 4)?,
+            reader.offset(0x2E, 2)?,
+            reader.offset(0x30, 2)?,
+            reader.offset(0x32, 2)?,
+        ),
+    };
+
+    let mut sections = Vec::with_capacity(count);
+    for index in 0..count {
+        let base = table.checked_add(index.checked_mul(entry_size)?)?;
+        sections.push(match wide {
+            true => SectionHeader {
+                name: reader.offset(base, 4)?,
+                kind: reader.field(base, 0x04, 4)? as u32,
+                offset: reader.field_offset(base, 0x18, 8)?,
+                size: reader.field_offset(base, 0x20, 8)?,
+                link: reader.field_offset(base, 0x28, 4)?,
+            },
+            false => SectionHeader {
+                name: reader.offset(base, 4)?,
+                kind: reader.field(base, 0x04, 4)? as u32,
+                offset: reader.field_offset(base, 0x10, 4)?,
+                size: reader.field_offset(base, 0x14, 4)?,
+                link: reader.field_offset(base, 0x18, 4)?,
+            },
+        });
+    }
+    Some((sections, names_index))
+}
+
+impl SectionHeader {
+    fn contents<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        data.get(self.offset..self.offset.checked_add(self.size)?)
+    }
+}
+
+// A string terminated by a null byte, starting at offset in a string table
+fn string_at(strings: &[u8], offset: usize) -> Option<String> {
+    let string = strings.get(offset..)?;
+    let end = string
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(string.len());
+    Some(String::from_utf8_lossy(&string[..end]).into_owned())
+}
+
+// The names of every section, None if the file isn't an ELF file or is truncated
+pub fn section_names(data: &[u8]) -> Option<Vec<String>> {
+    let reader = Reader::new(data)?;
+    let (sections, names_index) = section_headers(&reader)?;
+    if sections.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let names = sections.get(names_index)?.contents(data)?;
+    sections
+        .iter()
+        .map(|section| string_at(names, section.name))
+        .collect()
+}
+
+// The symbols defined by a file, None if the file isn't an ELF file, is truncated or has no
+// symbol table
+pub fn symbols(data: &[u8]) -> Option<Vec<Symbol>> {
+    let reader = Reader::new(data)?;
+    let wide = reader.wide()?;
+    let (sections, _) = section_headers(&reader)?;
+    let table = sections
+        .iter()
+        .find(|section| section.kind == SECTION_SYMBOLS)?;
+    let names = sections.get(table.link)?.contents(data)?;
+
+    let entry_size = match wide {
+        true => 24,
+        false => 16,
+    };
+    let mut symbols = Vec::new();
+    for index in 0..table.size / entry_size {
+        let base = table.offset.checked_add(index.checked_mul(entry_size)?)?;
+        let (info, section, address, size) = match wide {
+            true => (
+                reader.field(base, 0x04, 1)?,
+                reader.field(base, 0x06, 2)?,
+                reader.field(base, 0x08, 8)?,
+                reader.field(base, 0x10, 8)?,
+            ),
+            false => (
+                reader.field(base, 0x0C, 1)?,
+                reader.field(base, 0x0E, 2)?,
+                reader.field(base, 0x04, 4)?,
+                reader.field(base, 0x08, 4)?,
+            ),
+        };
+
+        // Undefined symbols are in section 0
+        if section == 0 {
+            continue;
+        }
+
+        symbols.push(Symbol {
+            name: string_at(names, reader.offset(base, 4)?)?,
+            kind: (info & 0xF) as u8,
+            address,
+            size,
+        });
+    }
+    Some(symbols)
+}
+
+// Whether an ELF file still has its DWARF debug information
+pub fn has_debug_info(data: &[u8]) -> bool {
+    section_names(data)
+        .map(|names| names.iter().any(|name| name.starts_with(".debug_")))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{has_debug_info, parse_header, section_names, symbols, SYMBOL_FUNCTION};
+
+    fn put(data: &mut [u8], offset: usize, value: u64, size: usize) {
+        data[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    // A little-endian 64-bit executable with one segment and a symbol table holding the symbols
+    // given, as their names, kinds, addresses and sizes
+    pub fn executable(symbol_table: &[(&str, u8, u64, u64)]) -> Vec<u8> {
+        let mut names = vec![0];
+        let mut table = vec![0; 24];
+        for (name, kind, address, size) in symbol_table {
+            let mut symbol = vec![0; 24];
+            put(&mut symbol, 0, names.len() as u64, 4);
+            symbol[0x04] = 0x10 | kind; // Global
+            put(&mut symbol, 0x06, 1, 2);
+            put(&mut symbol, 0x08, *address, 8);
+            put(&mut symbol, 0x10, *size, 8);
+            table.extend_from_slice(&symbol);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        let section_names = b"\0.symtab\0.strtab\0.shstrtab\0";
+
+        let table_offset = 120;
+        let names_offset = table_offset + table.len();
+        let section_names_offset = names_offset + names.len();
+        let sections = (section_names_offset + section_names.len()).next_multiple_of(8);
+
+        let mut data = vec![0; sections + 4 * 64];
+        data[..8].copy_from_slice(b"\x7FELF\x02\x01\x01\x00");
+        put(&mut data, 0x10, 2, 2); // Executable
+        put(&mut data, 0x12, 62, 2); // x86_64
+        put(&mut data, 0x18, 0x1010, 8); // Entry
+        put(&mut data, 0x20, 64, 8); // Program headers
+        put(&mut data, 0x28, sections as u64, 8); // Section headers
+        put(&mut data, 0x36, 56, 2);
+        put(&mut data, 0x38, 1, 2);
+        put(&mut data, 0x3A, 64, 2);
+        put(&mut data, 0x3C, 4, 2);
+        put(&mut data, 0x3E, 3, 2); // Section names
+
+        put(&mut data, 64, 1, 4); // Loaded
+        put(&mut data, 64 + 0x04, 5, 4); // Readable and executable
+        put(&mut data, 64 + 0x10, 0x1000, 8);
+        put(&mut data, 64 + 0x28, 0x100, 8);
+
+        data[table_offset..names_offset].copy_from_slice(&table);
+        data[names_offset..section_names_offset].copy_from_slice(&names);
+        data[section_names_offset..section_names_offset + section_names.len()]
+            .copy_from_slice(section_names);
+
+        // Name, kind, offset, size and link of each section after the null section
+        for (index, (name, kind, offset, size, link)) in [
+            (1, 2, table_offset, table.len(), 2),
+            (9, 3, names_offset, names.len(), 0),
+            (17, 3, section_names_offset, section_names.len(), 0),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let base = sections + (index + 1) * 64;
+            put(&mut data, base, *name, 4);
+            put(&mut data, base + 0x04, *kind, 4);
+            put(&mut data, base + 0x18, *offset as u64, 8);
+            put(&mut data, base + 0x20, *size as u64, 8);
+            put(&mut data, base + 0x28, *link, 4);
+        }
+        data
+    }
+
+    fn sample() -> Vec<u8> {
+        executable(&[("kmain", SYMBOL_FUNCTION, 0x1000, 0x20)])
+    }
+
+    #[test]
+    fn parses_sample() {
+        let data = sample();
+        let header = parse_header(&data).unwrap();
+        assert!(header.wide);
+        assert_eq!((header.kind, header.machine, header.entry), (2, 62, 0x1010));
+        assert_eq!(header.segments.len(), 1);
+        let segment = &header.segments[0];
+        assert_eq!(
+            (segment.kind, segment.flags, segment.address, segment.size),
+            (1, 5, 0x1000, 0x100)
+        );
+
+        assert_eq!(
+            section_names(&data).unwrap(),
+            ["", ".symtab", ".strtab", ".shstrtab"]
+        );
+        assert!(!has_debug_info(&data));
+
+        let symbols = symbols(&data).unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "kmain");
+        assert_eq!(
+            (symbols[0].kind, symbols[0].address, symbols[0].size),
+            (2, 0x1000, 0x20)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let data = sample();
+        for length in 0..data.len() {
+            let data = &data[..length];
+            parse_header(data);
+            section_names(data);
+            symbols(data);
+        }
+        assert!(parse_header(&data[..63]).is_none());
+        assert!(symbols(&data[..400]).is_none());
+    }
+
+    // Offsets read from the file are added to, which mustn't overflow
+    #[test]
+    fn overflowing_offsets() {
+        for offset in [u64::MAX, u64::MAX - 3, u64::MAX - 0x20] {
+            let mut data = sample();
+            put(&mut data, 0x20, offset, 8);
+            assert!(parse_header(&data).is_none());
+
+            let mut data = sample();
+            put(&mut data, 0x28, offset, 8);
+            assert!(section_names(&data).is_none());
+            assert!(symbols(&data).is_none());
+
+            let mut data = sample();
+            put(&mut data, 208 + 64 + 0x18, offset, 8);
+            assert!(symbols(&data).is_none());
+        }
+
+        let mut data = sample();
+        put(&mut data, 0x38, 0xFFFF, 2);
+        put(&mut data, 0x36, 0xFFFF, 2);
+        assert!(parse_header(&data).is_none());
+    }
+}
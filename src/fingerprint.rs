@@ -0,0 +1,282 @@
+use crate::config::Config;
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// Describes the state of a build step, the step is skipped while its fingerprint is unchanged
+#[derive(PartialEq, Eq)]
+pub struct Fingerprint {
+    pub inputs: u64,
+    pub outputs: u64,
+}
+
+// FNV-1a, which is stable between versions of losb unlike the hashers of std
+pub struct Hasher(u64);
+
+impl Hasher {
+    pub fn new() -> Self {
+        Hasher(0xCBF29CE484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001B3);
+        }
+    }
+
+    // Strings are terminated so neighbouring strings can't run into each other
+    pub fn write_str(&mut self, string: &str) {
+        self.write(string.as_bytes());
+        self.write(&[0]);
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    // Hashes the name, size and modification time of a file, or of every file below a
+    // directory in a fixed order. Missing files hash differently from every present one.
+    pub fn write_tree(&mut self, path: &Path, skip: &dyn Fn(&Path) -> bool) {
+        let metadata = match path.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                self.write_str("missing");
+                return;
+            }
+        };
+
+        if !metadata.is_dir() {
+            self.write_u64(metadata.len());
+            self.write_u64(
+                metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|modified| modified.as_nanos() as u64)
+                    .unwrap_or(0),
+            );
+            return;
+        }
+
+        let mut entries: Vec<_> = match read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| !skip(path))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        entries.sort();
+
+        self.write_str("directory");
+        for entry in entries {
+            self.write_str(&entry.file_name().unwrap_or_default().to_string_lossy());
+            self.write_tree(&entry, skip);
+        }
+        self.write_str("end");
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Where the fingerprints of everything built into the selected sysroot are kept
+pub fn directory(config: &Config) -> PathBuf {
+    Path::new(crate::config::STATE_DIR)
+        .join("fingerprints")
+        .join(config.sysroot().file_name().unwrap_or_default())
+}
+
+pub fn load(path: &Path) -> Option<Fingerprint> {
+    let contents = read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+    let mut value = |name: &str| {
+        let line = lines.next()?.strip_prefix(name)?.strip_prefix(' ')?;
+        u64::from_str_radix(line, 16).ok()
+    };
+
+    Some(Fingerprint {
+        inputs: value("inputs")?,
+        outputs: value("outputs")?,
+    })
+}
+
+pub fn save(path: &Path, fingerprint: &Fingerprint) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+
+    std::fs::write(
+        path,
+        format!(
+            "inputs {:016x}\noutputs {:016x}\n",
+            fingerprint.inputs, fingerprint.outputs
+        ),
+    )
+}
+
+// Forgets the fingerprints of one sysroot, or of all of them, so everything is built again
+pub fn clear(config: Option<&Config>) -> Result<(), std::io::Error> {
+    let path = match config {
+        Some(config) => directory(config),
+        None => Path::new(crate::config::STATE_DIR).join("fingerprints"),
+    };
+
+    match path.exists() {
+        true => remove_dir_all(path),
+        false => Ok(()),
+    }
+}
+
+// Forgets a component in every sysroot so it is built again
+pub fn forget(name: &str) -> Result<(), std::io::Error> {
+    let entries = match read_dir(Path::new(crate::config::STATE_DIR).join("fingerprints")) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+
+    for entry in entries {
+        let path = entry?.path().join(name);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Entries which never affect a build, such as version control and cargo's output
+pub fn skip_source(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.starts_with('.') || name == "target",
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, save, skip_source, Fingerprint, Hasher};
+    use std::{
+        fs::{create_dir_all, write, File},
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn skipped_sources() {
+        for path in ["target", "./kernel/target", ".git", "src/.hidden.rs"] {
+            assert!(skip_source(Path::new(path)), "{}", path);
+        }
+        // A component in the project root is still hashed
+        for path in [
+            ".",
+            "src",
+            "kernel/src/target.rs",
+            "targets",
+            "Cargo.toml",
+            "/",
+        ] {
+            assert!(!skip_source(Path::new(path)), "{}", path);
+        }
+    }
+
+    fn hash(path: &Path) -> u64 {
+        let mut hasher = Hasher::new();
+        hasher.write_tree(path, &skip_source);
+        hasher.finish()
+    }
+
+    fn set_modified(path: &Path, seconds: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn tree() {
+        let directory =
+            std::env::temp_dir().join(format!("losb-fingerprint-{}", std::process::id()));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        let source = directory.join("src/main.rs");
+        create_dir_all(source.parent().unwrap()).unwrap();
+        write(&source, "fn main() {}").unwrap();
+        set_modified(&source, 1_000_000);
+
+        let original = hash(&directory);
+        assert_eq!(hash(&directory), original);
+
+        // Build output and hidden files never change it
+        create_dir_all(directory.join("target/debug")).unwrap();
+        write(directory.join("target/debug/main"), "binary").unwrap();
+        create_dir_all(directory.join(".git")).unwrap();
+        write(directory.join(".git/HEAD"), "ref").unwrap();
+        write(directory.join("src/.main.rs.swp"), "swap").unwrap();
+        assert_eq!(hash(&directory), original);
+
+        // An edit changes the size or the time it was modified
+        write(&source, "fn main() { }").unwrap();
+        set_modified(&source, 1_000_000);
+        let edited = hash(&directory);
+        assert_ne!(edited, original);
+        write(&source, "fn main() {}").unwrap();
+        set_modified(&source, 1_000_000);
+        assert_eq!(hash(&directory), original);
+        write(&source, "fn main() {}").unwrap();
+        set_modified(&source, 1_000_001);
+        assert_ne!(hash(&directory), original);
+        set_modified(&source, 1_000_000);
+
+        // So do new and renamed files, and empty directories
+        let added = directory.join("src/lib.rs");
+        write(&added, "").unwrap();
+        assert_ne!(hash(&directory), original);
+        std::fs::remove_file(&added).unwrap();
+        std::fs::rename(&source, directory.join("src/lib.rs")).unwrap();
+        assert_ne!(hash(&directory), original);
+        std::fs::rename(directory.join("src/lib.rs"), &source).unwrap();
+        create_dir_all(directory.join("src/empty")).unwrap();
+        assert_ne!(hash(&directory), original);
+        std::fs::remove_dir(directory.join("src/empty")).unwrap();
+        assert_eq!(hash(&directory), original);
+
+        // A missing path hashes differently from an empty file or directory
+        let missing = hash(&directory.join("missing"));
+        write(directory.join("empty"), "").unwrap();
+        assert_ne!(missing, hash(&directory.join("empty")));
+        assert_ne!(missing, hash(&directory.join("target")));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn saved() {
+        let path = std::env::temp_dir().join(format!(
+            "losb-fingerprint-saved-{}/kernel",
+            std::process::id()
+        ));
+        let fingerprint = Fingerprint {
+            inputs: 0x0123_4567_89AB_CDEF,
+            outputs: 7,
+        };
+        save(&path, &fingerprint).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "inputs 0123456789abcdef\noutputs 0000000000000007\n"
+        );
+        assert!(load(&path) == Some(fingerprint));
+
+        write(&path, "inputs 1\n").unwrap();
+        assert!(load(&path).is_none());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(load(&path).is_none());
+    }
+}
@@ -4,7 +4,10 @@ pub fn display_help() {
     println!("Build utility for Lance OS\n");
 
     println!("\x1B[1mUsage:\x1B[0m");
-    println!("    {} [command]\n", std::env::args().next().unwrap());
+    println!(
+        "    {} [command] [configuration] [components...] [options]\n",
+        std::env::args().next().unwrap()
+    );
 
     println!("\x1B[1mCommands:\x1B[0m");
     println!("    {}\t Builds everything", Command::Build);
@@ -29,17 +32,42 @@ pub fn display_help() {
         Command::Debug,
         Command::BuildImage
     );
+    println!(
+        "    {}\t Checks the tools needed to build and run are installed",
+        Command::Doctor
+    );
+    println!(
+        "    {}\t Performs {}, then packs the image into a .ova appliance",
+        Command::ExportOVA,
+        Command::BuildImage
+    );
     println!(
         "    {}\t Displays information about this program",
         Command::Help
     );
+    println!(
+        "    {} <name>\t Creates a library in the libraries component and adds it to the build",
+        Command::NewLibrary
+    );
+    println!(
+        "    {} <name>\t Creates a program in the programs component and adds it to the build",
+        Command::NewProgram
+    );
     println!(
         "    {}\t\t Performs {}, then runs qemu (Linux Only)",
         Command::Run,
         Command::BuildImage
     );
     println!(
-        "    {}\t Performs {}, then converts the image into a .vdi",
+        "    {}\t Lists the components in build order and whether they are installed",
+        Command::Status
+    );
+    println!(
+        "    {}\t Shows the timings of the last build, or with --compare how they changed",
+        Command::Timings
+    );
+    println!(
+        "    {}\t Performs {}, then converts the image into a .vdi and a .vbox machine",
         Command::VBox,
         Command::BuildImage
     );
@@ -47,7 +75,67 @@ pub fn display_help() {
         "    {}\t Displays the version of this program",
         Command::Version
     );
+    println!(
+        "    {} [{}|{}|{}]\t Repeats a command whenever the sources change, {} by default",
+        Command::Watch,
+        Command::Build,
+        Command::BuildImage,
+        Command::Run,
+        Command::Build
+    );
+
+    println!();
+    println!("\x1B[1mConfigurations:\x1B[0m");
+    println!("    debug\t Unoptimized build with debug information");
+    println!("    release\t Optimized build");
+    println!(
+        "    Any profile declared under [profiles] in {}",
+        crate::config::CONFIG_FILE
+    );
+
+    println!();
+    println!("\x1B[1mComponents:\x1B[0m");
+    println!(
+        "    Builds or cleans only the named components, along with those which depend on them"
+    );
+
+    println!();
+    println!("\x1B[1mOptions:\x1B[0m");
+    println!(
+        "    --arch <arch>\t Builds for and emulates {}",
+        crate::config::ARCHES
+            .iter()
+            .map(|arch| arch.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    println!("    --only <names>\t Same as naming components, separated by commas");
+    println!("    -q, --quiet\t Prints only status lines, and the output of whatever fails");
+    println!("    -v, --verbose\t Prints every command run along with its output");
+    println!("    --features <features>\t Enables cargo features, as feature or component:feature");
+    println!(
+        "    --no-default-features[=<components>]\t Disables the default cargo features, of the components given"
+    );
+    println!(
+        "    --env <KEY=VALUE>\t Sets a variable while building, optionally as component:KEY=VALUE"
+    );
+    println!("    --timings[=json]\t Prints how long each part of the build took");
+    println!(
+        "    --compare\t With {}, compares the last build to the one before",
+        Command::Timings
+    );
+    println!(
+        "    --previous\t With {}, boots the sysroot from before the last build without building",
+        Command::Run
+    );
+    println!(
+        "    --cmdline <line>\t With {}, boots with this kernel command line rather than the configured one",
+        Command::Run
+    );
+    println!("    -j, --jobs <n>\t Builds up to n components at once, defaults to the CPU count");
 
     println!();
     println!("Default Command - {}", crate::config::DEFAULT_COMMAND);
+    println!("Default Configuration - {}", crate::config::DEFAULT_PROFILE);
+    println!("Default Architecture - {}", crate::config::DEFAULT_ARCH);
 }
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 // Calculates the volume size in bytes of the volume needed to hold the directory
-pub fn volume_size(directory_path: &Path) -> Result<usize, ()> {
+pub fn volume_size(directory_path: &Path, minimum_size: usize) -> Result<usize, ()> {
     print!(
         " \x1B[36;1mCalculating\x1B[0m volume size for {} . . .",
         directory_path.to_string_lossy()
@@ -14,6 +14,6 @@ pub fn volume_size(directory_path: &Path) -> Result<usize, ()> {
         directory_path.to_string_lossy()
     );
 
-    // TEMPORARY: returns the minimum volume size of 128 MB
-    Ok(128 * 1024 * 1024)
+    // TEMPORARY: returns the minimum volume size (in MB) from the configuration
+    Ok(minimum_size * 1024 * 1024)
 }
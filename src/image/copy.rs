@@ -236,7 +236,9 @@ impl Copier {
                     let checksum = {
                         let mut sum: u8 = 0;
                         for byte in name {
-                            sum = if sum & 1 != 0 { 0x80 } else { 0 } + sum.wrapping_shr(1) + byte;
+                            sum = if sum & 1 != 0 { 0x80u8 } else { 0 }
+                                .wrapping_add(sum.wrapping_shr(1))
+                                .wrapping_add(byte);
                         }
 
                         sum
@@ -285,7 +287,7 @@ impl Copier {
                         entry_index += 1;
 
                         current_entry -= 1;
-                        current_offset -= 13;
+                        current_offset = current_offset.saturating_sub(13);
                     }
                 } else {
                     let mut i = 0;
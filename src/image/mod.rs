@@ -1,40 +1,219 @@
-use std::path::Path;
+use crate::{
+    config::Config,
+    fingerprint::{self, Fingerprint, Hasher},
+    timings::Timings,
+};
+use std::path::{Path, PathBuf};
 
 mod calculate;
 mod copy;
 mod create;
 mod fat32;
+mod patch;
 
 #[derive(Debug)]
 pub enum BuildImageError {
     BuildError(crate::build::BuildError),
     CreateImageError(std::io::Error),
     SysrootError(std::io::Error),
+    Fingerprint(std::io::Error),
+    Timings(std::io::Error),
+    NoPrevious(PathBuf),
+    BootConfig(PathBuf, std::io::Error),
+    NoBootConfig,
 }
 
-pub fn build_image() -> Result<(), BuildImageError> {
-    crate::build::build()?;
+pub fn build_image(config: &Config) -> Result<(), BuildImageError> {
+    let timings = Timings::new();
+    crate::build::build_components(config, &timings)?;
 
     println!();
-    let sysroot_path = Path::new(crate::config::SYSROOT_DIR);
-    let target_path = Path::new(crate::config::TARGET_IMG);
+    let sysroot_path = config.sysroot();
+    update_image(
+        config,
+        &sysroot_path,
+        &config.image_path(),
+        "image",
+        &timings,
+    )?;
+    match timings.finish(config) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(BuildImageError::Timings(error)),
+    }
+}
+
+// Makes an image of the sysroot from before the last build, without building anything
+pub fn build_previous_image(config: &Config) -> Result<PathBuf, BuildImageError> {
+    let sysroot_path = config.previous_sysroot();
+    if !sysroot_path.exists() {
+        return Err(BuildImageError::NoPrevious(sysroot_path));
+    }
+
+    let target_path = config.previous_image();
+    update_image(
+        config,
+        &sysroot_path,
+        &target_path,
+        "previous-image",
+        &Timings::new(),
+    )?;
+    Ok(target_path)
+}
+
+// Rewrites the boot configuration in an image already made for a kernel command line given when
+// running, leaving the rest of the image alone. Running without one puts the configured command
+// line back. An image which was fresh stays fresh.
+pub fn set_cmdline(
+    config: &Config,
+    previous: bool,
+    cmdline: Option<&str>,
+) -> Result<(), BuildImageError> {
+    if config.boot.path.is_empty() {
+        return match cmdline {
+            Some(_) => Err(BuildImageError::NoBootConfig),
+            None => Ok(()),
+        };
+    }
+
+    let (target_path, fingerprint_name) = match previous {
+        true => (config.previous_image(), "previous-image"),
+        false => (config.image_path(), "image"),
+    };
+    patch_boot_config(
+        config,
+        &target_path,
+        &fingerprint::directory(config).join(fingerprint_name),
+        cmdline,
+    )
+}
+
+fn patch_boot_config(
+    config: &Config,
+    target_path: &Path,
+    fingerprint_path: &Path,
+    cmdline: Option<&str>,
+) -> Result<(), BuildImageError> {
+    if config.boot.path.is_empty() {
+        return Ok(());
+    }
+
+    let error = |error| BuildImageError::BootConfig(target_path.to_owned(), error);
+
+    let contents = crate::build::boot_config(config, cmdline);
+    let path = Path::new(&config.boot.path);
+    let mut volume = patch::Volume::open(target_path).map_err(error)?;
+    match volume.read_file(path).map_err(error)? {
+        Some(existing) if existing == contents.as_bytes() => return Ok(()),
+        // An image made before the boot configuration was written is left as it was made
+        None if cmdline.is_none() => return Ok(()),
+        _ => {}
+    }
+
+    let fingerprint = fingerprint::load(fingerprint_path)
+        .filter(|fingerprint| fingerprint.outputs == image_outputs(target_path));
+
+    volume
+        .replace_file(path, contents.as_bytes())
+        .map_err(error)?;
+    println!(
+        "     \x1B[32;1mUpdated\x1B[0m {} in {} ({})",
+        config.boot.path,
+        target_path.to_string_lossy(),
+        match cmdline {
+            Some(cmdline) => format!("cmdline \"{}\"", cmdline),
+            None => "configured cmdline".to_owned(),
+        }
+    );
+
+    match fingerprint {
+        Some(fingerprint) => fingerprint::save(
+            fingerprint_path,
+            &Fingerprint {
+                inputs: fingerprint.inputs,
+                outputs: image_outputs(target_path),
+            },
+        )
+        .map_err(BuildImageError::Fingerprint),
+        None => Ok(()),
+    }
+}
+
+// Recreates an image from a sysroot, unless the sysroot hasn't changed
+fn update_image(
+    config: &Config,
+    sysroot_path: &Path,
+    target_path: &Path,
+    fingerprint_name: &str,
+    timings: &Timings,
+) -> Result<(), BuildImageError> {
+    // Skip the image while the sysroot it was made from is unchanged
+    let fingerprint_path = fingerprint::directory(config).join(fingerprint_name);
+    let mut inputs = Hasher::new();
+    inputs.write_str(&target_path.to_string_lossy());
+    inputs.write_u64(config.image.size as u64);
+    inputs.write_tree(sysroot_path, &|_| false);
+    let inputs = inputs.finish();
+
+    // A run given a kernel command line patches it into the image, which must not be kept
+    if target_path.exists()
+        && fingerprint::load(&fingerprint_path).map(|fingerprint| fingerprint.inputs)
+            == Some(inputs)
+    {
+        patch_boot_config(config, target_path, &fingerprint_path, None)?;
+    }
+
+    if fingerprint::load(&fingerprint_path)
+        == Some(Fingerprint {
+            inputs,
+            outputs: image_outputs(target_path),
+        })
+    {
+        println!(
+            "       \x1B[32;1mFresh\x1B[0m {}",
+            target_path.to_string_lossy()
+        );
+        return Ok(());
+    }
 
     // Calculate image size
-    let volume_size = calculate::volume_size(sysroot_path).unwrap();
+    let volume_size = timings
+        .time("size image".to_owned(), || {
+            calculate::volume_size(sysroot_path, config.image.size)
+        })
+        .unwrap();
 
     // Create blank FAT32 image
-    match create::create_image(volume_size, target_path) {
+    match timings.time("create image".to_owned(), || {
+        create::create_image(volume_size, target_path)
+    }) {
         Ok(()) => {}
         Err(error) => return Err(BuildImageError::CreateImageError(error)),
     };
 
     // Copy sysroot into the image
-    match copy::copy_directory(target_path, sysroot_path) {
+    match timings.time("copy sysroot into image".to_owned(), || {
+        copy::copy_directory(target_path, sysroot_path)
+    }) {
+        Ok(()) => {}
+        Err(error) => return Err(BuildImageError::SysrootError(error)),
+    }
+
+    let fingerprint = Fingerprint {
+        inputs,
+        outputs: image_outputs(target_path),
+    };
+    match fingerprint::save(&fingerprint_path, &fingerprint) {
         Ok(()) => Ok(()),
-        Err(error) => Err(BuildImageError::SysrootError(error)),
+        Err(error) => Err(BuildImageError::Fingerprint(error)),
     }
 }
 
+fn image_outputs(path: &Path) -> u64 {
+    let mut hasher = Hasher::new();
+    hasher.write_tree(path, &|_| false);
+    hasher.finish()
+}
+
 impl std::error::Error for BuildImageError {}
 
 impl std::fmt::Display for BuildImageError {
@@ -48,6 +227,23 @@ impl std::fmt::Display for BuildImageError {
                     format!("Unable to create blank image ({})", error),
                 BuildImageError::SysrootError(error) =>
                     format!("Unable to copy sysroot into image ({})", error),
+                BuildImageError::Fingerprint(error) =>
+                    format!("Unable to record what was built ({})", error),
+                BuildImageError::Timings(error) =>
+                    format!("Unable to record build timings ({})", error),
+                BuildImageError::NoPrevious(path) => format!(
+                    "There is no previous sysroot at {}, one is kept once a build replaces the sysroot",
+                    path.to_string_lossy()
+                ),
+                BuildImageError::BootConfig(path, error) => format!(
+                    "Unable to update the boot configuration in {} ({})",
+                    path.to_string_lossy(),
+                    error
+                ),
+                BuildImageError::NoBootConfig => format!(
+                    "A kernel command line needs a boot configuration, but path under [boot] of {} is empty",
+                    crate::config::CONFIG_FILE
+                ),
             }
         )
     }
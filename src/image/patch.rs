@@ -0,0 +1,426 @@
+use super::fat32;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const END_OF_CHAIN: u32 = 0x0FFFFFF8;
+const CLUSTER_MASK: u32 = 0x0FFFFFFF;
+const ENTRY_SIZE: usize = std::mem::size_of::<fat32::DirectoryEntry>();
+const DELETED: u8 = 0xE5;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x41615252;
+const FS_INFO_STRUCTURE_SIGNATURE: u32 = 0x61417272;
+const FS_INFO_UNKNOWN: u32 = 0xFFFFFFFF;
+
+// Reads and replaces single files in an image made by losb, for changes too small to remake it
+pub struct Volume {
+    file: File,
+    cluster_size: usize,
+    first_fat_sector: usize,
+    first_data_sector: usize,
+    fat_size: usize,
+    num_fats: usize,
+    root_cluster: u32,
+    clusters: u32,               // Including the two reserved at the start of the FAT
+    fs_info_sectors: Vec<usize>, // The FSInfo sector and its backup, when there is one
+}
+
+// Where a file's directory entry is in the image, and what it holds
+struct Found {
+    entry_offset: u64,
+    first_cluster: u32,
+    size: usize,
+}
+
+impl Volume {
+    pub fn open(image: &Path) -> Result<Self, std::io::Error> {
+        let mut file = OpenOptions::new().read(true).write(true).open(image)?;
+        let mut bpb = [0; fat32::BYTES_PER_SECTOR];
+        file.read_exact(&mut bpb)?;
+
+        let u16_at = |offset: usize| u16::from_le_bytes([bpb[offset], bpb[offset + 1]]) as usize;
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bpb[offset],
+                bpb[offset + 1],
+                bpb[offset + 2],
+                bpb[offset + 3],
+            ])
+        };
+
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = bpb[13] as usize;
+        let first_fat_sector = u16_at(14);
+        let num_fats = bpb[16] as usize;
+        let total_sectors = u32_at(32) as usize;
+        let fat_size = u32_at(36) as usize;
+        if bytes_per_sector != fat32::BYTES_PER_SECTOR || sectors_per_cluster == 0 || fat_size == 0
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} isn't a FAT32 image", image.to_string_lossy()),
+            ));
+        }
+
+        // The backup FSInfo follows the backup boot sector
+        let fs_info_sectors = [u16_at(48), u16_at(50).wrapping_add(1)]
+            .iter()
+            .copied()
+            .filter(|sector| (1..first_fat_sector).contains(sector))
+            .collect();
+
+        let first_data_sector = first_fat_sector + num_fats * fat_size;
+        Ok(Volume {
+            file,
+            cluster_size: bytes_per_sector * sectors_per_cluster,
+            first_fat_sector,
+            first_data_sector,
+            fat_size,
+            num_fats,
+            root_cluster: u32_at(44),
+            clusters: (total_sectors.saturating_sub(first_data_sector) / sectors_per_cluster + 2)
+                as u32,
+            fs_info_sectors,
+        })
+    }
+
+    // Reads a file by its path from the root of the volume, None when it doesn't exist
+    pub fn read_file(&mut self, path: &Path) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let found = match self.find(path)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let mut data = Vec::with_capacity(found.size);
+        for cluster in self.chain(found.first_cluster)? {
+            data.extend_from_slice(&self.read_cluster(cluster)?);
+        }
+        data.truncate(found.size);
+        Ok(Some(data))
+    }
+
+    // Replaces the contents of a file already in the volume, growing or shrinking its clusters
+    pub fn replace_file(&mut self, path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+        let found = self.find(path)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} isn't in the image", path.to_string_lossy()),
+            )
+        })?;
+
+        let mut chain = self.chain(found.first_cluster)?;
+        let needed = data.len().div_ceil(self.cluster_size);
+        let (mut allocated, mut freed) = (Vec::new(), Vec::new());
+        while chain.len() > needed {
+            let cluster = chain.pop().unwrap();
+            self.write_fat(cluster, 0)?;
+            freed.push(cluster);
+        }
+        while chain.len() < needed {
+            let cluster = self.free_cluster()?;
+            if let Some(last) = chain.last() {
+                self.write_fat(*last, cluster)?;
+            }
+            chain.push(cluster);
+            self.write_fat(cluster, CLUSTER_MASK)?;
+            allocated.push(cluster);
+        }
+        if let Some(last) = chain.last() {
+            self.write_fat(*last, CLUSTER_MASK)?;
+        }
+        self.update_fs_info(&allocated, &freed)?;
+
+        for (cluster, contents) in chain.iter().zip(data.chunks(self.cluster_size)) {
+            let mut buffer = contents.to_vec();
+            buffer.resize(self.cluster_size, 0);
+            self.write_cluster(*cluster, &buffer)?;
+        }
+
+        // The first cluster is split across the entry, with the file's size at its end
+        let first_cluster = chain.first().copied().unwrap_or(0);
+        let mut entry = [0; ENTRY_SIZE];
+        self.file.seek(SeekFrom::Start(found.entry_offset))?;
+        self.file.read_exact(&mut entry)?;
+        entry[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        self.file.seek(SeekFrom::Start(found.entry_offset))?;
+        self.file.write_all(&entry)?;
+        self.file.flush()
+    }
+
+    // Follows a path down from the root directory, matching long names when an entry has one
+    fn find(&mut self, path: &Path) -> Result<Option<Found>, std::io::Error> {
+        let mut directory = self.root_cluster;
+        let mut parts = path
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .filter(|part| part != "/" && part != ".")
+            .peekable();
+
+        while let Some(part) = parts.next() {
+            let found = match self.find_entry(directory, &part)? {
+                Some((found, is_directory)) if is_directory == parts.peek().is_some() => found,
+                _ => return Ok(None),
+            };
+
+            if parts.peek().is_none() {
+                return Ok(Some(found));
+            }
+            directory = found.first_cluster;
+        }
+        Ok(None)
+    }
+
+    fn find_entry(
+        &mut self,
+        directory: u32,
+        name: &str,
+    ) -> Result<Option<(Found, bool)>, std::io::Error> {
+        let mut long_name: Vec<u16> = Vec::new();
+        for cluster in self.chain(directory)? {
+            let data = self.read_cluster(cluster)?;
+            for (index, entry) in data.chunks(ENTRY_SIZE).enumerate() {
+                match entry[0] {
+                    0 => return Ok(None),
+                    DELETED => {
+                        long_name.clear();
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                // Long name entries come before their entry, last part first
+                if entry[11] == fat32::ATTR_LONG_NAME {
+                    if entry[0] & 0x40 != 0 {
+                        long_name.clear();
+                    }
+                    let part: Vec<u16> = [1..11, 14..26, 28..32]
+                        .iter()
+                        .flat_map(|range| entry[range.clone()].chunks(2))
+                        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+                        .take_while(|character| *character != 0)
+                        .collect();
+                    long_name.splice(0..0, part);
+                    continue;
+                }
+
+                let entry_name = match long_name.is_empty() {
+                    true => short_name(&entry[..11]),
+                    false => String::from_utf16_lossy(&long_name),
+                };
+                long_name.clear();
+
+                if entry[11] & fat32::ATTR_VOLUME_ID == 0 && entry_name.eq_ignore_ascii_case(name) {
+                    let high = u16::from_le_bytes([entry[20], entry[21]]) as u32;
+                    let low = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+                    let found = Found {
+                        entry_offset: self.cluster_offset(cluster) + (index * ENTRY_SIZE) as u64,
+                        first_cluster: high << 16 | low,
+                        size: u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]])
+                            as usize,
+                    };
+                    return Ok(Some((found, entry[11] & fat32::ATTR_DIRECTORY != 0)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Every cluster of a file or directory in order, none for an empty file
+    fn chain(&mut self, first_cluster: u32) -> Result<Vec<u32>, std::io::Error> {
+        let mut chain = Vec::new();
+        let mut cluster = first_cluster;
+        while (2..END_OF_CHAIN).contains(&cluster) {
+            if cluster >= self.clusters || chain.len() >= self.clusters as usize {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "the image's file allocation table is corrupt",
+                ));
+            }
+            chain.push(cluster);
+            cluster = self.read_fat(cluster)?;
+        }
+        Ok(chain)
+    }
+
+    // Keeps the free cluster count, when it is known, and the hint of where to look for a free
+    // cluster in step with the clusters allocated and freed
+    fn update_fs_info(&mut self, allocated: &[u32], freed: &[u32]) -> Result<(), std::io::Error> {
+        if allocated.is_empty() && freed.is_empty() {
+            return Ok(());
+        }
+
+        for sector in self.fs_info_sectors.clone() {
+            let offset = (sector * fat32::BYTES_PER_SECTOR) as u64;
+            let mut fs_info = [0; fat32::BYTES_PER_SECTOR];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut fs_info)?;
+
+            let u32_at = |offset: usize| {
+                u32::from_le_bytes([
+                    fs_info[offset],
+                    fs_info[offset + 1],
+                    fs_info[offset + 2],
+                    fs_info[offset + 3],
+                ])
+            };
+            if u32_at(0) != FS_INFO_LEAD_SIGNATURE || u32_at(484) != FS_INFO_STRUCTURE_SIGNATURE {
+                continue;
+            }
+
+            let free_count = match u32_at(488) {
+                FS_INFO_UNKNOWN => FS_INFO_UNKNOWN,
+                count => (count as usize + freed.len())
+                    .checked_sub(allocated.len())
+                    .filter(|count| *count < self.clusters as usize)
+                    .map(|count| count as u32)
+                    .unwrap_or(FS_INFO_UNKNOWN),
+            };
+
+            // Clusters are allocated from the start, so the next free one is after the last
+            // allocated unless one before it was freed
+            let next_free = match (allocated.iter().max(), freed.iter().min()) {
+                (_, Some(freed)) => match u32_at(492) {
+                    hint if (2..self.clusters).contains(&hint) => hint.min(*freed),
+                    _ => *freed,
+                },
+                (Some(allocated), None) if allocated + 1 < self.clusters => allocated + 1,
+                _ => FS_INFO_UNKNOWN,
+            };
+
+            fs_info[488..492].copy_from_slice(&free_count.to_le_bytes());
+            fs_info[492..496].copy_from_slice(&next_free.to_le_bytes());
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(&fs_info)?;
+        }
+        Ok(())
+    }
+
+    fn free_cluster(&mut self) -> Result<u32, std::io::Error> {
+        for cluster in 2..self.clusters {
+            if self.read_fat(cluster)? == 0 {
+                return Ok(cluster);
+            }
+        }
+        Err(std::io::Error::other("the image is full"))
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> Result<u32, std::io::Error> {
+        let mut value = [0; 4];
+        self.file
+            .seek(SeekFrom::Start(self.fat_offset(cluster, 0)))?;
+        self.file.read_exact(&mut value)?;
+        Ok(u32::from_le_bytes(value) & CLUSTER_MASK)
+    }
+
+    // Every copy of the FAT is kept the same
+    fn write_fat(&mut self, cluster: u32, value: u32) -> Result<(), std::io::Error> {
+        for fat in 0..self.num_fats {
+            self.file
+                .seek(SeekFrom::Start(self.fat_offset(cluster, fat)))?;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn fat_offset(&self, cluster: u32, fat: usize) -> u64 {
+        ((self.first_fat_sector + fat * self.fat_size) * fat32::BYTES_PER_SECTOR) as u64
+            + cluster as u64 * 4
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        (self.first_data_sector * fat32::BYTES_PER_SECTOR) as u64
+            + (cluster as u64 - 2) * self.cluster_size as u64
+    }
+
+    fn read_cluster(&mut self, cluster: u32) -> Result<Vec<u8>, std::io::Error> {
+        let mut buffer = vec![0; self.cluster_size];
+        self.file
+            .seek(SeekFrom::Start(self.cluster_offset(cluster)))?;
+        self.file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn write_cluster(&mut self, cluster: u32, buffer: &[u8]) -> Result<(), std::io::Error> {
+        self.file
+            .seek(SeekFrom::Start(self.cluster_offset(cluster)))?;
+        self.file.write_all(buffer)
+    }
+}
+
+// Turns an 8.3 name, padded with spaces, into "NAME.EXT"
+fn short_name(name: &[u8]) -> String {
+    let base = String::from_utf8_lossy(&name[..8]).trim_end().to_owned();
+    let extension = String::from_utf8_lossy(&name[8..11]).trim_end().to_owned();
+    match extension.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, extension),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Volume;
+    use std::{
+        io::{Read, Seek, SeekFrom, Write},
+        path::Path,
+    };
+
+    fn fs_info(image: &Path) -> (u32, u32) {
+        let mut fs_info = [0; 512];
+        let mut file = std::fs::File::open(image).unwrap();
+        file.seek(SeekFrom::Start(512)).unwrap();
+        file.read_exact(&mut fs_info).unwrap();
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                fs_info[offset],
+                fs_info[offset + 1],
+                fs_info[offset + 2],
+                fs_info[offset + 3],
+            ])
+        };
+        (u32_at(488), u32_at(492))
+    }
+
+    #[test]
+    fn replace_file_keeps_fs_info() {
+        let directory = std::env::temp_dir().join(format!("losb-patch-{}", std::process::id()));
+        let sysroot = directory.join("sysroot");
+        let image = directory.join("os.img");
+        std::fs::create_dir_all(sysroot.join("EFI/BOOT")).unwrap();
+        std::fs::write(sysroot.join("EFI/BOOT/los.cfg"), "cmdline=\n").unwrap();
+        let volume_size =
+            super::super::calculate::volume_size(&sysroot, crate::config::MIN_IMAGE_SIZE).unwrap();
+        super::super::create::create_image(volume_size, &image).unwrap();
+        super::super::copy::copy_directory(&image, &sysroot).unwrap();
+
+        // losb leaves the free count unknown, so give it one to keep
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&image)
+            .unwrap();
+        file.seek(SeekFrom::Start(512 + 488)).unwrap();
+        file.write_all(&1000u32.to_le_bytes()).unwrap();
+        drop(file);
+
+        let path = Path::new("EFI/BOOT/los.cfg");
+        let mut volume = Volume::open(&image).unwrap();
+        let first = volume.find(path).unwrap().unwrap().first_cluster;
+
+        let long = vec![b'x'; volume.cluster_size * 4];
+        volume.replace_file(path, &long).unwrap();
+        let chain = volume.chain(first).unwrap();
+        assert_eq!(chain.len(), 4);
+        assert_eq!(fs_info(&image), (997, chain[3] + 1));
+        assert_eq!(volume.read_file(path).unwrap().unwrap(), long);
+
+        volume.replace_file(path, b"cmdline=\n").unwrap();
+        assert_eq!(fs_info(&image), (1000, chain[1]));
+        assert_eq!(volume.read_file(path).unwrap().unwrap(), b"cmdline=\n");
+
+        std::fs::remove_dir_all(directory).ok();
+    }
+}
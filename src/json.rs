@@ -0,0 +1,419 @@
+// A minimal JSON parser, used to read the messages of tools such as cargo and the files losb
+// keeps itself
+
+// How deeply arrays and objects may be nested, so a hostile message can't exhaust the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug)]
+pub struct ParseError(usize);
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+pub fn parse(source: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        bytes: source.as_bytes(),
+        position: 0,
+        depth: 0,
+    };
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    match parser.position == parser.bytes.len() {
+        true => Ok(value),
+        false => Err(ParseError(parser.position)),
+    }
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+}
+
+// Writes a string as a JSON string literal
+pub fn quote(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> ParseError {
+        ParseError(self.position)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Value) -> Result<Value, ParseError> {
+        match self.bytes[self.position..].starts_with(word.as_bytes()) {
+            true => {
+                self.position += word.len();
+                Ok(value)
+            }
+            false => Err(self.error()),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect_word("null", Value::Null),
+            Some(b't') => self.expect_word("true", Value::Boolean(true)),
+            Some(b'f') => self.expect_word("false", Value::Boolean(false)),
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(b'[') | Some(b'{') if self.depth == MAX_DEPTH => Err(self.error()),
+            Some(b'[') => {
+                self.depth += 1;
+                let array = self.parse_array();
+                self.depth -= 1;
+                array
+            }
+            Some(b'{') => {
+                self.depth += 1;
+                let object = self.parse_object();
+                self.depth -= 1;
+                object
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.parse_number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, ParseError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit()
+                || c == b'-'
+                || c == b'+'
+                || c == b'.'
+                || c == b'e'
+                || c == b'E')
+            {
+                break;
+            }
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Value::Number)
+            .ok_or(ParseError(start))
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error())?;
+            self.position += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error())?;
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.parse_hex()?;
+
+                            // Combine surrogate pairs. An escape after an unpaired surrogate
+                            // is read again as a character of its own.
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.bytes[self.position..].starts_with(b"\\u")
+                            {
+                                let escape = self.position;
+                                self.position += 2;
+                                let low = self.parse_hex()?;
+                                match (0xDC00..0xE000).contains(&low) {
+                                    true => {
+                                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+                                    }
+                                    false => self.position = escape,
+                                }
+                            }
+
+                            std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return Err(self.error()),
+                    };
+
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error())
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.position += 1;
+        let mut array = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Value::Array(array));
+        }
+
+        loop {
+            array.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(array));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, ParseError> {
+        self.position += 1;
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error());
+            }
+            let key = self.parse_string()?;
+
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error());
+            }
+            self.position += 1;
+
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid JSON at byte {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, quote, Value};
+
+    fn string(source: &str) -> String {
+        match parse(source).unwrap() {
+            Value::String(string) => string,
+            value => panic!("{:?} is not a string", value),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(string(r#""""#), "");
+        assert_eq!(string(r#""plain text""#), "plain text");
+        assert_eq!(string("\"caf\u{e9} \u{1F980}\""), "caf\u{e9} \u{1F980}");
+        assert!(parse(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""\"\\\/\b\f\n\r\t""#), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(string(r#""\u0041\u00e9\u20AC""#), "A\u{e9}\u{20ac}");
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""\u12G4""#).is_err());
+
+        for text in ["", "line\nbreak", "\"quoted\" \\ \t\u{1}", "caf\u{e9}"] {
+            assert_eq!(string(&quote(text)), text);
+        }
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(string(r#""\uD83E\uDD80""#), "\u{1F980}");
+        assert_eq!(string(r#""\ud83e\udd80!""#), "\u{1F980}!");
+
+        // Unpaired surrogates are replaced, keeping whatever follows them
+        assert_eq!(string(r#""\uD800\u0041""#), "\u{FFFD}A");
+        assert_eq!(string(r#""\uD800\uD83E\uDD80""#), "\u{FFFD}\u{1F980}");
+        assert_eq!(string(r#""\uD800x""#), "\u{FFFD}x");
+        assert_eq!(string(r#""\uD800""#), "\u{FFFD}");
+        assert_eq!(string(r#""\uDC00\uD800""#), "\u{FFFD}\u{FFFD}");
+        assert_eq!(string(r#""\uDBFF\uDFFF""#), "\u{10FFFF}");
+    }
+
+    #[test]
+    fn numbers() {
+        for (source, number) in [
+            ("0", 0.0),
+            ("-12", -12.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("-2.5E-1", -0.25),
+        ] {
+            assert_eq!(parse(source).unwrap(), Value::Number(number), "{}", source);
+        }
+        for source in ["-", "1.2.3", "1e", "+1", "--1"] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let value =
+            parse(r#" { "reason": "compiler-artifact", "fresh": true, "filenames": ["a", "b"], "profile": {"opt": null, "flags": [[], {}]} } "#)
+                .unwrap();
+        assert_eq!(
+            value.get("reason").and_then(Value::as_str),
+            Some("compiler-artifact")
+        );
+        assert_eq!(value.get("fresh"), Some(&Value::Boolean(true)));
+        let filenames: Vec<_> = value
+            .get("filenames")
+            .and_then(Value::as_array)
+            .unwrap()
+            .iter()
+            .map(|filename| filename.as_str().unwrap())
+            .collect();
+        assert_eq!(filenames, ["a", "b"]);
+        let profile = value.get("profile").unwrap();
+        assert_eq!(profile.get("opt"), Some(&Value::Null));
+        assert_eq!(
+            profile.get("flags"),
+            Some(&Value::Array(vec![
+                Value::Array(Vec::new()),
+                Value::Object(Vec::new())
+            ]))
+        );
+        assert_eq!(value.get("missing"), None);
+
+        // Deep nesting is refused rather than overflowing the stack
+        let deep = format!("{}{}", "[".repeat(128), "]".repeat(128));
+        assert!(parse(&deep).is_ok());
+        let deeper = format!("{}{}", "[".repeat(100000), "]".repeat(100000));
+        assert!(parse(&deeper).is_err());
+        let objects = "{\"a\":".repeat(100000);
+        assert!(parse(&objects).is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        for source in [
+            "",
+            "   ",
+            "nul",
+            "truefalse",
+            "[1,]",
+            "[1 2]",
+            "[",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "{a: 1}",
+            "{\"a\": }",
+            "\"a\" \"b\"",
+            "{\"a\": 1} x",
+        ] {
+            assert!(parse(source).is_err(), "{:?}", source);
+        }
+    }
+}
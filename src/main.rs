@@ -4,11 +4,23 @@ mod clean;
 mod command;
 mod config;
 mod debug;
+mod doctor;
+mod elf;
+mod fingerprint;
 mod help;
 mod image;
+mod json;
+mod manifest;
+mod new;
+mod ova;
+mod pe;
 mod run;
+mod status;
+mod timings;
 mod vbox;
 mod version;
+mod watch;
+mod xml;
 
 use command::Command;
 
@@ -30,23 +42,37 @@ fn main() {
 
 fn run() -> Result<(), Box<dyn std::error::Error>> {
     // Parse arguments
-    let command = arguments::parse_command_line()?;
+    let arguments = arguments::parse_command_line()?;
 
     // Set defaults if nescessary
-    let command = command.unwrap_or(config::DEFAULT_COMMAND);
+    let command = arguments.command.unwrap_or(config::DEFAULT_COMMAND);
+
+    // Load the project configuration, only commands which use it fail if it is invalid
+    let config = config::Config::load(&arguments);
 
     // Process command
     match command {
-        Command::Build => build::build()?,
-        Command::BuildImage => image::build_image()?,
+        Command::Build => build::build(&config?)?,
+        Command::BuildImage => image::build_image(&config?)?,
         Command::BuildISO => return Err(Box::new(NotImplementedError(Command::BuildISO))),
-        Command::Clean => clean::clean()?,
-        Command::CleanUser => clean::clean_user()?,
-        Command::Debug => debug::debug()?,
+        Command::Clean => clean::clean(&config?)?,
+        Command::CleanUser => clean::clean_user(&config?)?,
+        Command::Debug => debug::debug(&config?)?,
+        Command::Doctor => doctor::doctor(config, &arguments)?,
+        Command::ExportOVA => ova::export_ova(&config?)?,
         Command::Help => help::display_help(),
-        Command::Run => run::run()?,
-        Command::VBox => vbox::vbox()?,
+        Command::NewLibrary => {
+            new::new(&config?, new::Kind::Library, arguments.created.as_deref())?
+        }
+        Command::NewProgram => {
+            new::new(&config?, new::Kind::Program, arguments.created.as_deref())?
+        }
+        Command::Run => run::run(&config?, arguments.previous, arguments.cmdline.as_deref())?,
+        Command::Status => status::display_status(&config?),
+        Command::Timings => timings::display_timings(&config?, arguments.compare)?,
+        Command::VBox => vbox::vbox(&config?)?,
         Command::Version => version::display_version(),
+        Command::Watch => watch::watch(&config?, arguments.watched.unwrap_or(Command::Build))?,
     };
 
     Ok(())
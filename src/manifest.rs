@@ -0,0 +1,169 @@
+use crate::config::Config;
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, remove_dir_all, remove_file},
+    path::{Path, PathBuf},
+};
+
+// Records which component installed each file in a sysroot, by path relative to the sysroot
+pub struct Manifest {
+    path: PathBuf,
+    owners: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    // Loads the manifest of the selected sysroot, a missing manifest has no files
+    pub fn load(config: &Config) -> Result<Self, std::io::Error> {
+        Self::load_from(manifest_path(config))
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Self, std::io::Error> {
+        let contents = match read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        // Each line is the component, a tab, then the file
+        let owners = contents
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(component, file)| (PathBuf::from(file), component.to_owned()))
+            .collect();
+
+        Ok(Manifest { path, owners })
+    }
+
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let mut contents = String::new();
+        for (file, component) in &self.owners {
+            contents.push_str(component);
+            contents.push('\t');
+            contents.push_str(&file.to_string_lossy());
+            contents.push('\n');
+        }
+        std::fs::write(&self.path, contents)
+    }
+
+    pub fn owner(&self, file: &Path) -> Option<&str> {
+        self.owners.get(file).map(String::as_str)
+    }
+
+    // The files a component installed, in a fixed order
+    pub fn files(&self, component: &str) -> Vec<&Path> {
+        self.owners
+            .iter()
+            .filter(|(_, owner)| *owner == component)
+            .map(|(file, _)| file.as_path())
+            .collect()
+    }
+
+    // Every component with files installed, in a fixed order
+    pub fn components(&self) -> Vec<&str> {
+        let mut components: Vec<&str> = self.owners.values().map(String::as_str).collect();
+        components.sort_unstable();
+        components.dedup();
+        components
+    }
+
+    // Replaces the files a component installed
+    pub fn set_files(&mut self, component: &str, files: &[PathBuf]) {
+        self.owners.retain(|_, owner| owner != component);
+        for file in files {
+            self.owners.insert(file.clone(), component.to_owned());
+        }
+    }
+}
+
+// Where the manifest of the selected sysroot is kept
+fn manifest_path(config: &Config) -> PathBuf {
+    Path::new(crate::config::STATE_DIR)
+        .join("manifests")
+        .join(config.sysroot().file_name().unwrap_or_default())
+}
+
+// Forgets what was installed into one sysroot, or into all of them
+pub fn clear(config: Option<&Config>) -> Result<(), std::io::Error> {
+    let path = match config {
+        Some(config) => manifest_path(config),
+        None => Path::new(crate::config::STATE_DIR).join("manifests"),
+    };
+
+    match (path.is_dir(), path.exists()) {
+        (true, _) => remove_dir_all(path),
+        (false, true) => remove_file(path),
+        (false, false) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Manifest;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn round_trip() {
+        let directory = std::env::temp_dir().join(format!("losb-manifest-{}", std::process::id()));
+        let path = directory.join("manifests/sysroot");
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+
+        // A missing manifest has no files, and saving creates its directory
+        let mut manifest = Manifest::load_from(path.clone()).unwrap();
+        assert!(manifest.components().is_empty());
+        manifest.set_files(
+            "kernel",
+            &[PathBuf::from("kernel.elf"), PathBuf::from("kernel.sym")],
+        );
+        manifest.set_files(
+            "programs",
+            &[
+                PathBuf::from("los/bin/shell"),
+                PathBuf::from("los/bin/a file with spaces"),
+            ],
+        );
+        manifest.set_files("boot", &[]);
+        manifest.save().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "kernel\tkernel.elf\nkernel\tkernel.sym\nprograms\tlos/bin/a file with spaces\nprograms\tlos/bin/shell\n"
+        );
+
+        let mut manifest = Manifest::load_from(path.clone()).unwrap();
+        assert_eq!(manifest.components(), ["kernel", "programs"]);
+        assert_eq!(
+            manifest.files("kernel"),
+            [Path::new("kernel.elf"), Path::new("kernel.sym")]
+        );
+        assert_eq!(
+            manifest.files("programs"),
+            [
+                Path::new("los/bin/a file with spaces"),
+                Path::new("los/bin/shell")
+            ]
+        );
+        assert_eq!(manifest.owner(Path::new("los/bin/shell")), Some("programs"));
+        assert_eq!(manifest.owner(Path::new("los/bin/missing")), None);
+        assert!(manifest.files("libraries").is_empty());
+
+        // Setting a component's files replaces the ones it had, leaving the others alone
+        manifest.set_files("kernel", &[PathBuf::from("kernel.elf")]);
+        assert_eq!(manifest.files("kernel"), [Path::new("kernel.elf")]);
+        assert_eq!(manifest.owner(Path::new("kernel.sym")), None);
+        assert_eq!(manifest.files("programs").len(), 2);
+        manifest.set_files("programs", &[]);
+        assert_eq!(manifest.components(), ["kernel"]);
+
+        // Lines without a tab are skipped
+        std::fs::write(&path, "kernel\tkernel.elf\ngarbage\n\n").unwrap();
+        let manifest = Manifest::load_from(path).unwrap();
+        assert_eq!(manifest.components(), ["kernel"]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,665 @@
+use crate::config::{toml, Config};
+use std::{
+    fs::{create_dir_all, read, read_dir, read_to_string, write},
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum NewError {
+    NoName(Kind),
+    InvalidName(String),
+    NoComponent(&'static str),
+    Exists(PathBuf),
+    Template(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+    Register(PathBuf, std::io::Error),
+    Registered(String, PathBuf),
+    Members(PathBuf, String),
+    NoBuildFile(Kind, PathBuf, PathBuf),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Program,
+    Library,
+}
+
+// The build file of each program and library
+const BUILD_FILE: &str = "brew.toml";
+
+// The sources used when the project has no templates of its own, as paths and contents. Paths and
+// contents both have placeholders replaced. The build file is taken from an existing project.
+const PROGRAM_TEMPLATE: &[(&str, &str)] = &[(
+    "src/main.c",
+    r#"#include <stdio.h>
+
+int main(int argc, const char* argv[]) {
+    printf("Hello from {{name}}!\n");
+    return 0;
+}
+"#,
+)];
+
+const LIBRARY_TEMPLATE: &[(&str, &str)] = &[
+    (
+        "include/{{name}}.h",
+        r#"#pragma once
+
+int {{identifier}}_version(void);
+"#,
+    ),
+    (
+        "src/{{name}}.c",
+        r#"#include <{{name}}.h>
+
+int {{identifier}}_version(void) {
+    return 1;
+}
+"#,
+    ),
+];
+
+// Creates a program or library in the directory brew builds them from, from the project's own
+// templates when it has them, then adds it to the members of the build there
+pub fn new(config: &Config, kind: Kind, name: Option<&str>) -> Result<(), NewError> {
+    let name = name.ok_or(NewError::NoName(kind))?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(NewError::InvalidName(name.to_owned()));
+    }
+
+    let component = kind.component();
+    let parent = match config.component(component) {
+        Some(component) => PathBuf::from(&component.path),
+        None => return Err(NewError::NoComponent(component)),
+    };
+    let path = parent.join(name);
+    if path.exists() {
+        return Err(NewError::Exists(path));
+    }
+
+    // The sysroot's paths are given relative to the new project, so the project can be moved
+    // along with the sysroot. Projects are shared by every configuration, so they are given the
+    // sysroot of none in particular.
+    let sysroot = relative_path(&path, Path::new(&config.image.sysroot));
+    let placeholders = [
+        ("{{name}}", name.to_owned()),
+        ("{{identifier}}", name.replace('-', "_")),
+        ("{{arch}}", config.arch.name().to_owned()),
+        ("{{target}}", config.arch.kernel_target().to_owned()),
+        ("{{sysroot}}", path_string(&sysroot)),
+        ("{{include}}", path_string(&sysroot.join("los/include"))),
+        ("{{lib}}", path_string(&sysroot.join("los/lib"))),
+    ];
+    let render = |text: &str| {
+        placeholders
+            .iter()
+            .fold(text.to_owned(), |text, (placeholder, value)| {
+                text.replace(placeholder, value)
+            })
+    };
+
+    // The build file is read first so nothing is written when the name is already a member
+    let registration = match config.scaffold.register.is_empty() {
+        true => None,
+        false => {
+            let build_file = parent.join(&config.scaffold.register);
+            let updated = add_member(&build_file, name)?;
+            Some((build_file, updated))
+        }
+    };
+
+    let templates = Path::new(&config.scaffold.templates).join(kind.to_string());
+    let files = match templates.is_dir() {
+        true => project_template(&templates, Path::new(""))?,
+        false => {
+            let build_file = match existing_build_file(&parent)? {
+                Some(build_file) => build_file,
+                None => return Err(NewError::NoBuildFile(kind, parent, templates)),
+            };
+            let mut files = vec![(PathBuf::from(BUILD_FILE), build_file.into_bytes())];
+            files.extend(
+                kind.template()
+                    .iter()
+                    .map(|(file, contents)| (PathBuf::from(file), contents.as_bytes().to_vec())),
+            );
+            files
+        }
+    };
+
+    for (file, contents) in files {
+        let target = path.join(render(&file.to_string_lossy()));
+        // Only text is rendered, anything else is copied as it is
+        let contents = match String::from_utf8(contents) {
+            Ok(text) => render(&text).into_bytes(),
+            Err(error) => error.into_bytes(),
+        };
+
+        let result = match target.parent() {
+            Some(parent) => create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| write(&target, contents));
+        if let Err(error) = result {
+            return Err(NewError::Write(target, error));
+        }
+    }
+    println!(
+        "     \x1B[32;1mCreated\x1B[0m {} {} ({})",
+        kind,
+        name,
+        path.to_string_lossy()
+    );
+
+    if let Some((build_file, updated)) = registration {
+        write(&build_file, updated)
+            .map_err(|error| NewError::Register(build_file.clone(), error))?;
+        println!(
+            "  \x1B[32;1mRegistered\x1B[0m {} in {}",
+            name,
+            build_file.to_string_lossy()
+        );
+    }
+
+    Ok(())
+}
+
+// Every file below a template directory, relative to it, in a fixed order
+fn project_template(templates: &Path, path: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>, NewError> {
+    let directory = templates.join(path);
+    let error = |error| NewError::Template(directory.clone(), error);
+
+    let mut entries = Vec::new();
+    for entry in read_dir(&directory).map_err(error)? {
+        entries.push(entry.map_err(error)?.file_name());
+    }
+    entries.sort();
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let file = path.join(entry);
+        match templates.join(&file).is_dir() {
+            true => files.append(&mut project_template(templates, &file)?),
+            false => {
+                let contents = read(templates.join(&file))
+                    .map_err(|error| NewError::Template(templates.join(&file), error))?;
+                files.push((file, contents));
+            }
+        }
+    }
+    Ok(files)
+}
+
+// The build file of the first project in a directory, as a template with its name replaced by
+// the placeholder. Projects sit side by side, so its paths to the sysroot hold for the new one.
+fn existing_build_file(parent: &Path) -> Result<Option<String>, NewError> {
+    let error = |error| NewError::Template(parent.to_owned(), error);
+    let mut projects = Vec::new();
+    match read_dir(parent) {
+        Ok(entries) => {
+            for entry in entries {
+                projects.push(entry.map_err(error)?.file_name());
+            }
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(NewError::Template(parent.to_owned(), error)),
+    }
+    projects.sort();
+
+    for project in projects {
+        let path = parent.join(&project).join(BUILD_FILE);
+        if !path.is_file() {
+            continue;
+        }
+
+        let source =
+            read_to_string(&path).map_err(|error| NewError::Template(path.clone(), error))?;
+        let name = format!("\"{}\"", project.to_string_lossy());
+        return Ok(Some(source.replace(&name, "\"{{name}}\"")));
+    }
+    Ok(None)
+}
+
+// A build file with a project added to its "members" array, creating either when missing
+fn add_member(build_file: &Path, name: &str) -> Result<String, NewError> {
+    let source = match read_to_string(build_file) {
+        Ok(source) => source,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(NewError::Register(build_file.to_owned(), error)),
+    };
+    insert_member(build_file, &source, name)
+}
+
+// Only an array of strings at the top level of the file, starting on the line assigning it, is
+// added to. The result is read back to be sure nothing but the new member changed.
+fn insert_member(build_file: &Path, source: &str, name: &str) -> Result<String, NewError> {
+    let error = |message: &str| NewError::Members(build_file.to_owned(), message.to_owned());
+    let (members, line) = match read_members(source).map_err(|message| error(&message))? {
+        Some(members) => members,
+        None => {
+            let updated = match source.is_empty() {
+                true => format!("members = [\"{}\"]\n", name),
+                false => format!("members = [\"{}\"]\n\n{}", name, source),
+            };
+            return Ok(updated);
+        }
+    };
+    if members.iter().any(|member| member == name) {
+        return Err(NewError::Registered(name.to_owned(), build_file.to_owned()));
+    }
+
+    let array = MembersArray::find(source, line)
+        .ok_or_else(|| error("\"members\" is not laid out one value after another"))?;
+    let updated = array.insert(source, name, members.is_empty());
+
+    let mut expected = members;
+    expected.push(name.to_owned());
+    match read_members(&updated) {
+        Ok(Some((updated_members, _))) if updated_members == expected => Ok(updated),
+        _ => Err(error(
+            "\"members\" is laid out in a way it can't be added to",
+        )),
+    }
+}
+
+// The names in the top level "members" array of a build file and the line it is assigned on
+fn read_members(source: &str) -> Result<Option<(Vec<String>, usize)>, String> {
+    let table =
+        toml::parse(source).map_err(|error| format!("line {}: {}", error.line, error.message))?;
+    let entry = match table.get("members") {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let not_strings = || {
+        format!(
+            "\"members\" on line {} is not an array of strings",
+            entry.line
+        )
+    };
+    match &entry.value {
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| match &value.value {
+                toml::Value::String(member) => Ok(member.clone()),
+                _ => Err(not_strings()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|members| Some((members, entry.line))),
+        _ => Err(not_strings()),
+    }
+}
+
+// Where the members array is in a build file, as byte offsets
+struct MembersArray {
+    close: usize,           // The closing bracket
+    last: usize,            // The end of the last member or comma in it, or the opening bracket
+    trailing_comma: bool,   // Whether the last member is followed by a comma
+    several_lines: bool,    // Whether the array continues over several lines
+    indent: Option<String>, // The indent of the last member, when it has a line of its own
+}
+
+impl MembersArray {
+    // Reads the array assigned on a line, which may only hold strings, commas and comments
+    fn find(source: &str, line: usize) -> Option<Self> {
+        let start: usize = source
+            .split_inclusive('\n')
+            .take(line - 1)
+            .map(|line| line.len())
+            .sum();
+        let assignment = &source[start..];
+        let value = assignment
+            .trim_start()
+            .strip_prefix("members")?
+            .trim_start()
+            .strip_prefix('=')?
+            .trim_start();
+        if !value.starts_with('[') {
+            return None;
+        }
+        let open = start + assignment.len() - value.len();
+
+        let bytes = source.as_bytes();
+        let mut position = open + 1;
+        let mut last = open + 1;
+        let mut trailing_comma = false;
+        let mut last_line = start;
+        loop {
+            match *bytes.get(position)? {
+                b']' => break,
+                b',' => {
+                    last = position + 1;
+                    trailing_comma = true;
+                }
+                b'#' => {
+                    while bytes.get(position + 1).is_some_and(|c| *c != b'\n') {
+                        position += 1;
+                    }
+                }
+                quote @ (b'"' | b'\'') => {
+                    let string_start = position;
+                    loop {
+                        position += 1;
+                        match *bytes.get(position)? {
+                            b'\n' => return None,
+                            b'\\' if quote == b'"' => position += 1,
+                            c if c == quote => break,
+                            _ => {}
+                        }
+                    }
+                    last = position + 1;
+                    trailing_comma = false;
+                    last_line = source[..string_start].rfind('\n').map_or(0, |at| at + 1);
+                }
+                c if c.is_ascii_whitespace() => {}
+                _ => return None,
+            }
+            position += 1;
+        }
+
+        let close_line = source[..position].rfind('\n').map_or(0, |at| at + 1);
+        let indent = &source[last_line..];
+        let indent = &indent[..indent.len() - indent.trim_start().len()];
+        Some(MembersArray {
+            close: position,
+            last,
+            trailing_comma,
+            several_lines: close_line > open,
+            indent: match last_line > start && source[close_line..position].trim().is_empty() {
+                true => Some(indent.to_owned()),
+                false => None,
+            },
+        })
+    }
+
+    fn insert(&self, source: &str, name: &str, empty: bool) -> String {
+        let member = format!("\"{}\"", name);
+        let close_line = source[..self.close].rfind('\n').map_or(0, |at| at + 1);
+
+        // Arrays over several lines get a line of their own for the new member
+        if self.several_lines && source[close_line..self.close].trim().is_empty() {
+            let comma = match empty || self.trailing_comma {
+                true => "",
+                false => ",",
+            };
+            let line = format!(
+                "{}{}{}\n",
+                self.indent.as_deref().unwrap_or("    "),
+                member,
+                match self.trailing_comma {
+                    true => ",",
+                    false => "",
+                }
+            );
+            return format!(
+                "{}{}{}{}{}",
+                &source[..self.last],
+                comma,
+                &source[self.last..close_line],
+                line,
+                &source[close_line..]
+            );
+        }
+
+        let insertion = match (empty, self.trailing_comma) {
+            (true, _) => member,
+            (false, true) => format!(" {}", member),
+            (false, false) => format!(", {}", member),
+        };
+        format!(
+            "{}{}{}",
+            &source[..self.last],
+            insertion,
+            &source[self.last..]
+        )
+    }
+}
+
+// The path to "to" from the directory "from", through the current directory
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let current = std::env::current_dir().unwrap_or_default();
+    let absolute = |path: &Path| -> Vec<String> {
+        current
+            .join(path)
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect()
+    };
+    let (from, to) = (absolute(from), absolute(to));
+
+    let common = from
+        .iter()
+        .zip(&to)
+        .take_while(|(from, to)| from == to)
+        .count();
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for part in &to[common..] {
+        path.push(part);
+    }
+    path
+}
+
+// Paths in build files always use forward slashes
+fn path_string(path: &Path) -> String {
+    path.iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Kind {
+    // The component brew builds them as part of
+    fn component(&self) -> &'static str {
+        match self {
+            Kind::Program => "programs",
+            Kind::Library => "libraries",
+        }
+    }
+
+    fn template(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Kind::Program => PROGRAM_TEMPLATE,
+            Kind::Library => LIBRARY_TEMPLATE,
+        }
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Kind::Program => "program",
+                Kind::Library => "library",
+            }
+        )
+    }
+}
+
+impl std::error::Error for NewError {}
+
+impl std::fmt::Display for NewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                NewError::NoName(kind) => format!("Missing the name of the new {}", kind),
+                NewError::InvalidName(name) => format!(
+                    "Invalid name \"{}\" (names may only contain letters, digits, '-' and '_')",
+                    name
+                ),
+                NewError::NoComponent(component) => format!(
+                    "There is no {} component to add to in {}",
+                    component,
+                    crate::config::CONFIG_FILE
+                ),
+                NewError::Exists(path) => format!("{} already exists", path.to_string_lossy()),
+                NewError::Template(path, error) => format!(
+                    "Unable to read the template {} ({})",
+                    path.to_string_lossy(),
+                    error
+                ),
+                NewError::Write(path, error) =>
+                    format!("Unable to write {} ({})", path.to_string_lossy(), error),
+                NewError::Register(path, error) => format!(
+                    "Unable to add to the members of {} ({})",
+                    path.to_string_lossy(),
+                    error
+                ),
+                NewError::Registered(name, path) =>
+                    format!("{} is already a member of {}", name, path.to_string_lossy()),
+                NewError::Members(path, message) => format!(
+                    "Unable to add to the members of {} ({}), add the new project by hand or set register under [new] of {} to \"\"",
+                    path.to_string_lossy(),
+                    message,
+                    crate::config::CONFIG_FILE
+                ),
+                NewError::NoBuildFile(kind, parent, templates) => format!(
+                    "There is no {} in {} to take a {} from and no template in {}",
+                    kind,
+                    parent.to_string_lossy(),
+                    BUILD_FILE,
+                    templates.to_string_lossy()
+                ),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_member, insert_member, relative_path, NewError};
+    use std::path::{Path, PathBuf};
+
+    fn insert(source: &str, name: &str) -> Result<String, NewError> {
+        insert_member(Path::new("brew.toml"), source, name)
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(insert("", "shell").unwrap(), "members = [\"shell\"]\n");
+        assert_eq!(
+            insert("[profile.release]\nopt = 3\n", "shell").unwrap(),
+            "members = [\"shell\"]\n\n[profile.release]\nopt = 3\n"
+        );
+
+        let missing = std::env::temp_dir().join(format!("losb-new-{}.toml", std::process::id()));
+        assert_eq!(
+            add_member(&missing, "shell").unwrap(),
+            "members = [\"shell\"]\n"
+        );
+    }
+
+    #[test]
+    fn single_line_array() {
+        assert_eq!(
+            insert("members = []\n", "shell").unwrap(),
+            "members = [\"shell\"]\n"
+        );
+        assert_eq!(
+            insert("members = [\"init\"] # the programs\n", "shell").unwrap(),
+            "members = [\"init\", \"shell\"] # the programs\n"
+        );
+        assert_eq!(
+            insert("members = [\"init\",]\n", "shell").unwrap(),
+            "members = [\"init\", \"shell\"]\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_array() {
+        assert_eq!(
+            insert("members = [\n  \"init\",\n  \"ls\"\n]\n", "shell").unwrap(),
+            "members = [\n  \"init\",\n  \"ls\",\n  \"shell\"\n]\n"
+        );
+        assert_eq!(
+            insert("members = [\n]\n", "shell").unwrap(),
+            "members = [\n    \"shell\"\n]\n"
+        );
+    }
+
+    #[test]
+    fn trailing_comma() {
+        assert_eq!(
+            insert("members = [\n    \"init\",\n    \"ls\",\n]\n", "shell").unwrap(),
+            "members = [\n    \"init\",\n    \"ls\",\n    \"shell\",\n]\n"
+        );
+    }
+
+    #[test]
+    fn brackets_in_strings_and_comments() {
+        let source =
+            "members = [\n    \"a]b\", # [old]\n    'c]',\n]\n\n[other]\nmembers = [\"x\"]\n";
+        let updated = insert(source, "shell").unwrap();
+        assert_eq!(
+            updated,
+            "members = [\n    \"a]b\", # [old]\n    'c]',\n    \"shell\",\n]\n\n[other]\nmembers = [\"x\"]\n"
+        );
+
+        // Only the members of the file itself count, not those of a table
+        assert_eq!(
+            insert("[other]\nmembers = [\"x\"]\n", "shell").unwrap(),
+            "members = [\"shell\"]\n\n[other]\nmembers = [\"x\"]\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        assert!(matches!(
+            insert("members = [\"init\", \"shell\"]\n", "shell"),
+            Err(NewError::Registered(..))
+        ));
+        assert!(matches!(
+            insert("members = [\n    \"shell\", # ]\n]\n", "shell"),
+            Err(NewError::Registered(..))
+        ));
+    }
+
+    #[test]
+    fn refuses_other_layouts() {
+        for source in [
+            "members = \"init\"\n",
+            "members = [1]\n",
+            "\"members\" = [\"init\"]\n",
+            "members = [\"\"\"\ninit\"\"\"]\n",
+            "members = [\n",
+        ] {
+            assert!(
+                matches!(insert(source, "shell"), Err(NewError::Members(..))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(Path::new("programs/shell"), Path::new("./sysroot")),
+            PathBuf::from("../../sysroot")
+        );
+        assert_eq!(
+            relative_path(Path::new("./sysroot/los"), Path::new("sysroot/los/include")),
+            PathBuf::from("include")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a/b/c"), Path::new("/a/d")),
+            PathBuf::from("../../d")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a/b"), Path::new("/a/b")),
+            PathBuf::new()
+        );
+    }
+}
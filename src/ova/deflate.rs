@@ -0,0 +1,373 @@
+// A small deflate encoder using LZ77 and the fixed huffman codes, which is enough for the mostly
+// empty grains of a disk image
+
+const WINDOW_SIZE: usize = 32768;
+const HASH_SIZE: usize = 1 << 15;
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new(output: Vec<u8>) -> Self {
+        BitWriter {
+            output,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    // Writes bits least significant bit first
+    fn write_bits(&mut self, value: u32, length: u32) {
+        self.buffer |= value << self.count;
+        self.count += length;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are stored most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        let mut reversed = 0;
+        for i in 0..length {
+            reversed |= ((code >> i) & 1) << (length - 1 - i);
+        }
+        self.write_bits(reversed, length);
+    }
+
+    fn write_literal(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASE
+            .iter()
+            .rposition(|base| *base as usize <= length)
+            .unwrap();
+        self.write_literal(257 + code as u32);
+        self.write_bits(
+            (length - LENGTH_BASE[code] as usize) as u32,
+            LENGTH_EXTRA[code] as u32,
+        );
+
+        let code = DISTANCE_BASE
+            .iter()
+            .rposition(|base| *base as usize <= distance)
+            .unwrap();
+        self.write_code(code as u32, 5);
+        self.write_bits(
+            (distance - DISTANCE_BASE[code] as usize) as u32,
+            DISTANCE_EXTRA[code] as u32,
+        );
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    ((data[0] as usize) << 10 ^ (data[1] as usize) << 5 ^ data[2] as usize) & (HASH_SIZE - 1)
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Compresses data into a single fixed huffman deflate block
+pub fn deflate(data: &[u8], output: Vec<u8>) -> Vec<u8> {
+    let mut writer = BitWriter::new(output);
+    writer.write_bits(1, 1); // Final block
+    writer.write_bits(1, 2); // Fixed huffman codes
+
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let hash = hash(&data[i..]);
+
+            // Search the chain for the longest match
+            let max_length = (data.len() - i).min(MAX_MATCH);
+            let mut candidate = head[hash];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let mut length = 0;
+                while length < max_length && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                candidate = previous[candidate % WINDOW_SIZE];
+                chain += 1;
+            }
+        }
+
+        let advance = if best_length >= MIN_MATCH {
+            writer.write_match(best_length, best_distance);
+            best_length
+        } else {
+            writer.write_literal(data[i] as u32);
+            1
+        };
+
+        // Insert the consumed positions into the hash chains
+        for position in i..i + advance {
+            if position + MIN_MATCH <= data.len() {
+                let hash = hash(&data[position..]);
+                previous[position % WINDOW_SIZE] = head[hash];
+                head[hash] = position;
+            }
+        }
+
+        i += advance;
+    }
+
+    writer.write_literal(256); // End of block
+    writer.finish()
+}
+
+// Wraps a deflate stream in a zlib header and trailer
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut output = deflate(data, vec![0x78, 0x01]);
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut value = i as u32;
+        for _ in 0..8 {
+            value = match value & 1 {
+                1 => 0xEDB88320 ^ (value >> 1),
+                _ => value >> 1,
+            };
+        }
+        *entry = value;
+    }
+
+    let mut crc = !0u32;
+    for byte in data {
+        crc = table[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// Wraps a deflate stream in a gzip header and trailer, without a name or time so the output only
+// depends on the data
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let header = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+    let mut output = deflate(data, header);
+    output.extend_from_slice(&crc32(data).to_le_bytes());
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{adler32, crc32, deflate, gzip, zlib};
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn bits(&mut self, length: usize) -> u32 {
+            let mut value = 0;
+            for i in 0..length {
+                let bit = (self.data[self.position / 8] >> (self.position % 8)) & 1;
+                value |= (bit as u32) << i;
+                self.position += 1;
+            }
+            value
+        }
+
+        // Huffman codes are read most significant bit first
+        fn code(&mut self, length: usize) -> u32 {
+            (0..length).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn literal(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xBF => code - 0x30,
+                0xC0..=0xC7 => 280 + code - 0xC0,
+                _ => 144 + (code << 1 | self.bits(1)) - 0x190,
+            }
+        }
+    }
+
+    // Decodes the fixed huffman blocks deflate writes, returning the data and the bytes read
+    pub fn inflate(data: &[u8]) -> (Vec<u8>, usize) {
+        let mut reader = BitReader { data, position: 0 };
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            let last = reader.bits(1);
+            assert_eq!(reader.bits(2), 1, "not a fixed huffman block");
+            loop {
+                let symbol = reader.literal();
+                match symbol {
+                    0..=255 => output.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let code = symbol as usize - 257;
+                        let length = super::LENGTH_BASE[code] as usize
+                            + reader.bits(super::LENGTH_EXTRA[code] as usize) as usize;
+                        let code = reader.code(5) as usize;
+                        let distance = super::DISTANCE_BASE[code] as usize
+                            + reader.bits(super::DISTANCE_EXTRA[code] as usize) as usize;
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+            if last == 1 {
+                return (output, reader.position.div_ceil(8));
+            }
+        }
+    }
+
+    fn samples() -> Vec<Vec<u8>> {
+        // A simple generator so the incompressible sample is the same every run
+        let mut state = 0x2545F491u32;
+        let random: Vec<u8> = (0..70000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        let mut mixed = vec![0; 65536];
+        mixed[1000..1100].fill(0xAA);
+        mixed[40000..40013].copy_from_slice(b"Hello, world!");
+
+        vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabc".to_vec(),
+            b"The quick brown fox jumps over the lazy dog".repeat(50),
+            (0..=255).collect(),
+            vec![0; 65536],
+            mixed,
+            random,
+        ]
+    }
+
+    #[test]
+    fn known_checksums() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        // Large enough for the sums to be reduced more than once
+        assert_eq!(adler32(&vec![0xFF; 100000]), 0x149A302C);
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        for sample in samples() {
+            let compressed = deflate(&sample, Vec::new());
+            let (inflated, length) = inflate(&compressed);
+            assert_eq!(inflated, sample);
+            assert_eq!(length, compressed.len());
+        }
+    }
+
+    #[test]
+    fn compresses_empty_grains() {
+        assert!(deflate(&vec![0; 65536], Vec::new()).len() < 1024);
+    }
+
+    #[test]
+    fn zlib_round_trip() {
+        for sample in samples() {
+            let compressed = zlib(&sample);
+            assert_eq!(&compressed[..2], [0x78, 0x01]);
+            assert_eq!(u16::from_be_bytes([compressed[0], compressed[1]]) % 31, 0);
+
+            let (inflated, length) = inflate(&compressed[2..]);
+            assert_eq!(inflated, sample);
+            assert_eq!(compressed[2 + length..], adler32(&sample).to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        assert_eq!(
+            gzip(b""),
+            [0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        for sample in samples() {
+            let compressed = gzip(&sample);
+            assert_eq!(&compressed[..10], [0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF]);
+
+            let (inflated, length) = inflate(&compressed[10..]);
+            assert_eq!(inflated, sample);
+            let trailer = &compressed[10 + length..];
+            assert_eq!(trailer[..4], crc32(&sample).to_le_bytes());
+            assert_eq!(trailer[4..], (sample.len() as u32).to_le_bytes());
+        }
+    }
+}
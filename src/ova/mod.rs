@@ -0,0 +1,159 @@
+use crate::config::{Config, STATE_DIR};
+use std::{
+    fs::{create_dir_all, remove_file, File},
+    io::BufWriter,
+    path::Path,
+};
+
+pub mod deflate;
+mod ovf;
+mod sha256;
+pub mod tar;
+mod vmdk;
+
+#[derive(Debug)]
+pub enum ExportOVAError {
+    BuildError(crate::image::BuildImageError),
+    ConvertImage(std::io::Error),
+    WriteAppliance(std::io::Error),
+}
+
+pub fn export_ova(config: &Config) -> Result<(), ExportOVAError> {
+    crate::image::build_image(config)?;
+
+    println!();
+    let image_path = config.image_path();
+    let ova_path = Path::new(&config.ova.path);
+
+    let stem = ova_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "os".to_owned());
+    let descriptor_name = format!("{}.ovf", stem);
+    let manifest_name = format!("{}.mf", stem);
+    let disk_name = format!("{}.vmdk", stem);
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    // Convert the image
+    print!(
+        "  \x1B[36;1mConverting\x1B[0m {} into {} . . .",
+        image_path.to_string_lossy(),
+        disk_name
+    );
+    // The disk is written beside the state so it is never held in memory, hashing it on the way
+    let disk_path = Path::new(STATE_DIR).join(&disk_name);
+    let (disk, disk_digest) = match convert_disk(&image_path, &disk_path, &disk_name, timestamp) {
+        Ok(disk) => disk,
+        Err(error) => {
+            let _ = remove_file(&disk_path);
+            return Err(ExportOVAError::ConvertImage(error));
+        }
+    };
+    println!(
+        "\r    \x1B[32;1mFinished\x1B[0m converting {} into {}",
+        image_path.to_string_lossy(),
+        disk_name
+    );
+
+    // Generate the descriptor and manifest
+    let descriptor = ovf::Descriptor {
+        name: &config.ova.name,
+        disk_file: &disk_name,
+        disk_file_size: disk.size(),
+        disk_capacity: disk.capacity(),
+        disk_populated_size: disk.populated_size(),
+        memory: config.ova.memory,
+        cpu_count: config.ova.cpus,
+    }
+    .to_xml();
+
+    let manifest = format!(
+        "SHA256({})= {}\nSHA256({})= {}\n",
+        descriptor_name,
+        sha256::digest_hex(descriptor.as_bytes()),
+        disk_name,
+        disk_digest
+    );
+
+    // Pack the appliance, the descriptor must be the first file
+    print!(
+        "     \x1B[36;1mPacking\x1B[0m {} . . .",
+        ova_path.to_string_lossy()
+    );
+    let result = write_appliance(
+        ova_path,
+        timestamp,
+        [
+            (descriptor_name.as_str(), descriptor.as_bytes()),
+            (manifest_name.as_str(), manifest.as_bytes()),
+        ],
+        (&disk_name, disk.size(), &disk_path),
+    );
+    let _ = remove_file(&disk_path);
+    if let Err(error) = result {
+        return Err(ExportOVAError::WriteAppliance(error));
+    }
+    println!(
+        "\r    \x1B[32;1mFinished\x1B[0m packing {}",
+        ova_path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+// Returns the converted disk and its SHA256 digest
+fn convert_disk(
+    image_path: &Path,
+    disk_path: &Path,
+    disk_name: &str,
+    timestamp: u64,
+) -> Result<(vmdk::StreamOptimized, String), std::io::Error> {
+    create_dir_all(STATE_DIR)?;
+    let mut writer = sha256::Writer::new(BufWriter::new(File::create(disk_path)?));
+    let disk = vmdk::convert(image_path, disk_name, timestamp as u32, &mut writer)?;
+    Ok((disk, writer.finish_hex()))
+}
+
+fn write_appliance(
+    path: &Path,
+    timestamp: u64,
+    files: [(&str, &[u8]); 2],
+    (disk_name, disk_size, disk_path): (&str, u64, &Path),
+) -> Result<(), std::io::Error> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut writer = tar::Writer::new(file, timestamp);
+    for (name, data) in files {
+        writer.append_file(name, data)?;
+    }
+    writer.append_reader(disk_name, disk_size, &mut File::open(disk_path)?)?;
+    writer.finish()?;
+    Ok(())
+}
+
+impl std::error::Error for ExportOVAError {}
+
+impl std::fmt::Display for ExportOVAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ExportOVAError::BuildError(error) => format!("{}", error),
+                ExportOVAError::ConvertImage(error) =>
+                    format!("Unable to convert image into a VMDK ({})", error),
+                ExportOVAError::WriteAppliance(error) =>
+                    format!("Unable to write OVA appliance ({})", error),
+            }
+        )
+    }
+}
+
+impl From<crate::image::BuildImageError> for ExportOVAError {
+    fn from(error: crate::image::BuildImageError) -> Self {
+        ExportOVAError::BuildError(error)
+    }
+}
@@ -0,0 +1,118 @@
+use crate::xml::escape;
+
+pub struct Descriptor<'a> {
+    pub name: &'a str,
+    pub disk_file: &'a str,
+    pub disk_file_size: u64,
+    pub disk_capacity: u64,
+    pub disk_populated_size: u64,
+    pub memory: usize,
+    pub cpu_count: usize,
+}
+
+const DISK_FORMAT: &str =
+    "http://www.vmware.com/interfaces/specifications/vmdk.html#streamOptimized";
+
+impl<'a> Descriptor<'a> {
+    pub fn to_xml(&self) -> String {
+        let name = escape(self.name);
+        let mut xml = String::new();
+
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<Envelope ovf:version=\"1.0\" xml:lang=\"en-US\" xmlns=\"http://schemas.dmtf.org/ovf/envelope/1\" xmlns:ovf=\"http://schemas.dmtf.org/ovf/envelope/1\" xmlns:rasd=\"http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_ResourceAllocationSettingData\" xmlns:vssd=\"http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_VirtualSystemSettingData\" xmlns:vmw=\"http://www.vmware.com/schema/ovf\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n");
+
+        // Files
+        xml.push_str("  <References>\n");
+        xml.push_str(&format!(
+            "    <File ovf:id=\"file1\" ovf:href=\"{}\" ovf:size=\"{}\"/>\n",
+            escape(self.disk_file),
+            self.disk_file_size
+        ));
+        xml.push_str("  </References>\n");
+
+        // Disks
+        xml.push_str("  <DiskSection>\n");
+        xml.push_str("    <Info>List of the virtual disks</Info>\n");
+        xml.push_str(&format!(
+            "    <Disk ovf:capacity=\"{}\" ovf:capacityAllocationUnits=\"byte\" ovf:diskId=\"vmdisk1\" ovf:fileRef=\"file1\" ovf:format=\"{}\" ovf:populatedSize=\"{}\"/>\n",
+            self.disk_capacity, DISK_FORMAT, self.disk_populated_size
+        ));
+        xml.push_str("  </DiskSection>\n");
+
+        // Machine
+        xml.push_str(&format!("  <VirtualSystem ovf:id=\"{}\">\n", name));
+        xml.push_str("    <Info>A virtual machine</Info>\n");
+        xml.push_str(&format!("    <Name>{}</Name>\n", name));
+        xml.push_str("    <OperatingSystemSection ovf:id=\"102\">\n");
+        xml.push_str("      <Info>The kind of installed guest operating system</Info>\n");
+        xml.push_str("      <Description>Other 64-bit</Description>\n");
+        xml.push_str("    </OperatingSystemSection>\n");
+
+        // Hardware
+        xml.push_str("    <VirtualHardwareSection>\n");
+        xml.push_str("      <Info>Virtual hardware requirements</Info>\n");
+        xml.push_str("      <System>\n");
+        xml.push_str("        <vssd:ElementName>Virtual Hardware Family</vssd:ElementName>\n");
+        xml.push_str("        <vssd:InstanceID>0</vssd:InstanceID>\n");
+        xml.push_str(&format!(
+            "        <vssd:VirtualSystemIdentifier>{}</vssd:VirtualSystemIdentifier>\n",
+            name
+        ));
+        xml.push_str("        <vssd:VirtualSystemType>vmx-14</vssd:VirtualSystemType>\n");
+        xml.push_str("      </System>\n");
+
+        xml.push_str("      <Item>\n");
+        xml.push_str("        <rasd:AllocationUnits>hertz * 10^6</rasd:AllocationUnits>\n");
+        xml.push_str(&format!(
+            "        <rasd:ElementName>{} virtual CPU(s)</rasd:ElementName>\n",
+            self.cpu_count
+        ));
+        xml.push_str("        <rasd:InstanceID>1</rasd:InstanceID>\n");
+        xml.push_str("        <rasd:ResourceType>3</rasd:ResourceType>\n");
+        xml.push_str(&format!(
+            "        <rasd:VirtualQuantity>{}</rasd:VirtualQuantity>\n",
+            self.cpu_count
+        ));
+        xml.push_str("      </Item>\n");
+
+        xml.push_str("      <Item>\n");
+        xml.push_str("        <rasd:AllocationUnits>byte * 2^20</rasd:AllocationUnits>\n");
+        xml.push_str(&format!(
+            "        <rasd:ElementName>{} MB of memory</rasd:ElementName>\n",
+            self.memory
+        ));
+        xml.push_str("        <rasd:InstanceID>2</rasd:InstanceID>\n");
+        xml.push_str("        <rasd:ResourceType>4</rasd:ResourceType>\n");
+        xml.push_str(&format!(
+            "        <rasd:VirtualQuantity>{}</rasd:VirtualQuantity>\n",
+            self.memory
+        ));
+        xml.push_str("      </Item>\n");
+
+        xml.push_str("      <Item>\n");
+        xml.push_str("        <rasd:Address>0</rasd:Address>\n");
+        xml.push_str("        <rasd:ElementName>SATA Controller</rasd:ElementName>\n");
+        xml.push_str("        <rasd:InstanceID>3</rasd:InstanceID>\n");
+        xml.push_str("        <rasd:ResourceSubType>AHCI</rasd:ResourceSubType>\n");
+        xml.push_str("        <rasd:ResourceType>20</rasd:ResourceType>\n");
+        xml.push_str("      </Item>\n");
+
+        xml.push_str("      <Item>\n");
+        xml.push_str("        <rasd:AddressOnParent>0</rasd:AddressOnParent>\n");
+        xml.push_str("        <rasd:ElementName>Hard Disk 1</rasd:ElementName>\n");
+        xml.push_str("        <rasd:HostResource>ovf:/disk/vmdisk1</rasd:HostResource>\n");
+        xml.push_str("        <rasd:InstanceID>4</rasd:InstanceID>\n");
+        xml.push_str("        <rasd:Parent>3</rasd:Parent>\n");
+        xml.push_str("        <rasd:ResourceType>17</rasd:ResourceType>\n");
+        xml.push_str("      </Item>\n");
+
+        xml.push_str(
+            "      <vmw:Config ovf:required=\"false\" vmw:key=\"firmware\" vmw:value=\"efi\"/>\n",
+        );
+        xml.push_str("    </VirtualHardwareSection>\n");
+        xml.push_str("  </VirtualSystem>\n");
+        xml.push_str("</Envelope>\n");
+
+        xml
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{Read, Write};
+
+const BLOCK_SIZE: usize = 512;
+
+// Writes a ustar archive
+pub struct Writer<W: Write> {
+    inner: W,
+    mtime: u64,
+}
+
+fn write_octal(field: &mut [u8], value: u64) -> Result<(), std::io::Error> {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    if digits.len() > width {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is too large for a tar header", value),
+        ));
+    }
+    field[..width].copy_from_slice(digits.as_bytes());
+    field[width] = 0;
+    Ok(())
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W, mtime: u64) -> Self {
+        Writer { inner, mtime }
+    }
+
+    pub fn append_file(&mut self, name: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        self.append(name, 0o644, b'0', data)
+    }
+
+    pub fn append_entry(
+        &mut self,
+        name: &str,
+        mode: u32,
+        directory: bool,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        match directory {
+            true => self.append(&format!("{}/", name), mode, b'5', &[]),
+            false => self.append(name, mode, b'0', data),
+        }
+    }
+
+    // Appends a file of a known size, copying it from a reader rather than holding it in memory
+    pub fn append_reader<R: Read>(
+        &mut self,
+        name: &str,
+        size: u64,
+        reader: &mut R,
+    ) -> Result<(), std::io::Error> {
+        self.write_header(name, 0o644, b'0', size)?;
+        let copied = std::io::copy(&mut reader.take(size), &mut self.inner)?;
+        if copied != size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} ended before its size in the tar header", name),
+            ));
+        }
+        self.write_padding(size)
+    }
+
+    fn append(
+        &mut self,
+        name: &str,
+        mode: u32,
+        kind: u8,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        self.write_header(name, mode, kind, data.len() as u64)?;
+        self.inner.write_all(data)?;
+        self.write_padding(data.len() as u64)
+    }
+
+    fn write_header(
+        &mut self,
+        name: &str,
+        mode: u32,
+        kind: u8,
+        size: u64,
+    ) -> Result<(), std::io::Error> {
+        if name.len() > 100 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is too long for a tar entry", name),
+            ));
+        }
+
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_octal(&mut header[100..108], mode as u64)?;
+        write_octal(&mut header[108..116], 0)?; // Owner
+        write_octal(&mut header[116..124], 0)?; // Group
+        write_octal(&mut header[124..136], size)?;
+        write_octal(&mut header[136..148], self.mtime)?;
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is calculated with the checksum field filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+        write_octal(&mut header[148..155], checksum as u64)?;
+        header[155] = b' ';
+
+        self.inner.write_all(&header)
+    }
+
+    fn write_padding(&mut self, size: u64) -> Result<(), std::io::Error> {
+        let padding = (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE;
+        self.inner.write_all(&[0; BLOCK_SIZE][..padding])
+    }
+
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        self.inner.write_all(&[0; BLOCK_SIZE * 2])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_octal, Writer, BLOCK_SIZE};
+
+    #[test]
+    fn octal_fields() {
+        let mut field = [0xFF; 12];
+        write_octal(&mut field, 0o1234).unwrap();
+        assert_eq!(&field, b"00000001234\0");
+
+        // 8 GiB doesn't fit the 11 digits of the size field
+        write_octal(&mut field, 0o77777777777).unwrap();
+        let error = write_octal(&mut field, 8 << 30).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn entry_layout() {
+        let mut writer = Writer::new(Vec::new(), 0o17);
+        writer.append_file("os.ovf", b"hello").unwrap();
+        writer
+            .append_reader("os.vmdk", 3, &mut &b"abcdef"[..])
+            .unwrap();
+        let archive = writer.finish().unwrap();
+        assert_eq!(archive.len(), BLOCK_SIZE * 6);
+
+        let header = &archive[..BLOCK_SIZE];
+        assert_eq!(&header[..7], b"os.ovf\0");
+        assert_eq!(&header[124..136], b"00000000005\0");
+        assert_eq!(&header[136..148], b"00000000017\0");
+        assert_eq!(&header[257..265], b"ustar\x0000");
+        let checksum: u32 = header
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| match i {
+                148..=155 => b' ' as u32,
+                _ => *byte as u32,
+            })
+            .sum();
+        assert_eq!(&header[148..156], format!("{:06o}\0 ", checksum).as_bytes());
+        assert_eq!(&archive[BLOCK_SIZE..BLOCK_SIZE + 6], b"hello\0");
+
+        let header = &archive[BLOCK_SIZE * 2..BLOCK_SIZE * 3];
+        assert_eq!(&header[124..136], b"00000000003\0");
+        assert_eq!(&archive[BLOCK_SIZE * 3..BLOCK_SIZE * 3 + 4], b"abc\0");
+        assert!(archive[BLOCK_SIZE * 4..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn short_reader() {
+        let mut writer = Writer::new(Vec::new(), 0);
+        let error = writer
+            .append_reader("os.vmdk", 10, &mut &b"abc"[..])
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
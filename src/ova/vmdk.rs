@@ -0,0 +1,313 @@
+use super::deflate;
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+const SECTOR_SIZE: usize = 512;
+const GRAIN_SIZE: usize = 128; // In sectors
+const GRAIN_TABLE_ENTRIES: usize = 512;
+
+const MAGIC: u32 = 0x564D444B; // "KDMV"
+const VERSION: u32 = 3;
+const FLAGS: u32 = 1 | (1 << 16) | (1 << 17); // Valid newline detection, compressed and markers
+const COMPRESSION_DEFLATE: u16 = 1;
+const GD_AT_END: u64 = 0xFFFFFFFFFFFFFFFF;
+
+const MARKER_EOS: u32 = 0;
+const MARKER_GRAIN_TABLE: u32 = 1;
+const MARKER_GRAIN_DIRECTORY: u32 = 2;
+const MARKER_FOOTER: u32 = 3;
+
+// A disk converted into a streamOptimized sparse VMDK
+pub struct StreamOptimized {
+    size: u64,
+    capacity: u64,
+    populated_size: u64,
+}
+
+// Writes the disk, keeping count of where in it the output is
+struct Output<W: Write> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Output<W> {
+    fn write(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        self.inner.write_all(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    fn pad_to_sector(&mut self) -> Result<(), std::io::Error> {
+        let padding = (SECTOR_SIZE - self.written as usize % SECTOR_SIZE) % SECTOR_SIZE;
+        self.write(&[0; SECTOR_SIZE][..padding])
+    }
+
+    fn current_sector(&self) -> u64 {
+        self.written / SECTOR_SIZE as u64
+    }
+
+    fn write_marker(&mut self, value: u64, marker_type: u32) -> Result<(), std::io::Error> {
+        let mut marker = [0u8; SECTOR_SIZE];
+        marker[0..8].copy_from_slice(&value.to_le_bytes());
+        marker[12..16].copy_from_slice(&marker_type.to_le_bytes());
+        self.write(&marker)
+    }
+}
+
+fn header(capacity: u64, descriptor_size: u64, gd_offset: u64) -> [u8; SECTOR_SIZE] {
+    let mut header = [0u8; SECTOR_SIZE];
+    header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..12].copy_from_slice(&FLAGS.to_le_bytes());
+    header[12..20].copy_from_slice(&capacity.to_le_bytes());
+    header[20..28].copy_from_slice(&(GRAIN_SIZE as u64).to_le_bytes());
+    header[28..36].copy_from_slice(&1u64.to_le_bytes()); // Descriptor offset
+    header[36..44].copy_from_slice(&descriptor_size.to_le_bytes());
+    header[44..48].copy_from_slice(&(GRAIN_TABLE_ENTRIES as u32).to_le_bytes());
+    header[48..56].copy_from_slice(&0u64.to_le_bytes()); // Redundant grain directory
+    header[56..64].copy_from_slice(&gd_offset.to_le_bytes());
+    header[64..72].copy_from_slice(&(1 + descriptor_size).to_le_bytes()); // Overhead
+    header[72] = 0; // Unclean shutdown
+    header[73..77].copy_from_slice(b"\n \r\n");
+    header[77..79].copy_from_slice(&COMPRESSION_DEFLATE.to_le_bytes());
+    header
+}
+
+fn descriptor(capacity: u64, extent_name: &str, cid: u32) -> String {
+    let cylinders = (capacity / (16 * 63)).min(16383);
+
+    format!(
+        "# Disk DescriptorFile\n\
+         version=1\n\
+         CID={:08x}\n\
+         parentCID=ffffffff\n\
+         createType=\"streamOptimized\"\n\
+         \n\
+         # Extent description\n\
+         RW {} SPARSE \"{}\"\n\
+         \n\
+         # The Disk Data Base\n\
+         #DDB\n\
+         \n\
+         ddb.virtualHWVersion = \"4\"\n\
+         ddb.adapterType = \"ide\"\n\
+         ddb.geometry.cylinders = \"{}\"\n\
+         ddb.geometry.heads = \"16\"\n\
+         ddb.geometry.sectors = \"63\"\n",
+        cid, capacity, extent_name, cylinders
+    )
+}
+
+// Converts a raw disk image, writing the disk to output as it is produced
+pub fn convert<W: Write>(
+    source: &Path,
+    extent_name: &str,
+    cid: u32,
+    output: W,
+) -> Result<StreamOptimized, std::io::Error> {
+    let mut source = std::fs::File::open(source)?;
+    let source_size = source.metadata()?.len();
+    convert_from(&mut source, source_size, extent_name, cid, output)
+}
+
+fn convert_from<R: Read, W: Write>(
+    source: &mut R,
+    source_size: u64,
+    extent_name: &str,
+    cid: u32,
+    output: W,
+) -> Result<StreamOptimized, std::io::Error> {
+    let capacity = source_size.div_ceil(SECTOR_SIZE as u64);
+    let num_grains = (capacity as usize).div_ceil(GRAIN_SIZE);
+    let num_grain_tables = num_grains.div_ceil(GRAIN_TABLE_ENTRIES);
+
+    // Write the header and descriptor
+    let descriptor = descriptor(capacity, extent_name, cid);
+    let descriptor_size = descriptor.len().div_ceil(SECTOR_SIZE) as u64;
+
+    let mut output = Output {
+        inner: output,
+        written: 0,
+    };
+    output.write(&header(capacity, descriptor_size, GD_AT_END))?;
+    output.write(descriptor.as_bytes())?;
+    output.pad_to_sector()?;
+
+    // Write every grain which contains data, empty grains are left sparse
+    let mut grain_tables = vec![0u32; num_grain_tables * GRAIN_TABLE_ENTRIES];
+    let mut grain = vec![0u8; GRAIN_SIZE * SECTOR_SIZE];
+    let mut populated_size = 0;
+    for (i, entry) in grain_tables.iter_mut().enumerate().take(num_grains) {
+        grain.fill(0);
+        let mut length = 0;
+        while length < grain.len() {
+            match source.read(&mut grain[length..])? {
+                0 => break,
+                count => length += count,
+            }
+        }
+
+        if grain.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+
+        let compressed = deflate::zlib(&grain);
+        *entry = output.current_sector() as u32;
+        output.write(&((i * GRAIN_SIZE) as u64).to_le_bytes())?;
+        output.write(&(compressed.len() as u32).to_le_bytes())?;
+        output.write(&compressed)?;
+        output.pad_to_sector()?;
+
+        populated_size += grain.len() as u64;
+    }
+
+    // Write the grain tables, tables without any grains are left out of the directory
+    let grain_table_size = (GRAIN_TABLE_ENTRIES * 4 / SECTOR_SIZE) as u64;
+    let mut grain_directory = vec![0u32; num_grain_tables];
+    for (table, directory_entry) in grain_tables
+        .chunks(GRAIN_TABLE_ENTRIES)
+        .zip(grain_directory.iter_mut())
+    {
+        if table.iter().all(|entry| *entry == 0) {
+            continue;
+        }
+
+        output.write_marker(grain_table_size, MARKER_GRAIN_TABLE)?;
+        *directory_entry = output.current_sector() as u32;
+        for entry in table {
+            output.write(&entry.to_le_bytes())?;
+        }
+    }
+
+    // Write the grain directory
+    let grain_directory_size = (num_grain_tables * 4).div_ceil(SECTOR_SIZE) as u64;
+    output.write_marker(grain_directory_size, MARKER_GRAIN_DIRECTORY)?;
+    let gd_offset = output.current_sector();
+    for entry in &grain_directory {
+        output.write(&entry.to_le_bytes())?;
+    }
+    output.pad_to_sector()?;
+
+    // Write the footer and end of stream
+    output.write_marker(1, MARKER_FOOTER)?;
+    output.write(&header(capacity, descriptor_size, gd_offset))?;
+    output.write_marker(0, MARKER_EOS)?;
+    output.inner.flush()?;
+
+    Ok(StreamOptimized {
+        size: output.written,
+        capacity: capacity * SECTOR_SIZE as u64,
+        populated_size,
+    })
+}
+
+impl StreamOptimized {
+    // The size of the converted disk in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // The capacity of the disk in bytes
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn populated_size(&self) -> u64 {
+        self.populated_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_from, GD_AT_END, GRAIN_SIZE, SECTOR_SIZE};
+    use crate::ova::deflate::tests::inflate;
+    use std::convert::TryInto;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn sector(data: &[u8], sector: u64) -> &[u8] {
+        &data[sector as usize * SECTOR_SIZE..]
+    }
+
+    // Reads the grain at a sector, returning the sector it holds and the grain
+    fn grain(data: &[u8], at: u32) -> (u64, Vec<u8>) {
+        let grain = sector(data, at as u64);
+        let size = u32_at(grain, 8) as usize;
+        let (inflated, _) = inflate(&grain[12 + 2..12 + size]);
+        (u64_at(grain, 0), inflated)
+    }
+
+    #[test]
+    fn grain_and_footer_layout() {
+        // An empty grain, a full one and a partial one
+        let grain_bytes = GRAIN_SIZE * SECTOR_SIZE;
+        let mut source: Vec<u8> = vec![0; grain_bytes];
+        source.extend((0..grain_bytes).map(|i| (i % 251) as u8));
+        source.extend(vec![0x5A; 1000]);
+
+        let mut data = Vec::new();
+        let disk = convert_from(
+            &mut source.as_slice(),
+            source.len() as u64,
+            "os.vmdk",
+            0x12345678,
+            &mut data,
+        )
+        .unwrap();
+
+        let capacity = 258; // Sectors, the partial grain is rounded up to a whole sector
+        assert_eq!(disk.size(), data.len() as u64);
+        assert_eq!(disk.capacity(), capacity * SECTOR_SIZE as u64);
+        assert_eq!(disk.populated_size(), 2 * grain_bytes as u64);
+        assert_eq!(data.len() % SECTOR_SIZE, 0);
+
+        // The header points at a grain directory at the end, which the footer gives
+        let header = &data[..SECTOR_SIZE];
+        assert_eq!(&header[0..4], b"KDMV");
+        assert_eq!(u64_at(header, 12), capacity);
+        assert_eq!(u64_at(header, 20), GRAIN_SIZE as u64);
+        assert_eq!(u64_at(header, 56), GD_AT_END);
+        let descriptor = String::from_utf8_lossy(sector(&data, 1));
+        assert!(descriptor.starts_with("# Disk DescriptorFile\nversion=1\nCID=12345678\n"));
+        assert!(descriptor.contains("RW 258 SPARSE \"os.vmdk\"\n"));
+
+        // The stream ends with the footer marker, the footer and the end of stream marker
+        let end = data.len() / SECTOR_SIZE;
+        assert!(sector(&data, end as u64 - 1).iter().all(|byte| *byte == 0));
+        let marker = sector(&data, end as u64 - 3);
+        assert_eq!((u64_at(marker, 0), u32_at(marker, 12)), (1, 3));
+        let footer = &sector(&data, end as u64 - 2)[..SECTOR_SIZE];
+        assert_eq!(header[..56], footer[..56]);
+        assert_eq!(header[64..], footer[64..]);
+
+        // One grain table, listed in the grain directory
+        let gd_offset = u64_at(footer, 56);
+        let marker = sector(&data, gd_offset - 1);
+        assert_eq!((u64_at(marker, 0), u32_at(marker, 12)), (1, 2));
+        let table_offset = u32_at(sector(&data, gd_offset), 0) as u64;
+        let marker = sector(&data, table_offset - 1);
+        assert_eq!((u64_at(marker, 0), u32_at(marker, 12)), (4, 1));
+
+        // The empty grain is left out, the others hold their data
+        let table = sector(&data, table_offset);
+        assert_eq!(u32_at(table, 0), 0);
+        let (at, contents) = grain(&data, u32_at(table, 4));
+        assert_eq!(
+            (at, contents.as_slice()),
+            (128, &source[grain_bytes..][..grain_bytes])
+        );
+        let (at, contents) = grain(&data, u32_at(table, 8));
+        assert_eq!(at, 256);
+        assert_eq!(contents[..1000], [0x5A; 1000]);
+        assert!(contents[1000..].iter().all(|byte| *byte == 0));
+        assert!((3..512).all(|entry| u32_at(table, entry * 4) == 0));
+    }
+}
@@ -0,0 +1,130 @@
+// Reads just enough of PE files, such as UEFI applications, to tell what they contain
+
+pub const MAGIC_PE32_PLUS: u16 = 0x20B;
+
+pub const SUBSYSTEM_EFI_APPLICATION: u16 = 10;
+
+pub struct Header {
+    pub machine: u16,
+    pub magic: u16, // Of the optional header, telling PE32 and PE32+ apart
+    pub subsystem: u16,
+    pub entry: u32, // Relative to the image base
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// The COFF and optional headers, None if the file isn't a PE file or is truncated
+pub fn parse_header(data: &[u8]) -> Option<Header> {
+    if !data.starts_with(b"MZ") {
+        return None;
+    }
+
+    // The DOS header points to the PE signature, followed by the COFF header
+    let signature = read_u32(data, 0x3C)? as usize;
+    if data.get(signature..signature.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    let coff = signature.checked_add(4)?;
+    let optional = coff.checked_add(20)?;
+    if read_u16(data, coff + 16)? < 70 {
+        return None;
+    }
+
+    Some(Header {
+        machine: read_u16(data, coff)?,
+        magic: read_u16(data, optional)?,
+        subsystem: read_u16(data, optional.checked_add(68)?)?,
+        entry: read_u32(data, optional.checked_add(16)?)?,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{parse_header, MAGIC_PE32_PLUS, SUBSYSTEM_EFI_APPLICATION};
+
+    fn put(data: &mut [u8], offset: usize, value: u32, size: usize) {
+        data[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    // The headers of a PE32+ file for the machine and subsystem given, with the PE signature
+    // after a DOS stub and a full sized optional header
+    pub fn application(machine: u16, subsystem: u16) -> Vec<u8> {
+        let mut data = vec![0; 0x98 + 240];
+        data[..2].copy_from_slice(b"MZ");
+        put(&mut data, 0x3C, 0x80, 4);
+        data[0x80..0x84].copy_from_slice(b"PE\0\0");
+        put(&mut data, 0x84, machine as u32, 2);
+        put(&mut data, 0x86, 1, 2); // Sections
+        put(&mut data, 0x94, 240, 2); // Optional header size
+        put(&mut data, 0x98, MAGIC_PE32_PLUS as u32, 2);
+        put(&mut data, 0x98 + 16, 0x1000, 4); // Entry
+        put(&mut data, 0x98 + 68, subsystem as u32, 2);
+        data
+    }
+
+    #[test]
+    fn parses_application() {
+        let header = parse_header(&application(0xAA64, SUBSYSTEM_EFI_APPLICATION)).unwrap();
+        assert_eq!(header.machine, 0xAA64);
+        assert_eq!(header.magic, MAGIC_PE32_PLUS);
+        assert_eq!(header.subsystem, SUBSYSTEM_EFI_APPLICATION);
+        assert_eq!(header.entry, 0x1000);
+
+        let header = parse_header(&application(0x8664, 3)).unwrap();
+        assert_eq!((header.machine, header.subsystem), (0x8664, 3));
+    }
+
+    #[test]
+    fn not_pe() {
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        data[0] = b'X';
+        assert!(parse_header(&data).is_none());
+
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        data[0x82] = b'X';
+        assert!(parse_header(&data).is_none());
+
+        // Too small an optional header to hold the subsystem
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        put(&mut data, 0x94, 69, 2);
+        assert!(parse_header(&data).is_none());
+        put(&mut data, 0x94, 70, 2);
+        assert!(parse_header(&data).is_some());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        // The subsystem is the last field read
+        let needed = 0x98 + 70;
+        for length in 0..needed {
+            assert!(parse_header(&data[..length]).is_none(), "{}", length);
+        }
+        assert!(parse_header(&data[..needed]).is_some());
+    }
+
+    // The offset of the PE signature is read from the file and added to, which mustn't overflow
+    #[test]
+    fn overflowing_offsets() {
+        for offset in [u32::MAX, u32::MAX - 3, u32::MAX - 23, u32::MAX - 90] {
+            let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+            put(&mut data, 0x3C, offset, 4);
+            assert!(parse_header(&data).is_none());
+        }
+
+        // A signature right at the end leaves no room for the headers
+        let mut data = application(0x8664, SUBSYSTEM_EFI_APPLICATION);
+        let end = data.len() - 4;
+        data.copy_within(0x80..0x84, end);
+        put(&mut data, 0x3C, end as u32, 4);
+        assert!(parse_header(&data).is_none());
+    }
+}
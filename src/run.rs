@@ -1,18 +1,65 @@
+use crate::config::{Arch, Config};
+use std::path::Path;
+
 #[derive(Debug)]
 pub enum RunError {
     BuildError(crate::image::BuildImageError),
     Emulator(std::io::Error),
 }
 
-pub fn run() -> Result<(), RunError> {
-    crate::image::build_image()?;
+// Prepares the emulator to boot an image
+pub fn emulator_command(config: &Config, image: &Path) -> std::process::Command {
+    let mut emulator_command = std::process::Command::new(&config.emulator.program);
+    if let Some(machine) = &config.emulator.machine {
+        emulator_command.args(["-machine", machine]);
+    }
+    if let Some(cpu) = config.arch.cpu() {
+        emulator_command.args(["-cpu", cpu]);
+    }
+
+    // The RISC-V firmware runs from flash after OpenSBI rather than replacing it
+    match config.arch {
+        Arch::RiscV64 => emulator_command.args([
+            "-drive",
+            &format!(
+                "if=pflash,format=raw,unit=0,readonly=on,file={}",
+                config.emulator.firmware
+            ),
+        ]),
+        Arch::X86_64 | Arch::AArch64 => emulator_command.args(["-bios", &config.emulator.firmware]),
+    };
+
+    emulator_command.args(["-m", &config.emulator.memory.to_string()]);
 
-    let mut emulator_command = std::process::Command::new(crate::config::EMULATOR);
-    emulator_command.args(crate::config::EMULATOR_FLAGS);
+    // Only x86_64 machines have an IDE or AHCI controller for -hdd
+    match config.arch {
+        Arch::X86_64 => emulator_command.arg("-hdd").arg(image),
+        Arch::AArch64 | Arch::RiscV64 => {
+            let mut drive = std::ffi::OsString::from("if=virtio,format=raw,file=");
+            drive.push(image);
+            emulator_command.arg("-drive").arg(drive)
+        }
+    };
+    emulator_command.args(&config.emulator.flags);
     emulator_command.stdout(std::process::Stdio::inherit());
     emulator_command.stderr(std::process::Stdio::inherit());
     emulator_command.stdin(std::process::Stdio::inherit());
-    emulator_command.output()?;
+    emulator_command
+}
+
+// Builds and boots the image, or boots the sysroot from before the last build without building.
+// A command line given replaces the configured one in the image for this run.
+pub fn run(config: &Config, previous: bool, cmdline: Option<&str>) -> Result<(), RunError> {
+    let image = match previous {
+        true => crate::image::build_previous_image(config)?,
+        false => {
+            crate::image::build_image(config)?;
+            config.image_path()
+        }
+    };
+    crate::image::set_cmdline(config, previous, cmdline)?;
+
+    emulator_command(config, &image).output()?;
 
     Ok(())
 }
@@ -43,3 +90,108 @@ impl From<std::io::Error> for RunError {
         RunError::Emulator(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::emulator_command;
+    use crate::config::{Arch, Config};
+    use std::path::Path;
+
+    fn arguments(arch: Arch, source: &str) -> (String, Vec<String>) {
+        let config = Config::parse(source, arch).unwrap();
+        let command = emulator_command(&config, Path::new("./os.img"));
+        (
+            command.get_program().to_string_lossy().into_owned(),
+            command
+                .get_args()
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn x86_64() {
+        assert_eq!(
+            arguments(Arch::X86_64, ""),
+            (
+                "qemu-system-x86_64".to_owned(),
+                vec!["-bios", "OVMF.fd", "-m", "128", "-hdd", "./os.img"]
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect()
+            )
+        );
+
+        let source =
+            "[emulator]\nmachine = \"q35\"\nmemory = 512\nflags = [\"-enable-kvm\", \"-s\"]\n";
+        assert_eq!(
+            arguments(Arch::X86_64, source).1,
+            [
+                "-machine",
+                "q35",
+                "-bios",
+                "OVMF.fd",
+                "-m",
+                "512",
+                "-hdd",
+                "./os.img",
+                "-enable-kvm",
+                "-s"
+            ]
+        );
+    }
+
+    // Neither has a controller for -hdd, so the image is attached with virtio
+    #[test]
+    fn aarch64() {
+        assert_eq!(
+            arguments(Arch::AArch64, ""),
+            (
+                "qemu-system-aarch64".to_owned(),
+                vec![
+                    "-machine",
+                    "virt",
+                    "-cpu",
+                    "cortex-a72",
+                    "-bios",
+                    "QEMU_EFI.fd",
+                    "-m",
+                    "128",
+                    "-drive",
+                    "if=virtio,format=raw,file=./os.img"
+                ]
+                .into_iter()
+                .map(str::to_owned)
+                .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn riscv64() {
+        assert_eq!(
+            arguments(Arch::RiscV64, ""),
+            (
+                "qemu-system-riscv64".to_owned(),
+                vec![
+                    "-machine",
+                    "virt",
+                    "-drive",
+                    "if=pflash,format=raw,unit=0,readonly=on,file=RISCV_VIRT_CODE.fd",
+                    "-m",
+                    "128",
+                    "-drive",
+                    "if=virtio,format=raw,file=./os.img"
+                ]
+                .into_iter()
+                .map(str::to_owned)
+                .collect()
+            )
+        );
+
+        let source = "[emulator]\nfirmware = \"/usr/share/qemu/riscv.fd\"\n";
+        assert!(arguments(Arch::RiscV64, source).1.contains(
+            &"if=pflash,format=raw,unit=0,readonly=on,file=/usr/share/qemu/riscv.fd".to_owned()
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::{Component, ComponentKind, Config};
+
+// Whether everything a component installs is in the sysroot, unknown for brew
+fn installed(config: &Config, component: &Component) -> Option<bool> {
+    let sysroot = config.sysroot();
+    match &component.kind {
+        ComponentKind::Cargo(cargo) => cargo
+            .install
+            .as_ref()
+            .map(|install| sysroot.join(install).exists()),
+        ComponentKind::Custom(custom) => match custom.install.is_empty() {
+            true => None,
+            false => Some(
+                custom
+                    .install
+                    .iter()
+                    .all(|(_, to)| sysroot.join(to).exists()),
+            ),
+        },
+        ComponentKind::Brew => None,
+    }
+}
+
+pub fn display_status(config: &Config) {
+    println!("Components ({}) in build order:", config.variant_name());
+
+    let name_width = config
+        .components
+        .iter()
+        .map(|component| component.name.len())
+        .max()
+        .unwrap_or(0);
+    let path_width = config
+        .components
+        .iter()
+        .map(|component| component.path.len())
+        .max()
+        .unwrap_or(0);
+
+    for component in &config.components {
+        let state = match installed(config, component) {
+            Some(true) => "\x1B[32;1minstalled\x1B[0m    ",
+            Some(false) => "\x1B[33;1mnot installed\x1B[0m",
+            None => "             ",
+        };
+
+        let depends_on = match component.depends_on.is_empty() {
+            true => String::new(),
+            false => format!("after {}", component.depends_on.join(", ")),
+        };
+
+        let line = format!(
+            "    {:name_width$}  {:6}  {:path_width$}  {}  {}",
+            component.name,
+            component.kind_name(),
+            component.path,
+            state,
+            depends_on,
+            name_width = name_width,
+            path_width = path_width,
+        );
+        println!("{}", line.trim_end());
+    }
+
+    println!();
+    let image = config.image_path();
+    println!(
+        "Image {} {}",
+        image.to_string_lossy(),
+        match image.exists() {
+            true => "exists",
+            false => "has not been built",
+        }
+    );
+}
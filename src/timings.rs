@@ -0,0 +1,441 @@
+use crate::{arguments::TimingsFormat, config::Config, json};
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug)]
+pub enum TimingsError {
+    History(std::io::Error),
+    NotEnough(String, usize),
+}
+
+// Builds kept in the history, older ones are forgotten
+const HISTORY_LENGTH: usize = 50;
+
+// A phase is a regression once it is slower by both of these
+const REGRESSION_SECONDS: f64 = 0.1;
+const REGRESSION_FRACTION: f64 = 0.1;
+
+// The time taken by each phase of a build, recorded from any thread
+pub struct Timings {
+    started: Instant,
+    phases: Mutex<Vec<(String, Duration)>>,
+}
+
+// A finished build as kept in the history
+struct Run {
+    time: u64, // Seconds since the unix epoch
+    variant: String,
+    total: f64,
+    phases: Vec<(String, f64)>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings {
+            started: Instant::now(),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn time<T>(&self, phase: String, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.lock().unwrap().push((phase, start.elapsed()));
+        result
+    }
+
+    // Adds the build to the history, printing its summary if asked to
+    pub fn finish(self, config: &Config) -> Result<(), std::io::Error> {
+        let run = Run {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            variant: config.variant_name(),
+            total: self.started.elapsed().as_secs_f64(),
+            phases: self
+                .phases
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|(phase, duration)| (phase, duration.as_secs_f64()))
+                .collect(),
+        };
+
+        let mut history = load_history()?;
+        add_to_history(&mut history, &run);
+        create_dir_all(crate::config::STATE_DIR)?;
+        write(history_path(), history.join("\n") + "\n")?;
+
+        for line in run.summary(config.timings) {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+}
+
+// Adds a build to the end of the history, forgetting the oldest once it is full
+fn add_to_history(history: &mut Vec<String>, run: &Run) {
+    if history.len() >= HISTORY_LENGTH {
+        history.drain(..=history.len() - HISTORY_LENGTH);
+    }
+    history.push(run.to_json());
+}
+
+impl Run {
+    fn parse(line: &str) -> Option<Self> {
+        let value = json::parse(line).ok()?;
+        let mut phases = Vec::new();
+        for phase in value.get("phases")?.as_array()? {
+            phases.push((
+                phase.get("name")?.as_str()?.to_owned(),
+                phase.get("seconds")?.as_f64()?,
+            ));
+        }
+
+        Some(Run {
+            time: value.get("time")?.as_f64()? as u64,
+            variant: value.get("variant")?.as_str()?.to_owned(),
+            total: value.get("total")?.as_f64()?,
+            phases,
+        })
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"time\":{},\"variant\":{},\"total\":{:.3},\"phases\":[{}]}}",
+            self.time,
+            json::quote(&self.variant),
+            self.total,
+            self.phases
+                .iter()
+                .map(|(phase, seconds)| format!(
+                    "{{\"name\":{},\"seconds\":{:.3}}}",
+                    json::quote(phase),
+                    seconds
+                ))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+
+    // Every phase in the order it finished followed by the whole build
+    fn rows(&self) -> Vec<(&str, f64)> {
+        self.phases
+            .iter()
+            .map(|(phase, seconds)| (phase.as_str(), *seconds))
+            .chain(std::iter::once(("total", self.total)))
+            .collect()
+    }
+
+    // What is printed once a build finishes, a table preceded by an empty line and followed by
+    // the build as JSON when asked for
+    fn summary(&self, format: Option<TimingsFormat>) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(format) = format {
+            lines.push(String::new());
+            lines.append(&mut self.table());
+            if format == TimingsFormat::Json {
+                lines.push(self.to_json());
+            }
+        }
+        lines
+    }
+
+    fn table(&self) -> Vec<String> {
+        let mut lines = vec![format!("Timings ({}):", self.variant)];
+
+        let rows = self.rows();
+        let width = rows.iter().map(|(phase, _)| phase.len()).max().unwrap_or(0);
+        for (phase, seconds) in rows {
+            lines.push(format!(
+                "    {:width$}  {:>8.2}s",
+                phase,
+                seconds,
+                width = width
+            ));
+        }
+        lines
+    }
+
+    // Each phase next to its time in an earlier build, marking regressions in red
+    fn compare(&self, previous: &Run) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Timings ({}) compared to the previous build:",
+            self.variant
+        )];
+
+        let rows = self.rows();
+        let previous_rows = previous.rows();
+        let width = rows
+            .iter()
+            .chain(&previous_rows)
+            .map(|(phase, _)| phase.len())
+            .max()
+            .unwrap_or(0);
+
+        // Phases which are no longer run are listed after the rest
+        let removed = previous_rows
+            .iter()
+            .filter(|(phase, _)| !rows.iter().any(|(name, _)| name == phase))
+            .map(|(phase, seconds)| (*phase, Some(*seconds), None));
+        let rows = rows
+            .iter()
+            .map(|(phase, seconds)| {
+                let before = previous_rows
+                    .iter()
+                    .find(|(name, _)| name == phase)
+                    .map(|(_, seconds)| *seconds);
+                (*phase, before, Some(*seconds))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .chain(removed);
+
+        lines.push(format!(
+            "    {:width$}  {:>9}  {:>9}  {:>9}",
+            "",
+            "previous",
+            "latest",
+            "change",
+            width = width
+        ));
+        for (phase, before, after) in rows {
+            let change = match (before, after) {
+                (Some(before), Some(after)) => {
+                    let difference = after - before;
+                    let color = match difference.abs() > REGRESSION_SECONDS
+                        && difference.abs() > before * REGRESSION_FRACTION
+                    {
+                        true if difference > 0.0 => "\x1B[31;1m",
+                        true => "\x1B[32;1m",
+                        false => "",
+                    };
+                    format!(
+                        "{}{:>+8.2}s{}",
+                        color,
+                        difference,
+                        match color.is_empty() {
+                            true => "",
+                            false => "\x1B[0m",
+                        }
+                    )
+                }
+                _ => String::new(),
+            };
+
+            let seconds = |seconds: Option<f64>| match seconds {
+                Some(seconds) => format!("{:>8.2}s", seconds),
+                None => format!("{:>9}", "-"),
+            };
+
+            let line = format!(
+                "    {:width$}  {}  {}  {}",
+                phase,
+                seconds(before),
+                seconds(after),
+                change,
+                width = width
+            );
+            lines.push(line.trim_end().to_owned());
+        }
+        lines
+    }
+}
+
+fn history_path() -> PathBuf {
+    PathBuf::from(crate::config::STATE_DIR).join("timings.jsonl")
+}
+
+// The history has one build on each line, oldest first
+fn load_history() -> Result<Vec<String>, std::io::Error> {
+    match read_to_string(history_path()) {
+        Ok(history) => Ok(history
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+            .collect()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+// Shows the last build of the selected variant, or how it compares to the build before it
+pub fn display_timings(config: &Config, compare: bool) -> Result<(), TimingsError> {
+    let variant = config.variant_name();
+    let history = match load_history() {
+        Ok(history) => history,
+        Err(error) => return Err(TimingsError::History(error)),
+    };
+    let runs: Vec<Run> = history
+        .iter()
+        .filter_map(|line| Run::parse(line))
+        .filter(|run| run.variant == variant)
+        .collect();
+
+    let needed = match compare {
+        true => 2,
+        false => 1,
+    };
+    if runs.len() < needed {
+        return Err(TimingsError::NotEnough(variant, needed));
+    }
+
+    let latest = &runs[runs.len() - 1];
+    let lines = match compare {
+        true => latest.compare(&runs[runs.len() - 2]),
+        false => latest.table(),
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+impl std::error::Error for TimingsError {}
+
+impl std::fmt::Display for TimingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TimingsError::History(error) =>
+                    format!("Unable to read the timing history ({})", error),
+                TimingsError::NotEnough(variant, 1) =>
+                    format!("No builds of {} have been timed", variant),
+                TimingsError::NotEnough(variant, needed) => format!(
+                    "Fewer than {} builds of {} have been timed",
+                    needed, variant
+                ),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_to_history, Run, HISTORY_LENGTH};
+    use crate::arguments::TimingsFormat;
+
+    fn run(total: f64, phases: &[(&str, f64)]) -> Run {
+        Run {
+            time: 1_700_000_000,
+            variant: "debug".to_owned(),
+            total,
+            phases: phases
+                .iter()
+                .map(|(phase, seconds)| (phase.to_string(), *seconds))
+                .collect(),
+        }
+    }
+
+    fn latest() -> Run {
+        run(
+            12.3456,
+            &[
+                ("cargo kernel", 10.0),
+                ("cargo bootloader", 1.0),
+                ("link", 1.05),
+                ("pack \"initrd\"", 0.5),
+            ],
+        )
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = latest().to_json();
+        assert_eq!(
+            json,
+            "{\"time\":1700000000,\"variant\":\"debug\",\"total\":12.346,\"phases\":[\
+             {\"name\":\"cargo kernel\",\"seconds\":10.000},\
+             {\"name\":\"cargo bootloader\",\"seconds\":1.000},\
+             {\"name\":\"link\",\"seconds\":1.050},\
+             {\"name\":\"pack \\\"initrd\\\"\",\"seconds\":0.500}]}"
+        );
+
+        let parsed = Run::parse(&json).unwrap();
+        assert_eq!(parsed.time, 1_700_000_000);
+        assert_eq!(parsed.variant, "debug");
+        assert_eq!(parsed.total, 12.346);
+        assert_eq!(parsed.phases, latest().phases);
+        assert_eq!(parsed.to_json(), json);
+
+        assert!(Run::parse("").is_none());
+        assert!(Run::parse("{\"time\":1,\"variant\":\"debug\",\"total\":1}").is_none());
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut history: Vec<String> = (0..HISTORY_LENGTH).map(|i| i.to_string()).collect();
+        add_to_history(&mut history, &latest());
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0], "1");
+        assert_eq!(history[HISTORY_LENGTH - 1], latest().to_json());
+
+        // Even a longer history is cut down to the last builds
+        let mut history: Vec<String> = (0..80).map(|i| i.to_string()).collect();
+        add_to_history(&mut history, &latest());
+        assert_eq!(history.len(), HISTORY_LENGTH);
+        assert_eq!(history[0], "31");
+
+        let mut history = Vec::new();
+        add_to_history(&mut history, &latest());
+        assert_eq!(history, [latest().to_json()]);
+    }
+
+    #[test]
+    fn summary() {
+        let table = [
+            "Timings (debug):",
+            "    cargo kernel         10.00s",
+            "    cargo bootloader      1.00s",
+            "    link                  1.05s",
+            "    pack \"initrd\"         0.50s",
+            "    total                12.35s",
+        ];
+        assert_eq!(latest().table(), table);
+
+        assert!(latest().summary(None).is_empty());
+        assert_eq!(latest().summary(Some(TimingsFormat::Table))[0], "");
+        assert_eq!(latest().summary(Some(TimingsFormat::Table))[1..], table);
+
+        // The JSON follows the table, on a line of its own
+        let json = latest().summary(Some(TimingsFormat::Json));
+        assert_eq!(json.len(), table.len() + 2);
+        assert_eq!(json[1..=table.len()], table);
+        assert_eq!(json[table.len() + 1], latest().to_json());
+    }
+
+    #[test]
+    fn compare() {
+        let previous = run(
+            11.0,
+            &[
+                ("cargo kernel", 8.0),
+                ("cargo bootloader", 5.0),
+                ("link", 1.0),
+                ("brew programs", 3.0),
+            ],
+        );
+
+        // Slower phases are red and faster ones green, unless the change is small
+        assert_eq!(
+            latest().compare(&previous),
+            [
+                "Timings (debug) compared to the previous build:",
+                "                       previous     latest     change",
+                "    cargo kernel          8.00s     10.00s  \x1B[31;1m   +2.00s\x1B[0m",
+                "    cargo bootloader      5.00s      1.00s  \x1B[32;1m   -4.00s\x1B[0m",
+                "    link                  1.00s      1.05s     +0.05s",
+                "    pack \"initrd\"             -      0.50s",
+                "    total                11.00s     12.35s  \x1B[31;1m   +1.35s\x1B[0m",
+                "    brew programs         3.00s          -",
+            ]
+        );
+    }
+}
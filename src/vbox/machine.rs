@@ -0,0 +1,211 @@
+use crate::xml::escape;
+use std::path::Path;
+
+pub struct Machine<'a> {
+    name: &'a str,
+    machine_uuid: String,
+    disk_uuid: String,
+    disk_location: &'a str,
+    memory: usize,
+    cpu_count: usize,
+}
+
+// FNV-1a, used so the generated UUIDs are the same every time the same machine is generated
+fn fnv1a(seed: u64, bytes: &[u8]) -> u64 {
+    let mut hash = 0xCBF29CE484222325 ^ seed;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001B3);
+    }
+    hash
+}
+
+// Generates a version 4 style UUID from a name
+pub fn uuid(name: &str) -> String {
+    let high = fnv1a(0, name.as_bytes());
+    let low = fnv1a(high, name.as_bytes());
+
+    let high = (high & 0xFFFFFFFFFFFF0FFF) | 0x0000000000004000;
+    let low = (low & 0x3FFFFFFFFFFFFFFF) | 0x8000000000000000;
+
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xFFFF,
+        high & 0xFFFF,
+        low >> 48,
+        low & 0xFFFFFFFFFFFF
+    )
+}
+
+impl<'a> Machine<'a> {
+    // The UUIDs are derived from the machine path so regenerating the machine keeps them stable
+    pub fn new(
+        name: &'a str,
+        machine_path: &Path,
+        disk_location: &'a str,
+        memory: usize,
+        cpu_count: usize,
+    ) -> Self {
+        let machine_path = machine_path.to_string_lossy();
+
+        Machine {
+            name,
+            machine_uuid: uuid(&format!("{}#machine", machine_path)),
+            disk_uuid: uuid(&format!("{}#disk", machine_path)),
+            disk_location,
+            memory,
+            cpu_count,
+        }
+    }
+
+    pub fn disk_uuid(&self) -> &str {
+        &self.disk_uuid
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+
+        xml.push_str("<?xml version=\"1.0\"?>\n");
+        xml.push_str("<VirtualBox xmlns=\"http://www.virtualbox.org/\" version=\"1.16-linux\">\n");
+        xml.push_str(&format!(
+            "  <Machine uuid=\"{{{}}}\" name=\"{}\" OSType=\"Other_64\" snapshotFolder=\"Snapshots\">\n",
+            self.machine_uuid,
+            escape(self.name)
+        ));
+
+        // Media registry
+        xml.push_str("    <MediaRegistry>\n");
+        xml.push_str("      <HardDisks>\n");
+        xml.push_str(&format!(
+            "        <HardDisk uuid=\"{{{}}}\" location=\"{}\" format=\"VDI\" type=\"Normal\"/>\n",
+            self.disk_uuid,
+            escape(self.disk_location)
+        ));
+        xml.push_str("      </HardDisks>\n");
+        xml.push_str("    </MediaRegistry>\n");
+
+        // Hardware
+        xml.push_str("    <Hardware>\n");
+        xml.push_str(&format!("      <CPU count=\"{}\">\n", self.cpu_count));
+        xml.push_str("        <PAE enabled=\"true\"/>\n");
+        xml.push_str("        <LongMode enabled=\"true\"/>\n");
+        xml.push_str("        <HardwareVirtExLargePages enabled=\"true\"/>\n");
+        xml.push_str("      </CPU>\n");
+        xml.push_str(&format!("      <Memory RAMSize=\"{}\"/>\n", self.memory));
+        xml.push_str("      <Firmware type=\"EFI\"/>\n");
+        xml.push_str("      <Chipset type=\"ICH9\"/>\n");
+        xml.push_str("      <Boot>\n");
+        xml.push_str("        <Order position=\"1\" device=\"HardDisk\"/>\n");
+        xml.push_str("        <Order position=\"2\" device=\"None\"/>\n");
+        xml.push_str("        <Order position=\"3\" device=\"None\"/>\n");
+        xml.push_str("        <Order position=\"4\" device=\"None\"/>\n");
+        xml.push_str("      </Boot>\n");
+        xml.push_str("      <BIOS>\n");
+        xml.push_str("        <IOAPIC enabled=\"true\"/>\n");
+        xml.push_str("      </BIOS>\n");
+
+        // Storage
+        xml.push_str("      <StorageControllers>\n");
+        xml.push_str("        <StorageController name=\"SATA\" type=\"AHCI\" PortCount=\"1\" useHostIOCache=\"false\" Bootable=\"true\">\n");
+        xml.push_str("          <AttachedDevice type=\"HardDisk\" hotpluggable=\"false\" port=\"0\" device=\"0\">\n");
+        xml.push_str(&format!(
+            "            <Image uuid=\"{{{}}}\"/>\n",
+            self.disk_uuid
+        ));
+        xml.push_str("          </AttachedDevice>\n");
+        xml.push_str("        </StorageController>\n");
+        xml.push_str("      </StorageControllers>\n");
+        xml.push_str("    </Hardware>\n");
+
+        xml.push_str("  </Machine>\n");
+        xml.push_str("</VirtualBox>\n");
+
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{uuid, Machine};
+    use crate::config::{Arch, Config};
+    use std::path::Path;
+
+    fn machine(name: &str) -> Machine<'_> {
+        Machine::new(name, Path::new("/project/os.vbox"), "os.vdi", 512, 2)
+    }
+
+    #[test]
+    fn hardware_from_config() {
+        let config = Config::parse("[vbox]\nmemory = 2048\ncpus = 8\n", Arch::X86_64).unwrap();
+        let xml = Machine::new(
+            &config.vbox.name,
+            Path::new("/project/os.vbox"),
+            &config.vbox.image,
+            config.vbox.memory,
+            config.vbox.cpus,
+        )
+        .to_xml();
+        assert!(xml.contains("<Firmware type=\"EFI\"/>"));
+        assert!(xml.contains("<Memory RAMSize=\"2048\"/>"));
+        assert!(xml.contains("<CPU count=\"8\">"));
+    }
+
+    #[test]
+    fn disk_attached_by_uuid() {
+        let machine = machine("Lance OS");
+        let xml = machine.to_xml();
+        let image = format!("<Image uuid=\"{{{}}}\"/>", machine.disk_uuid());
+
+        let controller = xml
+            .find("<StorageController name=\"SATA\" type=\"AHCI\"")
+            .unwrap();
+        let attached = xml.find(&image).unwrap();
+        assert!(attached > controller);
+        assert!(xml.contains(&format!(
+            "<HardDisk uuid=\"{{{}}}\" location=\"os.vdi\" format=\"VDI\"",
+            machine.disk_uuid()
+        )));
+    }
+
+    #[test]
+    fn stable_uuids() {
+        let first = machine("Lance OS");
+        let second = machine("Lance OS");
+        assert_eq!(first.disk_uuid(), second.disk_uuid());
+        assert_eq!(first.to_xml(), second.to_xml());
+
+        let other = Machine::new("Lance OS", Path::new("/other/os.vbox"), "os.vdi", 512, 2);
+        assert_ne!(first.disk_uuid(), other.disk_uuid());
+        assert_ne!(first.machine_uuid, first.disk_uuid);
+    }
+
+    #[test]
+    fn uuid_format() {
+        let uuid = uuid("os.vbox#disk");
+        let groups: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(groups[2].starts_with('4'));
+        assert!(matches!(
+            groups[3].chars().next(),
+            Some('8'..='9' | 'a'..='b')
+        ));
+    }
+
+    #[test]
+    fn escaped_names() {
+        let xml = Machine::new(
+            "<Tom & Jerry's \"OS\">",
+            Path::new("/os.vbox"),
+            "a&b.vdi",
+            1,
+            1,
+        )
+        .to_xml();
+        assert!(xml.contains("name=\"&lt;Tom &amp; Jerry&apos;s &quot;OS&quot;&gt;\""));
+        assert!(xml.contains("location=\"a&amp;b.vdi\""));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::config::Config;
+use std::path::{Path, PathBuf};
+
+mod machine;
+
+#[derive(Debug)]
+pub enum VBoxError {
+    BuildError(crate::image::BuildImageError),
+    RemoveDisk(PathBuf, std::io::Error),
+    VBoxManage(Option<std::io::Error>),
+    Machine(std::io::Error),
+}
+
+pub fn vbox(config: &Config) -> Result<(), VBoxError> {
+    crate::image::build_image(config)?;
+
+    // Prepare the machine definition
+    let machine_path = Path::new(&config.vbox.machine);
+    let machine_path = std::env::current_dir()
+        .expect("No current working directory!")
+        .join(machine_path.strip_prefix(".").unwrap_or(machine_path));
+    let machine = machine::Machine::new(
+        &config.vbox.name,
+        &machine_path,
+        &config.vbox.image,
+        config.vbox.memory,
+        config.vbox.cpus,
+    );
+
+    // Remove the previous disk, VBoxManage refuses to overwrite it
+    let vdi_path = Path::new(&config.vbox.image);
+    if vdi_path.exists() {
+        std::fs::remove_file(vdi_path)
+            .map_err(|error| VBoxError::RemoveDisk(vdi_path.to_owned(), error))?;
+    }
+
+    // Convert the image, giving the disk the UUID the machine expects
+    println!(
+        "  \x1B[36;1mConverting\x1B[0m {} into {} . . .",
+        config.image_path().to_string_lossy(),
+        config.vbox.image
+    );
+    let mut vbox_command = std::process::Command::new(&config.vbox.program);
+    vbox_command.args(["convertfromraw", "--format", "VDI"]);
+    vbox_command.arg(config.image_path());
+    vbox_command.arg(&config.vbox.image);
+    vbox_command.args(["--uuid", machine.disk_uuid()]);
+    let status = vbox_command.status()?;
+
+    if !status.success() {
+        return Err(VBoxError::VBoxManage(None));
+    }
+
+    // Write the machine definition
+    println!(
+        "     \x1B[36;1mWriting\x1B[0m {} . . .",
+        config.vbox.machine
+    );
+    match std::fs::write(&config.vbox.machine, machine.to_xml()) {
+        Ok(()) => {}
+        Err(error) => return Err(VBoxError::Machine(error)),
+    }
+
+    println!(
+        "    \x1B[32;1mFinished\x1B[0m creating {}, import it with \"{} registervm {}\"",
+        config.vbox.machine,
+        config.vbox.program,
+        machine_path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+impl std::error::Error for VBoxError {}
+
+impl std::fmt::Display for VBoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                VBoxError::BuildError(error) => format!("{}", error),
+                VBoxError::RemoveDisk(path, error) => format!(
+                    "Unable to remove the previous VBox disk {} ({})",
+                    path.to_string_lossy(),
+                    error
+                ),
+                VBoxError::VBoxManage(error) => format!(
+                    "Unable to create VBox image{}",
+                    match error {
+                        Some(error) => format!(" ({})", error),
+                        None => String::new(),
+                    }
+                ),
+                VBoxError::Machine(error) =>
+                    format!("Unable to write VBox machine definition ({})", error),
+            }
+        )
+    }
+}
+
+impl From<crate::image::BuildImageError> for VBoxError {
+    fn from(error: crate::image::BuildImageError) -> Self {
+        VBoxError::BuildError(error)
+    }
+}
+
+impl From<std::io::Error> for VBoxError {
+    fn from(error: std::io::Error) -> Self {
+        VBoxError::VBoxManage(Some(error))
+    }
+}
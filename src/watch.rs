@@ -0,0 +1,143 @@
+use crate::{
+    command::Command,
+    config::Config,
+    fingerprint::{skip_source, Hasher},
+};
+use std::{path::Path, process::Child, thread::sleep, time::Duration};
+
+// How often the sources are checked, and how long they must stay unchanged before building
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+#[derive(Debug)]
+pub enum WatchError {
+    Unsupported(Command),
+    Emulator(std::io::Error),
+}
+
+// Repeats a command each time the sources of the selected components change, until interrupted
+pub fn watch(config: &Config, command: Command) -> Result<(), WatchError> {
+    match command {
+        Command::Build | Command::BuildImage | Command::Run => {}
+        _ => return Err(WatchError::Unsupported(command)),
+    }
+
+    let watched: Vec<&str> = config
+        .components
+        .iter()
+        .filter(|component| config.is_selected(component))
+        .map(|component| component.name.as_str())
+        .collect();
+
+    let mut sources = hash_sources(config);
+    let mut emulator: Option<Child> = None;
+    loop {
+        // The image is rewritten by the build, so the emulator can't keep running from it
+        if let Command::BuildImage | Command::Run = command {
+            stop_emulator(&mut emulator);
+        }
+
+        let result: Result<(), Box<dyn std::error::Error>> = match command {
+            Command::Build => crate::build::build(config).map_err(|error| error.into()),
+            _ => crate::image::build_image(config).map_err(|error| error.into()),
+        };
+        match result {
+            Ok(()) => {
+                if let Command::Run = command {
+                    let image = config.image_path();
+                    match crate::run::emulator_command(config, &image).spawn() {
+                        Ok(child) => emulator = Some(child),
+                        // Like a failed build, the next change tries again
+                        Err(error) => {
+                            println!("\x1B[31;1mError:\x1B[0m {}", WatchError::Emulator(error))
+                        }
+                    }
+                }
+            }
+            Err(error) => println!("\x1B[31;1mError:\x1B[0m {}", error),
+        }
+
+        println!(
+            "\n    \x1B[36;1mWatching\x1B[0m {} for changes . . .",
+            watched.join(", ")
+        );
+        sources = wait_for_change(config, sources, &mut emulator);
+        println!(
+            "\n     \x1B[36;1mChanged\x1B[0m {}\n",
+            match emulator.is_some() {
+                true => "rebuilding and restarting the emulator",
+                false => "rebuilding",
+            }
+        );
+    }
+}
+
+// Hashes the sources of every selected component, leaving out what builds write
+fn hash_sources(config: &Config) -> u64 {
+    let written = [config.sysroot(), config.symbols_path()];
+    let skip = |path: &Path| skip_source(path) || written.iter().any(|written| path == written);
+
+    let mut hasher = Hasher::new();
+    for component in &config.components {
+        if config.is_selected(component) {
+            hasher.write_str(&component.name);
+            hasher.write_tree(Path::new(&component.path), &skip);
+        }
+    }
+    hasher.finish()
+}
+
+// Waits until the sources differ from those hashed, then until they stop changing so a save
+// touching several files only builds once. Returns the hash of the changed sources.
+fn wait_for_change(config: &Config, sources: u64, emulator: &mut Option<Child>) -> u64 {
+    loop {
+        sleep(POLL_INTERVAL);
+
+        if let Some(Ok(Some(_))) = emulator.as_mut().map(Child::try_wait) {
+            println!("      \x1B[36;1mExited\x1B[0m emulator, restarting it after the next change");
+            *emulator = None;
+        }
+
+        let mut current = hash_sources(config);
+        if current == sources {
+            continue;
+        }
+
+        loop {
+            sleep(SETTLE_TIME);
+            let settled = hash_sources(config);
+            if settled == current {
+                return current;
+            }
+            current = settled;
+        }
+    }
+}
+
+fn stop_emulator(emulator: &mut Option<Child>) {
+    if let Some(mut child) = emulator.take() {
+        child.kill().ok();
+        child.wait().ok();
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WatchError::Unsupported(command) => format!(
+                    "{} can't be watched, only {}, {} and {} can",
+                    command,
+                    Command::Build,
+                    Command::BuildImage,
+                    Command::Run
+                ),
+                WatchError::Emulator(error) => format!("Unable to launch emulator ({})", error),
+            }
+        )
+    }
+}